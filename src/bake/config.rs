@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+
+/// Still image formats bake can produce resized variants in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    // Declared in order of preference, so sorting puts the best format first.
+    Avif,
+    Webp,
+    Jpeg,
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Avif => "avif",
+            ImageFormat::Webp => "webp",
+            ImageFormat::Jpeg => "jpg",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Avif => "image/avif",
+            ImageFormat::Webp => "image/webp",
+            ImageFormat::Jpeg => "image/jpeg",
+        }
+    }
+
+    /// ffmpeg encoder arguments for this format.
+    pub fn codec_args(&self) -> &'static [&'static str] {
+        match self {
+            ImageFormat::Avif => &[
+                "-c:v",
+                "libaom-av1",
+                "-still-picture",
+                "1",
+                "-crf",
+                "30",
+                "-cpu-used",
+                "6",
+            ],
+            ImageFormat::Webp => &["-c:v", "libwebp", "-quality", "80"],
+            ImageFormat::Jpeg => &["-q:v", "3"],
+        }
+    }
+}
+
+/// Per-site bake settings, read from the `bake` key of config.json.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BakeConfig {
+    /// Widths of the resized preview images used on listing pages
+    #[serde(default = "default_preview_widths")]
+    pub preview_widths: Vec<u32>,
+    /// Widths of the resized full images used on detail pages
+    #[serde(default = "default_display_widths")]
+    pub display_widths: Vec<u32>,
+    /// Formats each resized image is produced in
+    #[serde(default = "default_formats")]
+    pub formats: Vec<ImageFormat>,
+    /// Package long videos as adaptive HLS streams. Off unless present.
+    #[serde(default)]
+    pub hls: Option<HlsConfig>,
+    /// Cut very large images into deep-zoom tile pyramids. Off unless present.
+    #[serde(default)]
+    pub deep_zoom: Option<DeepZoomConfig>,
+}
+
+fn default_preview_widths() -> Vec<u32> {
+    vec![320, 640]
+}
+
+fn default_display_widths() -> Vec<u32> {
+    vec![1280, 1920, 2560]
+}
+
+fn default_formats() -> Vec<ImageFormat> {
+    vec![ImageFormat::Webp]
+}
+
+impl Default for BakeConfig {
+    fn default() -> Self {
+        BakeConfig {
+            preview_widths: default_preview_widths(),
+            display_widths: default_display_widths(),
+            formats: default_formats(),
+            hls: None,
+            deep_zoom: None,
+        }
+    }
+}
+
+/// One bitrate rung of an HLS ladder.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HlsRendition {
+    pub height: u32,
+    /// Target video bitrate in kbit/s
+    pub video_kbps: u32,
+    /// Audio bitrate in kbit/s
+    #[serde(default = "default_audio_kbps")]
+    pub audio_kbps: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HlsConfig {
+    /// Only videos at least this long are packaged
+    #[serde(default = "default_hls_min_duration")]
+    pub min_duration_seconds: u64,
+    #[serde(default = "default_hls_renditions")]
+    pub renditions: Vec<HlsRendition>,
+}
+
+fn default_audio_kbps() -> u32 {
+    128
+}
+
+fn default_hls_min_duration() -> u64 {
+    600
+}
+
+fn default_hls_renditions() -> Vec<HlsRendition> {
+    [(360, 800), (720, 2800), (1080, 5000)]
+        .into_iter()
+        .map(|(height, video_kbps)| HlsRendition {
+            height,
+            video_kbps,
+            audio_kbps: default_audio_kbps(),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeepZoomConfig {
+    /// Only images with at least this many pixels are tiled
+    #[serde(default = "default_deep_zoom_min_pixels")]
+    pub min_pixels: u64,
+    /// Edge length of each tile, not counting overlap
+    #[serde(default = "default_deep_zoom_tile_size")]
+    pub tile_size: u32,
+}
+
+fn default_deep_zoom_min_pixels() -> u64 {
+    40_000_000
+}
+
+fn default_deep_zoom_tile_size() -> u32 {
+    254
+}
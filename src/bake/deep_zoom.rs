@@ -0,0 +1,219 @@
+use std::path::Path;
+
+use super::{commit_partial_dir, config::DeepZoomConfig, ffmpeg, partial_dir_path, BakeOutcome};
+use crate::{errors::Result, site::FileCrawlType};
+
+/// Kept here rather than derived from `ArtifactKind::DeepZoom`, which carries
+/// the config and so is awkward to build just to look up a path.
+pub const DIRECTORY: &str = "deep_zoom";
+pub const DZI_FILENAME: &str = "image.dzi";
+/// Viewers look for tiles in `{name}_files` next to `{name}.dzi`.
+const TILES_DIRECTORY: &str = "image_files";
+
+/// Pixels each tile shares with its neighbours, so viewers can blend seams
+const OVERLAP: u32 = 1;
+const TILE_QUALITY: &str = "4";
+/// Tiles cut per ffmpeg run. Each run decodes the source again, but a level
+/// of a huge image can have thousands of tiles and each one costs a filter
+/// chain and an output on the command line.
+const TILES_PER_RUN: usize = 256;
+
+/// Fingerprint of the settings used to tile an image.
+pub fn settings(config: &DeepZoomConfig) -> String {
+    format!(
+        "deep zoom min={}px tile={} overlap={} q={}",
+        config.min_pixels, config.tile_size, OVERLAP, TILE_QUALITY
+    )
+}
+
+/// Cut a very large image into a Deep Zoom (DZI) pyramid: a descriptor plus
+/// one directory of JPEG tiles per level, each level half the size of the
+/// next, down to a single pixel. `output` is a directory holding both.
+pub fn create(
+    work_dir_path: &Path,
+    source: &FileCrawlType,
+    output: &Path,
+    config: &DeepZoomConfig,
+) -> Result<BakeOutcome> {
+    let FileCrawlType::Image { filename, .. } = source else {
+        return Ok(BakeOutcome::skipped("not an image"));
+    };
+
+    let image_path = work_dir_path.join(filename);
+    if !image_path.exists() {
+        return Ok(BakeOutcome::skipped("source file missing"));
+    }
+
+    let (width, height) = ffmpeg::dimensions(&image_path)?;
+    if (width as u64) * (height as u64) < config.min_pixels {
+        return Ok(BakeOutcome::not_needed("smaller than deep zoom threshold"));
+    }
+
+    let partial = partial_dir_path(output);
+    if partial.exists() {
+        std::fs::remove_dir_all(&partial)?;
+    }
+
+    for (level, (level_width, level_height)) in levels(width, height).into_iter().enumerate() {
+        let level_dir = partial.join(TILES_DIRECTORY).join(level.to_string());
+        std::fs::create_dir_all(&level_dir)?;
+
+        let tiles = tiles(level_width, level_height, config.tile_size);
+        for chunk in tiles.chunks(TILES_PER_RUN) {
+            let mut graph = format!(
+                "[0:v]scale={}:{}:flags=lanczos,split={}",
+                level_width,
+                level_height,
+                chunk.len()
+            );
+            for index in 0..chunk.len() {
+                graph.push_str(&format!("[in{}]", index));
+            }
+            for (index, tile) in chunk.iter().enumerate() {
+                graph.push_str(&format!(
+                    ";[in{}]crop={}:{}:{}:{}[out{}]",
+                    index, tile.width, tile.height, tile.x, tile.y, index
+                ));
+            }
+
+            let mut command = ffmpeg::ffmpeg();
+            command
+                .arg("-i")
+                .arg(&image_path)
+                .arg("-filter_complex")
+                .arg(graph);
+            for (index, tile) in chunk.iter().enumerate() {
+                command
+                    .arg("-map")
+                    .arg(format!("[out{}]", index))
+                    .arg("-frames:v")
+                    .arg("1")
+                    .arg("-q:v")
+                    .arg(TILE_QUALITY)
+                    .arg(level_dir.join(format!("{}_{}.jpg", tile.column, tile.row)));
+            }
+            ffmpeg::run("ffmpeg", &mut command)?;
+        }
+    }
+
+    std::fs::write(
+        partial.join(DZI_FILENAME),
+        dzi(width, height, config.tile_size),
+    )?;
+
+    commit_partial_dir(&partial, output)?;
+
+    Ok(BakeOutcome::created(work_dir_path, output))
+}
+
+/// Size of each level of the pyramid, smallest (1x1) first. Each level is
+/// the next one halved and rounded up, as the DZI format defines them.
+fn levels(width: u32, height: u32) -> Vec<(u32, u32)> {
+    let max_level = u32::BITS - (width.max(height).max(1) - 1).leading_zeros();
+    (0..=max_level)
+        .map(|level| {
+            let scale = 1u64 << (max_level - level);
+            (
+                (width as u64).div_ceil(scale) as u32,
+                (height as u64).div_ceil(scale) as u32,
+            )
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Tile {
+    column: u32,
+    row: u32,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+/// The tiles covering one level, with `OVERLAP` pixels borrowed from each
+/// neighbour that exists.
+fn tiles(level_width: u32, level_height: u32, tile_size: u32) -> Vec<Tile> {
+    let span = |index: u32, length: u32| {
+        let start = (index * tile_size).saturating_sub(if index > 0 { OVERLAP } else { 0 });
+        let end = ((index + 1) * tile_size + OVERLAP).min(length);
+        (start, end - start)
+    };
+
+    let columns = level_width.div_ceil(tile_size);
+    let rows = level_height.div_ceil(tile_size);
+    (0..rows)
+        .flat_map(|row| (0..columns).map(move |column| (column, row)))
+        .map(|(column, row)| {
+            let (x, width) = span(column, level_width);
+            let (y, height) = span(row, level_height);
+            Tile {
+                column,
+                row,
+                x,
+                y,
+                width,
+                height,
+            }
+        })
+        .collect()
+}
+
+fn dzi(width: u32, height: u32, tile_size: u32) -> String {
+    format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<Image xmlns=\"http://schemas.microsoft.com/deepzoom/2008\" ",
+            "Format=\"jpg\" Overlap=\"{}\" TileSize=\"{}\">\n",
+            "  <Size Width=\"{}\" Height=\"{}\"/>\n",
+            "</Image>\n"
+        ),
+        OVERLAP, tile_size, width, height
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels() {
+        assert_eq!(levels(1, 1), vec![(1, 1)]);
+        assert_eq!(
+            levels(1000, 600),
+            vec![
+                (1, 1),
+                (2, 2),
+                (4, 3),
+                (8, 5),
+                (16, 10),
+                (32, 19),
+                (63, 38),
+                (125, 75),
+                (250, 150),
+                (500, 300),
+                (1000, 600)
+            ]
+        );
+        // Exact powers of two don't get an extra level
+        assert_eq!(levels(1024, 1024).len(), 11);
+    }
+
+    #[test]
+    fn test_tiles_overlap_their_neighbours() {
+        let tiles = tiles(600, 300, 254);
+        assert_eq!(tiles.len(), 6);
+
+        // First tile only extends right and down
+        assert_eq!(
+            (tiles[0].x, tiles[0].y, tiles[0].width, tiles[0].height),
+            (0, 0, 255, 255)
+        );
+        // Middle column extends both ways
+        assert_eq!((tiles[1].x, tiles[1].width), (253, 256));
+        // Edge tiles are cut short at the image's edge
+        assert_eq!((tiles[2].x, tiles[2].width), (507, 93));
+        assert_eq!((tiles[5].column, tiles[5].row), (2, 1));
+        assert_eq!((tiles[5].y, tiles[5].height), (253, 47));
+    }
+}
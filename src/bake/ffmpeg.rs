@@ -0,0 +1,166 @@
+use std::{path::Path, process::Command};
+
+use crate::errors::{Error, Result};
+
+/// Run a prepared ffmpeg/ffprobe command, turning spawn failures and non-zero
+/// exit statuses into errors that carry the tail of stderr.
+pub fn run(tool: &'static str, command: &mut Command) -> Result<Vec<u8>> {
+    let output = command.output().map_err(|e| Error::Tool {
+        tool,
+        message: format!("could not be started: {}", e),
+    })?;
+
+    if !output.status.success() {
+        return Err(Error::Tool {
+            tool,
+            message: format!(
+                "exited with {}: {}",
+                output.status,
+                stderr_tail(&output.stderr)
+            ),
+        });
+    }
+
+    Ok(output.stdout)
+}
+
+/// The last few lines of a tool's stderr, which is where ffmpeg puts the
+/// actual reason for a failure after its banner and stream listing.
+fn stderr_tail(stderr: &[u8]) -> String {
+    let stderr = String::from_utf8_lossy(stderr);
+    let lines = stderr
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>();
+    let start = lines.len().saturating_sub(3);
+    lines[start..].join(" / ")
+}
+
+pub fn ffmpeg() -> Command {
+    let mut command = Command::new("ffmpeg");
+    command.arg("-nostdin").arg("-y").arg("-v").arg("error");
+    command
+}
+
+pub fn ffprobe() -> Command {
+    let mut command = Command::new("ffprobe");
+    command.arg("-v").arg("error");
+    command
+}
+
+pub fn is_audio_only(path: &Path) -> Result<bool> {
+    let stdout = run(
+        "ffprobe",
+        ffprobe()
+            .arg("-show_entries")
+            .arg("stream=codec_type")
+            .arg("-of")
+            .arg("default=nw=1")
+            .arg(path),
+    )?;
+
+    let stdout = String::from_utf8_lossy(&stdout);
+
+    let contains_video = stdout.contains("codec_type=video");
+    let contains_audio = stdout.contains("codec_type=audio");
+
+    Ok(contains_audio && !contains_video)
+}
+
+pub fn video_length(path: &Path) -> Result<f64> {
+    let stdout = run(
+        "ffprobe",
+        ffprobe()
+            .arg("-show_entries")
+            .arg("format=duration")
+            .arg("-of")
+            .arg("csv=p=0")
+            .arg(path),
+    )?;
+
+    let stdout = String::from_utf8_lossy(&stdout);
+    let duration = stdout
+        .split_whitespace()
+        .next()
+        .ok_or_else(|| Error::Tool {
+            tool: "ffprobe",
+            message: "reported no duration".to_string(),
+        })?;
+
+    duration.parse::<f64>().map_err(|_| Error::Tool {
+        tool: "ffprobe",
+        message: format!("reported an unparseable duration: {}", duration),
+    })
+}
+
+/// Width and height of the first video stream (or the image itself).
+pub fn dimensions(path: &Path) -> Result<(u32, u32)> {
+    let stdout = run(
+        "ffprobe",
+        ffprobe()
+            .arg("-select_streams")
+            .arg("v:0")
+            .arg("-show_entries")
+            .arg("stream=width,height")
+            .arg("-of")
+            .arg("csv=p=0:s=x")
+            .arg(path),
+    )?;
+
+    let stdout = String::from_utf8_lossy(&stdout);
+    let line = stdout.lines().next().unwrap_or("").trim();
+    line.split_once('x')
+        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+        .ok_or_else(|| Error::Tool {
+            tool: "ffprobe",
+            message: format!("reported unparseable dimensions: {:?}", line),
+        })
+}
+
+/// Container and stream codecs of a media file, as reported by ffprobe.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CodecProbe {
+    /// Demuxer names, e.g. `mov,mp4,m4a,3gp,3g2,mj2` or `asf`
+    pub format_name: String,
+    pub video_codecs: Vec<String>,
+    pub audio_codecs: Vec<String>,
+}
+
+/// Run ffprobe for the given `-show_entries` and parse its JSON output.
+pub fn probe_json(path: &Path, entries: &str) -> Result<serde_json::Value> {
+    let stdout = run(
+        "ffprobe",
+        ffprobe()
+            .arg("-show_entries")
+            .arg(entries)
+            .arg("-of")
+            .arg("json")
+            .arg(path),
+    )?;
+
+    serde_json::from_slice(&stdout).map_err(|e| Error::Tool {
+        tool: "ffprobe",
+        message: format!("reported unparseable JSON: {}", e),
+    })
+}
+
+pub fn probe_codecs(path: &Path) -> Result<CodecProbe> {
+    let json = probe_json(path, "format=format_name:stream=codec_type,codec_name")?;
+
+    let mut probe = CodecProbe {
+        format_name: json["format"]["format_name"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        ..Default::default()
+    };
+    for stream in json["streams"].as_array().into_iter().flatten() {
+        let codec = stream["codec_name"].as_str().unwrap_or_default().to_string();
+        match stream["codec_type"].as_str() {
+            Some("video") => probe.video_codecs.push(codec),
+            Some("audio") => probe.audio_codecs.push(codec),
+            _ => {}
+        }
+    }
+    Ok(probe)
+}
@@ -0,0 +1,132 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use crate::errors::Result;
+
+/// A file in a baked artifact directory that nothing refers to any more.
+#[derive(Debug, Clone)]
+pub struct Orphan {
+    /// Path relative to the work dir
+    pub path: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct GcReport {
+    pub site: String,
+    pub reachable: usize,
+    pub orphans: Vec<Orphan>,
+    /// Whether the orphans were actually removed
+    pub deleted: bool,
+}
+
+impl GcReport {
+    pub fn reclaimable_bytes(&self) -> u64 {
+        self.orphans.iter().map(|orphan| orphan.size).sum()
+    }
+
+    pub fn print(&self) {
+        let verb = if self.deleted { "deleted" } else { "would delete" };
+        for orphan in &self.orphans {
+            println!("{} {} ({})", verb, orphan.path, format_bytes(orphan.size));
+        }
+        println!(
+            "GC for {}: {} artifacts reachable, {} orphaned files, {} reclaimable{}",
+            self.site,
+            self.reachable,
+            self.orphans.len(),
+            format_bytes(self.reclaimable_bytes()),
+            if self.deleted {
+                ""
+            } else {
+                " (dry run, pass --delete to remove them)"
+            }
+        );
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Whether `path` is a reachable artifact or lives inside one
+/// (multi-file artifacts are recorded by their directory).
+fn is_reachable(path: &Path, reachable: &HashSet<PathBuf>) -> bool {
+    path.ancestors().any(|ancestor| reachable.contains(ancestor))
+}
+
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Find every file under the given artifact directories that is not reachable.
+pub fn find_orphans(
+    work_dir_path: &Path,
+    directories: &[&str],
+    reachable: &HashSet<String>,
+) -> Result<Vec<Orphan>> {
+    let reachable = reachable
+        .iter()
+        .map(|path| work_dir_path.join(path))
+        .collect::<HashSet<_>>();
+
+    let mut files = vec![];
+    for directory in directories {
+        let directory = work_dir_path.join(directory);
+        if directory.is_dir() {
+            walk(&directory, &mut files)?;
+        }
+    }
+    files.sort();
+
+    Ok(files
+        .into_iter()
+        .filter(|path| !is_reachable(path, &reachable))
+        .map(|path| Orphan {
+            size: std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0),
+            path: path
+                .strip_prefix(work_dir_path)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .to_string(),
+        })
+        .collect())
+}
+
+/// Remove the orphans and any directories they leave empty.
+pub fn delete_orphans(work_dir_path: &Path, orphans: &[Orphan]) -> Result<()> {
+    for orphan in orphans {
+        let path = work_dir_path.join(&orphan.path);
+        std::fs::remove_file(&path)?;
+
+        // Prune directories emptied by the removal, stopping at the first
+        // non-empty one (remove_dir refuses to delete those).
+        for ancestor in path.ancestors().skip(1) {
+            if ancestor == work_dir_path || std::fs::remove_dir(ancestor).is_err() {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
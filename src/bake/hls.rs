@@ -0,0 +1,157 @@
+use std::{fmt::Write, path::Path};
+
+use super::{
+    commit_partial_dir,
+    config::{HlsConfig, HlsRendition},
+    ffmpeg, partial_dir_path, BakeOutcome,
+};
+use crate::{errors::Result, site::FileCrawlType};
+
+/// Kept here rather than derived from `ArtifactKind::Hls`, which carries the
+/// whole ladder and so is awkward to build just to look up a path.
+pub const DIRECTORY: &str = "hls";
+pub const MASTER_PLAYLIST: &str = "master.m3u8";
+
+/// Segment length in seconds. Every rendition is cut at the same keyframes so
+/// players can switch between them at any segment boundary.
+const SEGMENT_SECONDS: u32 = 6;
+
+/// Fingerprint of the settings used to package a video.
+pub fn settings(config: &HlsConfig) -> String {
+    let ladder = config
+        .renditions
+        .iter()
+        .map(|r| format!("{}p@{}k/{}k", r.height, r.video_kbps, r.audio_kbps))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!(
+        "hls min={}s seg={}s {}",
+        config.min_duration_seconds, SEGMENT_SECONDS, ladder
+    )
+}
+
+/// Package a long video as an HLS stream with one rendition per configured
+/// height that doesn't exceed the source. `output` is a directory holding the
+/// master playlist and one subdirectory per rendition.
+pub fn create(
+    work_dir_path: &Path,
+    source: &FileCrawlType,
+    output: &Path,
+    config: &HlsConfig,
+) -> Result<BakeOutcome> {
+    let FileCrawlType::Video { filename, .. } = source else {
+        return Ok(BakeOutcome::skipped("not a video"));
+    };
+
+    let video_path = work_dir_path.join(filename);
+    if !video_path.exists() {
+        return Ok(BakeOutcome::skipped("source file missing"));
+    }
+
+    if ffmpeg::is_audio_only(&video_path)? {
+        return Ok(BakeOutcome::not_needed("audio only"));
+    }
+    let length = ffmpeg::video_length(&video_path)?;
+    if length < config.min_duration_seconds as f64 {
+        return Ok(BakeOutcome::not_needed("shorter than HLS threshold"));
+    }
+
+    let (source_width, source_height) = ffmpeg::dimensions(&video_path)?;
+    let renditions = ladder_for(config, source_height);
+    if renditions.is_empty() {
+        return Ok(BakeOutcome::skipped("no HLS renditions configured"));
+    }
+
+    let partial = partial_dir_path(output);
+    if partial.exists() {
+        std::fs::remove_dir_all(&partial)?;
+    }
+
+    let mut master = String::from("#EXTM3U\n#EXT-X-VERSION:3\n");
+    for rendition in &renditions {
+        let name = format!("{}p", rendition.height);
+        let rendition_dir = partial.join(&name);
+        std::fs::create_dir_all(&rendition_dir)?;
+
+        ffmpeg::run(
+            "ffmpeg",
+            ffmpeg::ffmpeg()
+                .arg("-i")
+                .arg(&video_path)
+                .arg("-map")
+                .arg("0:v:0")
+                .arg("-map")
+                .arg("0:a:0?")
+                .arg("-vf")
+                .arg(format!("scale=-2:{}", rendition.height))
+                .arg("-c:v")
+                .arg("libx264")
+                .arg("-preset")
+                .arg("veryfast")
+                .arg("-pix_fmt")
+                .arg("yuv420p")
+                .arg("-b:v")
+                .arg(format!("{}k", rendition.video_kbps))
+                .arg("-maxrate")
+                .arg(format!("{}k", rendition.video_kbps * 107 / 100))
+                .arg("-bufsize")
+                .arg(format!("{}k", rendition.video_kbps * 3 / 2))
+                .arg("-sc_threshold")
+                .arg("0")
+                .arg("-force_key_frames")
+                .arg(format!("expr:gte(t,n_forced*{})", SEGMENT_SECONDS))
+                .arg("-c:a")
+                .arg("aac")
+                .arg("-b:a")
+                .arg(format!("{}k", rendition.audio_kbps))
+                .arg("-f")
+                .arg("hls")
+                .arg("-hls_time")
+                .arg(SEGMENT_SECONDS.to_string())
+                .arg("-hls_playlist_type")
+                .arg("vod")
+                .arg("-hls_segment_filename")
+                .arg(rendition_dir.join("segment_%05d.ts"))
+                .arg(rendition_dir.join("index.m3u8")),
+        )?;
+
+        let width = scaled_width(source_width, source_height, rendition.height);
+        let _ = write!(
+            master,
+            "#EXT-X-STREAM-INF:BANDWIDTH={},RESOLUTION={}x{}\n{}/index.m3u8\n",
+            (rendition.video_kbps + rendition.audio_kbps) * 1000,
+            width,
+            rendition.height,
+            name
+        );
+    }
+    std::fs::write(partial.join(MASTER_PLAYLIST), master)?;
+
+    commit_partial_dir(&partial, output)?;
+
+    Ok(BakeOutcome::created(work_dir_path, output))
+}
+
+/// The renditions worth producing for a source of a given height: every
+/// configured one that doesn't upscale, or the smallest if they all would.
+fn ladder_for(config: &HlsConfig, source_height: u32) -> Vec<HlsRendition> {
+    let mut ladder = config.renditions.clone();
+    ladder.sort_by_key(|r| r.height);
+    let fitting = ladder
+        .iter()
+        .filter(|r| r.height <= source_height)
+        .cloned()
+        .collect::<Vec<_>>();
+    if fitting.is_empty() {
+        ladder.truncate(1);
+        ladder
+    } else {
+        fitting
+    }
+}
+
+/// The width ffmpeg's `scale=-2:height` produces.
+fn scaled_width(source_width: u32, source_height: u32, height: u32) -> u32 {
+    let width = source_width as f64 * height as f64 / source_height.max(1) as f64;
+    ((width / 2.0).round() as u32 * 2).max(2)
+}
@@ -0,0 +1,113 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{Result, ResultExt};
+
+pub const MANIFEST_FILENAME: &str = "bake_manifest.json";
+
+/// Rewritten when a bake run finishes. A server watching the WorkDir reloads
+/// on this rather than on the manifest, which is saved many times mid-run.
+pub const FINISHED_FILENAME: &str = "bake_finished";
+
+/// Write `value` as JSON to `path` via a temporary file, so readers never see
+/// a half-written file.
+pub(super) fn save_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let partial = path.with_extension("partial.json");
+    let file = File::create(&partial)?;
+    serde_json::to_writer(file, value)?;
+    std::fs::rename(partial, path)?;
+    Ok(())
+}
+
+/// Enough information about a source file to notice that it has changed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceFingerprint {
+    /// Path of the source file, relative to the work dir
+    pub path: String,
+    pub size: u64,
+    /// Modification time in seconds since the epoch
+    pub modified: u64,
+}
+
+impl SourceFingerprint {
+    pub fn of(work_dir_path: &Path, relative_path: &str) -> Option<Self> {
+        let metadata = std::fs::metadata(work_dir_path.join(relative_path)).ok()?;
+        Some(SourceFingerprint {
+            path: relative_path.to_string(),
+            size: metadata.len(),
+            modified: modified_secs(&metadata)?,
+        })
+    }
+}
+
+pub fn modified_secs(metadata: &std::fs::Metadata) -> Option<u64> {
+    metadata
+        .modified()
+        .ok()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
+
+/// How a single baked artifact was produced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub item_key: String,
+    pub source: SourceFingerprint,
+    /// Describes the generator and its ffmpeg settings; changing these
+    /// invalidates every artifact made with the old ones.
+    pub settings: String,
+    /// Set when the generator decided this artifact isn't needed for this
+    /// source, so there is deliberately nothing on disk.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub not_needed: bool,
+}
+
+/// Record of every artifact bake has generated for a WorkDir, keyed by the
+/// artifact's path relative to the work dir.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BakeManifest {
+    pub artifacts: BTreeMap<String, ManifestEntry>,
+}
+
+impl BakeManifest {
+    pub fn path(work_dir_path: &Path) -> PathBuf {
+        work_dir_path.join(MANIFEST_FILENAME)
+    }
+
+    /// Load the manifest for a work dir. A missing manifest is an empty one.
+    pub fn load(work_dir_path: &Path) -> Result<Self> {
+        let path = Self::path(work_dir_path);
+        if !path.exists() {
+            return Ok(BakeManifest::default());
+        }
+
+        let file = File::open(path).context("Unable to open bake_manifest.json")?;
+        serde_json::from_reader(file).context("bake_manifest.json was not well-formatted")
+    }
+
+    /// Write the manifest, replacing the old one atomically.
+    pub fn save(&self, work_dir_path: &Path) -> Result<()> {
+        save_json(&Self::path(work_dir_path), self)
+    }
+
+    pub fn record(&mut self, artifact: String, entry: ManifestEntry) {
+        self.artifacts.insert(artifact, entry);
+    }
+}
+
+/// Note that a bake run has finished, so its artifacts are picked up by a
+/// server watching the WorkDir.
+pub fn mark_finished(work_dir_path: &Path) -> Result<()> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    std::fs::write(work_dir_path.join(FINISHED_FILENAME), now.to_string())
+        .context("Unable to write bake_finished")
+}
@@ -0,0 +1,220 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{ffmpeg, manifest::save_json, manifest::SourceFingerprint, palette, phash};
+use crate::{
+    color::Rgb,
+    errors::{Result, ResultExt},
+};
+
+pub const METADATA_FILENAME: &str = "media_metadata.json";
+
+/// Bumped whenever extraction starts recording something new, so that bake
+/// re-probes files whose metadata predates it.
+const METADATA_VERSION: u32 = 2;
+
+/// What ffprobe told us about one image or video file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MediaMetadata {
+    /// The file the metadata was extracted from, so it can be refreshed when
+    /// the file changes
+    pub source: SourceFingerprint,
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    /// Length in seconds, for videos
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video_codec: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_codec: Option<String>,
+    /// Frames per second, for videos
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame_rate: Option<f64>,
+    /// Perceptual hash of the image, or of a representative frame of the
+    /// video, used to find the same media posted in several places
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phash: Option<u64>,
+    /// Dominant colors of the same image or frame, most dominant first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub palette: Vec<Rgb>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    Portrait,
+    Landscape,
+    Square,
+}
+
+impl MediaMetadata {
+    /// File size in bytes
+    pub fn size(&self) -> u64 {
+        self.source.size
+    }
+
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        Some((self.width?, self.height?))
+    }
+
+    pub fn orientation(&self) -> Option<Orientation> {
+        let (width, height) = self.dimensions()?;
+        Some(match width.cmp(&height) {
+            std::cmp::Ordering::Less => Orientation::Portrait,
+            std::cmp::Ordering::Greater => Orientation::Landscape,
+            std::cmp::Ordering::Equal => Orientation::Square,
+        })
+    }
+
+    /// Probe a file. `is_video` decides whether duration and frame rate are
+    /// meaningful; ffprobe reports a nominal one for still images too.
+    pub fn extract(work_dir_path: &Path, source: SourceFingerprint, is_video: bool) -> Result<Self> {
+        let json = ffmpeg::probe_json(
+            &work_dir_path.join(&source.path),
+            "format=duration:stream=codec_type,codec_name,width,height,avg_frame_rate",
+        )?;
+        let mut metadata = Self::from_probe(source, &json, is_video);
+        if metadata.video_codec.is_some() {
+            let path = work_dir_path.join(&metadata.source.path);
+            metadata.phash = Some(phash::dhash(&path, metadata.duration)?);
+            metadata.palette = palette::extract(&path, metadata.duration)?;
+        }
+        Ok(metadata)
+    }
+
+    fn from_probe(source: SourceFingerprint, json: &Value, is_video: bool) -> Self {
+        let streams = json["streams"].as_array().cloned().unwrap_or_default();
+        let video = streams.iter().find(|s| s["codec_type"] == "video");
+        let audio = streams.iter().find(|s| s["codec_type"] == "audio");
+
+        let dimension = |key: &str| {
+            video
+                .and_then(|s| s[key].as_u64())
+                .and_then(|v| u32::try_from(v).ok())
+        };
+        let codec = |stream: Option<&Value>| {
+            stream
+                .and_then(|s| s["codec_name"].as_str())
+                .map(str::to_string)
+        };
+
+        MediaMetadata {
+            source,
+            version: METADATA_VERSION,
+            width: dimension("width"),
+            height: dimension("height"),
+            duration: is_video
+                .then(|| json["format"]["duration"].as_str()?.parse::<f64>().ok())
+                .flatten(),
+            video_codec: codec(video),
+            audio_codec: codec(audio),
+            frame_rate: is_video
+                .then(|| parse_rate(video?["avg_frame_rate"].as_str()?))
+                .flatten(),
+            phash: None,
+            palette: vec![],
+        }
+    }
+}
+
+/// ffprobe reports frame rates as a fraction, e.g. `30000/1001`.
+fn parse_rate(rate: &str) -> Option<f64> {
+    let (numerator, denominator) = rate.split_once('/')?;
+    let numerator = numerator.parse::<f64>().ok()?;
+    let denominator = denominator.parse::<f64>().ok()?;
+    (denominator != 0.0 && numerator != 0.0).then(|| numerator / denominator)
+}
+
+/// Extracted metadata for every media file in a WorkDir, keyed by the file's
+/// path relative to the work dir.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MediaMetadataStore {
+    pub files: BTreeMap<String, MediaMetadata>,
+}
+
+impl MediaMetadataStore {
+    pub fn path(work_dir_path: &Path) -> PathBuf {
+        work_dir_path.join(METADATA_FILENAME)
+    }
+
+    /// Load the store for a work dir. A missing store is an empty one.
+    pub fn load(work_dir_path: &Path) -> Result<Self> {
+        let path = Self::path(work_dir_path);
+        if !path.exists() {
+            return Ok(MediaMetadataStore::default());
+        }
+
+        let file = File::open(path).context("Unable to open media_metadata.json")?;
+        serde_json::from_reader(file).context("media_metadata.json was not well-formatted")
+    }
+
+    pub fn save(&self, work_dir_path: &Path) -> Result<()> {
+        save_json(&Self::path(work_dir_path), self)
+    }
+
+    /// Whether the stored metadata for a file was extracted from its current
+    /// contents by the current version of bake.
+    pub fn is_current(&self, work_dir_path: &Path, relative_path: &str) -> bool {
+        self.files.get(relative_path).is_some_and(|metadata| {
+            metadata.version == METADATA_VERSION
+                && SourceFingerprint::of(work_dir_path, relative_path).as_ref()
+                    == Some(&metadata.source)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn source() -> SourceFingerprint {
+        SourceFingerprint {
+            path: "media/clip.mp4".to_string(),
+            size: 1024,
+            modified: 0,
+        }
+    }
+
+    #[test]
+    fn test_from_probe_video() {
+        let json = json!({
+            "streams": [
+                { "codec_type": "video", "codec_name": "h264", "width": 1920, "height": 1080, "avg_frame_rate": "30000/1001" },
+                { "codec_type": "audio", "codec_name": "aac", "avg_frame_rate": "0/0" }
+            ],
+            "format": { "duration": "93.500000" }
+        });
+        let metadata = MediaMetadata::from_probe(source(), &json, true);
+        assert_eq!(metadata.dimensions(), Some((1920, 1080)));
+        assert_eq!(metadata.orientation(), Some(Orientation::Landscape));
+        assert_eq!(metadata.duration, Some(93.5));
+        assert_eq!(metadata.video_codec.as_deref(), Some("h264"));
+        assert_eq!(metadata.audio_codec.as_deref(), Some("aac"));
+        assert!((metadata.frame_rate.unwrap() - 29.97).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_from_probe_image_ignores_nominal_duration() {
+        let json = json!({
+            "streams": [
+                { "codec_type": "video", "codec_name": "mjpeg", "width": 800, "height": 1200, "avg_frame_rate": "25/1" }
+            ],
+            "format": { "duration": "0.040000" }
+        });
+        let metadata = MediaMetadata::from_probe(source(), &json, false);
+        assert_eq!(metadata.orientation(), Some(Orientation::Portrait));
+        assert_eq!(metadata.duration, None);
+        assert_eq!(metadata.frame_rate, None);
+    }
+}
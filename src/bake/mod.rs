@@ -0,0 +1,813 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+
+use crate::{
+    errors::{Error, Result},
+    site::{CrawlItem, FileCrawlType},
+    workdir::WorkDir,
+};
+
+pub mod config;
+mod deep_zoom;
+mod ffmpeg;
+pub mod gc;
+mod hls;
+pub mod manifest;
+pub mod metadata;
+mod palette;
+pub mod phash;
+mod placeholder;
+pub mod plan;
+pub mod pool;
+mod report;
+pub mod service;
+mod storyboard;
+mod thumbnail;
+mod transcode;
+mod variants;
+
+pub use config::{BakeConfig, DeepZoomConfig, HlsConfig, HlsRendition, ImageFormat};
+pub use gc::GcReport;
+pub use manifest::BakeManifest;
+pub use metadata::{MediaMetadata, MediaMetadataStore, Orientation};
+pub use plan::{ArtifactKind, BakeJob, BakePlan, JobState, PlanEntry};
+pub use report::{BakeEntry, BakeOutcome, BakeReport, BakeSummary};
+pub use service::{ThumbnailService, ThumbnailStatus};
+
+/// Options controlling a bake run.
+#[derive(Debug, Clone)]
+pub struct BakeOptions {
+    /// Number of items processed concurrently (each may spawn an ffmpeg process)
+    pub jobs: usize,
+}
+
+impl Default for BakeOptions {
+    fn default() -> Self {
+        BakeOptions {
+            jobs: pool::default_jobs(),
+        }
+    }
+}
+
+pub trait Bake {
+    /// Work out what a bake run would do without doing any of it.
+    fn bake_plan(&self) -> BakePlan;
+    fn bake_all(&self, options: &BakeOptions) -> BakeReport;
+    /// Find baked artifacts that are no longer reachable from the current
+    /// items, removing them when `delete` is set.
+    fn collect_garbage(&self, delete: bool) -> Result<GcReport>;
+}
+
+impl FileCrawlType {
+    pub fn is_image(&self) -> bool {
+        matches!(self, FileCrawlType::Image { .. })
+    }
+
+    pub fn is_video(&self) -> bool {
+        matches!(self, FileCrawlType::Video { .. })
+    }
+
+    pub fn is_text(&self) -> bool {
+        matches!(self, FileCrawlType::Text { .. })
+    }
+
+    /// The path of an image or video file, relative to the work dir.
+    pub fn media_filename(&self) -> Option<&str> {
+        match self {
+            FileCrawlType::Image { filename, .. } | FileCrawlType::Video { filename, .. } => {
+                Some(filename)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Video extensions that are usually playable without transcoding.
+const WEB_READY_EXTENSIONS: &[&str] = &["mp4", "m4v", "webm"];
+
+/// Move a finished artifact from its temporary path into place, so an
+/// interrupted run never leaves a truncated file where a finished one belongs.
+fn commit_partial(partial_path: &Path, final_path: &Path) -> Result<()> {
+    std::fs::rename(partial_path, final_path).map_err(Error::from)
+}
+
+/// The temporary path an artifact is written to before `commit_partial`.
+/// Keeps the extension so ffmpeg can still infer the output format.
+fn partial_path(final_path: &Path) -> PathBuf {
+    let extension = final_path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_string())
+        .unwrap_or_default();
+    final_path.with_extension(format!("partial.{}", extension))
+}
+
+/// The temporary directory a multi-file artifact is assembled in before
+/// `commit_partial_dir`.
+fn partial_dir_path(final_path: &Path) -> PathBuf {
+    final_path.with_extension("partial")
+}
+
+/// Replace a directory artifact with a freshly assembled one.
+fn commit_partial_dir(partial_path: &Path, final_path: &Path) -> Result<()> {
+    if final_path.exists() {
+        std::fs::remove_dir_all(final_path)?;
+    }
+    std::fs::rename(partial_path, final_path).map_err(Error::from)
+}
+
+impl CrawlItem {
+    /// The key artifact paths are derived from. Always the key the item has
+    /// on its own site, so the `all` view finds the same artifacts.
+    fn artifact_key(&self) -> &str {
+        self.original_key.as_deref().unwrap_or(&self.key)
+    }
+
+    pub fn calculate_auto_thumbnail_path(
+        &self,
+        work_dir_path: &Path,
+        thumbnail_of: &FileCrawlType,
+    ) -> PathBuf {
+        let hash = md5::compute(self.artifact_key().as_bytes());
+        let hash_str = format!("{:x}", hash);
+
+        let extension = match thumbnail_of {
+            FileCrawlType::Image { .. } => "jpg",
+            FileCrawlType::Video { .. } => "mp4",
+            _ => panic!("Cannot create thumbnail for non-image or non-video file"),
+        };
+
+        work_dir_path
+            .join(ArtifactKind::Thumbnail.directory())
+            .join(hash_str)
+            .with_extension(extension)
+    }
+
+    /// The file an auto thumbnail would be generated from, if the item has one.
+    pub fn first_thumbnailable_file(&self) -> Option<FileCrawlType> {
+        self.flat_files()
+            .into_values()
+            .find(|file| file.is_downloaded() && (file.is_image() || file.is_video()))
+    }
+
+    /// The image preview variants are resized from: the explicit preview if
+    /// the site provided one, otherwise the item's first image.
+    pub fn preview_source_image(&self) -> Option<FileCrawlType> {
+        let is_usable = |file: &FileCrawlType| file.is_downloaded() && file.is_image();
+        self.flat_previews()
+            .into_values()
+            .find(is_usable)
+            .or_else(|| self.flat_files().into_values().find(is_usable))
+    }
+
+    pub fn preview_variant_path(
+        &self,
+        work_dir_path: &Path,
+        width: u32,
+        format: ImageFormat,
+    ) -> PathBuf {
+        let hash = md5::compute(self.artifact_key().as_bytes());
+        work_dir_path
+            .join(ArtifactKind::PreviewVariant { width, format }.directory())
+            .join(format!("{:x}-{}", hash, width))
+            .with_extension(format.extension())
+    }
+
+    pub fn placeholder_path(&self, work_dir_path: &Path) -> PathBuf {
+        let hash = md5::compute(self.artifact_key().as_bytes());
+        work_dir_path
+            .join(ArtifactKind::Placeholder.directory())
+            .join(format!("{:x}", hash))
+            .with_extension("jpg")
+    }
+
+    /// Read the baked placeholder into `placeholder` as a `data:` URI, small
+    /// enough to inline in listing pages so it shows before any request for
+    /// the thumbnail.
+    pub fn load_placeholder(&mut self) {
+        self.placeholder = self
+            .site_settings
+            .work_dir_path
+            .as_ref()
+            .and_then(|work_dir_path| std::fs::read(self.placeholder_path(work_dir_path)).ok())
+            .map(|bytes| {
+                format!("data:image/jpeg;base64,{}", BASE64_STANDARD.encode(bytes)).into()
+            });
+    }
+
+    pub fn display_variant_path(
+        &self,
+        work_dir_path: &Path,
+        file_key: &str,
+        width: u32,
+        format: ImageFormat,
+    ) -> PathBuf {
+        let hash = md5::compute(format!("{}/{}", self.artifact_key(), file_key).as_bytes());
+        work_dir_path
+            .join(ArtifactKind::DisplayVariant { width, format }.directory())
+            .join(format!("{:x}-{}", hash, width))
+            .with_extension(format.extension())
+    }
+
+    pub fn transcoded_video_path(&self, work_dir_path: &Path, file_key: &str) -> PathBuf {
+        let hash = md5::compute(format!("{}/{}", self.artifact_key(), file_key).as_bytes());
+        work_dir_path
+            .join(ArtifactKind::Transcode.directory())
+            .join(format!("{:x}", hash))
+            .with_extension("mp4")
+    }
+
+    /// The relative path of a version of a video that browsers can play: the
+    /// baked transcode if there is one, then the original if its extension
+    /// suggests it is web-ready, then an mp4 left next to it by the scraper.
+    /// Falls back to the original when nothing better exists.
+    pub fn playable_video_path(&self, file_key: &str, filename: &str) -> String {
+        let Some(work_dir_path) = self.site_settings.work_dir_path.as_ref() else {
+            return filename.to_string();
+        };
+
+        let transcoded = self.transcoded_video_path(work_dir_path, file_key);
+        if transcoded.exists() {
+            return transcoded
+                .strip_prefix(work_dir_path)
+                .unwrap_or(&transcoded)
+                .to_string_lossy()
+                .to_string();
+        }
+
+        let original = Path::new(filename);
+        let web_ready_extension = original
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| {
+                WEB_READY_EXTENSIONS
+                    .iter()
+                    .any(|ready| ext.eq_ignore_ascii_case(ready))
+            });
+        if !web_ready_extension {
+            let sibling = original.with_extension("mp4");
+            if work_dir_path.join(&sibling).exists() {
+                return sibling.to_string_lossy().to_string();
+            }
+        }
+
+        filename.to_string()
+    }
+
+    pub fn hls_path(&self, work_dir_path: &Path, file_key: &str) -> PathBuf {
+        let hash = md5::compute(format!("{}/{}", self.artifact_key(), file_key).as_bytes());
+        work_dir_path
+            .join(hls::DIRECTORY)
+            .join(format!("{:x}", hash))
+    }
+
+    /// Relative path to the HLS master playlist of one of the item's videos,
+    /// if it has been packaged.
+    pub fn hls_master_path(&self, file_key: &str) -> Option<String> {
+        let work_dir_path = self.site_settings.work_dir_path.as_ref()?;
+        let master = self
+            .hls_path(work_dir_path, file_key)
+            .join(hls::MASTER_PLAYLIST);
+        if !master.exists() {
+            return None;
+        }
+        Some(
+            master
+                .strip_prefix(work_dir_path)
+                .unwrap_or(&master)
+                .to_string_lossy()
+                .to_string(),
+        )
+    }
+
+    pub fn deep_zoom_path(&self, work_dir_path: &Path, file_key: &str) -> PathBuf {
+        let hash = md5::compute(format!("{}/{}", self.artifact_key(), file_key).as_bytes());
+        work_dir_path
+            .join(deep_zoom::DIRECTORY)
+            .join(format!("{:x}", hash))
+    }
+
+    /// Relative path to the DZI descriptor of one of the item's images, if
+    /// it was big enough to be tiled.
+    pub fn deep_zoom_dzi_path(&self, file_key: &str) -> Option<String> {
+        let work_dir_path = self.site_settings.work_dir_path.as_ref()?;
+        let dzi = self
+            .deep_zoom_path(work_dir_path, file_key)
+            .join(deep_zoom::DZI_FILENAME);
+        if !dzi.exists() {
+            return None;
+        }
+        Some(
+            dzi.strip_prefix(work_dir_path)
+                .unwrap_or(&dzi)
+                .to_string_lossy()
+                .to_string(),
+        )
+    }
+
+    /// The directory holding the storyboard of one of the item's videos.
+    pub fn storyboard_path(&self, work_dir_path: &Path, file_key: &str) -> PathBuf {
+        let hash = md5::compute(format!("{}/{}", self.artifact_key(), file_key).as_bytes());
+        work_dir_path
+            .join(ArtifactKind::Storyboard.directory())
+            .join(format!("{:x}", hash))
+    }
+
+    /// Relative path to the storyboard WebVTT track of one of the item's
+    /// videos, if it has been baked.
+    pub fn storyboard_vtt_path(&self, file_key: &str) -> Option<String> {
+        let work_dir_path = self.site_settings.work_dir_path.as_ref()?;
+        let vtt_path = self
+            .storyboard_path(work_dir_path, file_key)
+            .join(storyboard::VTT_FILENAME);
+        if !vtt_path.exists() {
+            return None;
+        }
+        Some(
+            vtt_path
+                .strip_prefix(work_dir_path)
+                .unwrap_or(&vtt_path)
+                .to_string_lossy()
+                .to_string(),
+        )
+    }
+
+    /// Baked preview variants that exist on disk, best format first.
+    pub fn preview_variants(&self) -> Vec<ImageVariant> {
+        self.existing_variants(
+            |work_dir_path, width, format| self.preview_variant_path(work_dir_path, width, format),
+            &self.site_settings.bake.preview_widths,
+        )
+    }
+
+    /// Baked display variants of one of the item's files that exist on disk,
+    /// best format first.
+    pub fn display_variants(&self, file_key: &str) -> Vec<ImageVariant> {
+        self.existing_variants(
+            |work_dir_path, width, format| {
+                self.display_variant_path(work_dir_path, file_key, width, format)
+            },
+            &self.site_settings.bake.display_widths,
+        )
+    }
+
+    fn existing_variants(
+        &self,
+        path_of: impl Fn(&Path, u32, ImageFormat) -> PathBuf,
+        widths: &[u32],
+    ) -> Vec<ImageVariant> {
+        let Some(work_dir_path) = self.site_settings.work_dir_path.as_ref() else {
+            return vec![];
+        };
+
+        let mut formats = self.site_settings.bake.formats.clone();
+        formats.sort();
+        formats.dedup();
+
+        let mut variants = vec![];
+        for format in formats {
+            for &width in widths {
+                let path = path_of(work_dir_path, width, format);
+                if path.exists() {
+                    variants.push(ImageVariant {
+                        path: path
+                            .strip_prefix(work_dir_path)
+                            .unwrap_or(&path)
+                            .to_string_lossy()
+                            .to_string(),
+                        width,
+                        format,
+                    });
+                }
+            }
+        }
+        variants
+    }
+}
+
+/// A resized copy of an image produced by bake.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageVariant {
+    /// Path of the variant, relative to the work dir
+    pub path: String,
+    pub width: u32,
+    pub format: ImageFormat,
+}
+
+/// Ensure that all items have previews available. If an explicit preview was
+/// not provided by the site, attempt to generate a thumbnail. Images are also
+/// resized to the widths and formats in the site's `bake` config, so pages can
+/// offer the browser a `srcset` to choose from.
+///
+/// Jobs are processed on a pool of `options.jobs` workers. Artifacts are
+/// written to a temporary path and moved into place when complete, and the
+/// manifest is saved as the run goes, so an interrupted run can simply be
+/// started again and picks up where it left off.
+impl Bake for WorkDir {
+    fn bake_plan(&self) -> BakePlan {
+        let items = self.crawled.values().collect::<Vec<_>>();
+        self.plan_for(&items)
+    }
+
+    fn bake_all(&self, options: &BakeOptions) -> BakeReport {
+        let work_dir_path = PathBuf::from(self.path.clone());
+        let manifest = load_manifest_or_empty(&work_dir_path);
+        let items = self.crawled.values().collect::<Vec<_>>();
+        let (metadata_extracted, metadata_failures) =
+            refresh_metadata(&work_dir_path, &items, options.jobs);
+        let plan = BakePlan::build(
+            self.config.slug.clone(),
+            &work_dir_path,
+            &items,
+            &self.config.bake,
+            &manifest,
+        );
+
+        let manifest = Mutex::new(manifest);
+        let unsaved = AtomicUsize::new(0);
+        let record = |job: &BakeJob, not_needed: bool| {
+            let Some(mut entry) = job.manifest_entry(&work_dir_path) else {
+                return;
+            };
+            entry.not_needed = not_needed;
+            let mut manifest = manifest.lock().expect("bake manifest poisoned");
+            manifest.record(job.output.clone(), entry);
+            if unsaved.fetch_add(1, Ordering::SeqCst) + 1 >= MANIFEST_SAVE_INTERVAL {
+                unsaved.store(0, Ordering::SeqCst);
+                save_manifest_or_warn(&manifest, &work_dir_path);
+            }
+        };
+
+        let progress = pool::Progress::new(plan.entries.len());
+        let entries = pool::run(&plan.entries, options.jobs, |entry| {
+            let outcome = match entry {
+                PlanEntry::Skip { reason, .. } => BakeOutcome::skipped(reason.clone()),
+                PlanEntry::Job { job, state } => match state {
+                    JobState::UpToDate => BakeOutcome::skipped("up to date"),
+                    JobState::Untracked => {
+                        record(job, false);
+                        BakeOutcome::Adopted {
+                            path: job.output.clone(),
+                        }
+                    }
+                    JobState::Missing | JobState::Stale(_) => {
+                        let outcome = run_job(&work_dir_path, job);
+                        match outcome {
+                            BakeOutcome::Created { .. } => record(job, false),
+                            BakeOutcome::NotNeeded { .. } => record(job, true),
+                            _ => {}
+                        }
+                        outcome
+                    }
+                },
+            };
+            progress.tick(entry.item_key(), &outcome);
+            BakeEntry {
+                item_key: entry.item_key().to_string(),
+                outcome,
+            }
+        });
+
+        save_manifest_or_warn(
+            &manifest.into_inner().expect("bake manifest poisoned"),
+            &work_dir_path,
+        );
+        if let Err(e) = manifest::mark_finished(&work_dir_path) {
+            println!("Failed to mark the bake as finished: {}", e);
+        }
+
+        let mut report = BakeReport::new(
+            self.config.slug.clone(),
+            metadata_failures.into_iter().chain(entries).collect(),
+        );
+        report.summary.metadata_extracted = metadata_extracted;
+        report
+    }
+
+    fn collect_garbage(&self, delete: bool) -> Result<GcReport> {
+        let work_dir_path = PathBuf::from(self.path.clone());
+        let mut items = self.crawled.values().collect::<Vec<_>>();
+        // The `all` view's items can differ from the site's own, and get
+        // thumbnails made for them while serving
+        if let Some(crawled_for_all) = &self.crawled_for_all {
+            items.extend(crawled_for_all.values());
+        }
+        let plan = self.plan_for(&items);
+        let reachable = plan
+            .jobs()
+            .map(|(job, _)| job.output.clone())
+            .collect::<HashSet<_>>();
+
+        let orphans = gc::find_orphans(&work_dir_path, ArtifactKind::DIRECTORIES, &reachable)?;
+
+        if delete {
+            gc::delete_orphans(&work_dir_path, &orphans)?;
+
+            let mut manifest = BakeManifest::load(&work_dir_path)?;
+            manifest
+                .artifacts
+                .retain(|artifact, _| reachable.contains(artifact));
+            manifest.save(&work_dir_path)?;
+        }
+
+        Ok(GcReport {
+            site: self.config.slug.clone(),
+            reachable: reachable.len(),
+            orphans,
+            deleted: delete,
+        })
+    }
+}
+
+impl WorkDir {
+    fn plan_for(&self, items: &[&CrawlItem]) -> BakePlan {
+        let work_dir_path = PathBuf::from(self.path.clone());
+        let manifest = load_manifest_or_empty(&work_dir_path);
+        BakePlan::build(
+            self.config.slug.clone(),
+            &work_dir_path,
+            items,
+            &self.config.bake,
+            &manifest,
+        )
+    }
+}
+
+/// How many newly recorded artifacts may accumulate before the manifest is
+/// written out mid-run.
+const MANIFEST_SAVE_INTERVAL: usize = 25;
+
+fn run_job(work_dir_path: &Path, job: &BakeJob) -> BakeOutcome {
+    let output = work_dir_path.join(&job.output);
+    if let Some(parent) = output.parent() {
+        if let Err(e) = std::fs::create_dir_all(parent) {
+            return BakeOutcome::failed(e);
+        }
+    }
+
+    let outcome = job
+        .kind
+        .generate(work_dir_path, &job.source, &output)
+        .unwrap_or_else(BakeOutcome::failed);
+
+    // An artifact made before the source changed may no longer be wanted.
+    if let BakeOutcome::NotNeeded { .. } = outcome {
+        let removed = if output.is_dir() {
+            std::fs::remove_dir_all(&output)
+        } else {
+            std::fs::remove_file(&output)
+        };
+        if let Err(e) = removed {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                return BakeOutcome::failed(e);
+            }
+        }
+    }
+
+    outcome
+}
+
+/// Probe every downloaded image and video whose metadata is missing or was
+/// extracted from an older version of the file, and drop metadata for files
+/// no item refers to any more. Returns how many files were probed, and an
+/// entry for each one that couldn't be.
+fn refresh_metadata(
+    work_dir_path: &Path,
+    items: &[&CrawlItem],
+    jobs: usize,
+) -> (usize, Vec<BakeEntry>) {
+    let mut store = MediaMetadataStore::load(work_dir_path).unwrap_or_else(|e| {
+        println!("Ignoring unreadable media metadata: {}", e);
+        MediaMetadataStore::default()
+    });
+
+    let mut referenced = HashSet::new();
+    let mut tasks = vec![];
+    for item in items {
+        let files = item
+            .flat_files()
+            .into_values()
+            .chain(item.flat_previews().into_values());
+        for file in files {
+            let Some(filename) = file.media_filename() else {
+                continue;
+            };
+            if !file.is_downloaded() || !referenced.insert(filename.to_string()) {
+                continue;
+            }
+            if store.is_current(work_dir_path, filename) {
+                continue;
+            }
+            if let Some(source) = manifest::SourceFingerprint::of(work_dir_path, filename) {
+                tasks.push((item.key.clone(), source, file.is_video()));
+            }
+        }
+    }
+    store.files.retain(|filename, _| referenced.contains(filename));
+
+    let progress = pool::Progress::new(tasks.len());
+    let results = pool::run(&tasks, jobs, |(item_key, source, is_video)| {
+        let result = MediaMetadata::extract(work_dir_path, source.clone(), *is_video);
+        match &result {
+            Ok(_) => progress.tick(item_key, &"metadata extracted"),
+            Err(e) => progress.tick(item_key, e),
+        }
+        result
+    });
+
+    let mut extracted = 0;
+    let mut failures = vec![];
+    for ((item_key, source, _), result) in tasks.into_iter().zip(results) {
+        match result {
+            Ok(metadata) => {
+                extracted += 1;
+                store.files.insert(source.path, metadata);
+            }
+            Err(e) => failures.push(BakeEntry {
+                item_key,
+                outcome: BakeOutcome::failed(format!("metadata for {}: {}", source.path, e)),
+            }),
+        }
+    }
+
+    if let Err(e) = store.save(work_dir_path) {
+        println!("Failed to save media metadata: {}", e);
+    }
+
+    (extracted, failures)
+}
+
+fn load_manifest_or_empty(work_dir_path: &Path) -> BakeManifest {
+    BakeManifest::load(work_dir_path).unwrap_or_else(|e| {
+        println!("Ignoring unreadable bake manifest: {}", e);
+        BakeManifest::default()
+    })
+}
+
+fn save_manifest_or_warn(manifest: &BakeManifest, work_dir_path: &Path) {
+    if let Err(e) = manifest.save(work_dir_path) {
+        println!("Failed to save bake manifest: {}", e);
+    }
+}
+
+/// A fresh, empty directory for a test to bake into.
+#[cfg(test)]
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("site-server-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workdir::SiteItems;
+
+    /// An image item whose file is `{key}.jpg`.
+    fn image_item(key: &str) -> CrawlItem {
+        let mut item = CrawlItem::for_test(key, key, &[]);
+        item.files.insert(
+            key.to_string(),
+            FileCrawlType::Image {
+                key: key.to_string(),
+                filename: format!("{}.jpg", key),
+                downloaded: true,
+                url: String::new(),
+            },
+        );
+        item
+    }
+
+    fn work_dir(path: &Path, crawled: Vec<CrawlItem>, for_all: Option<Vec<CrawlItem>>) -> WorkDir {
+        let site_items = |items: Vec<CrawlItem>| -> SiteItems {
+            items
+                .into_iter()
+                .map(|item| (item.key.clone(), item))
+                .collect::<indexmap::IndexMap<_, _>>()
+                .into()
+        };
+        WorkDir {
+            path: path.into(),
+            config: serde_json::from_value(
+                serde_json::json!({ "site": "x", "slug": "x", "label": "x" }),
+            )
+            .unwrap(),
+            crawled: site_items(crawled),
+            crawled_for_all: for_all.map(site_items),
+            server_config: Default::default(),
+            last_seen_modified: 0,
+            loaded_at: 0,
+        }
+    }
+
+    /// Write an empty file at `path`, relative to `dir`.
+    fn touch(dir: &Path, path: &str) {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, b"").unwrap();
+    }
+
+    fn thumbnail(item: &CrawlItem) -> String {
+        let job = plan::thumbnail_job(item, Path::new("")).unwrap();
+        job.output
+    }
+
+    #[test]
+    fn test_collect_garbage() {
+        let dir = test_dir("gc");
+        let item = image_item("a");
+        let reachable = thumbnail(&item);
+        for path in [
+            reachable.as_str(),
+            "auto_thumbnails/orphan.jpg",
+            "storyboards/gone/sprite.jpg",
+            "a.jpg",
+            "notes/old.jpg",
+        ] {
+            touch(&dir, path);
+        }
+        let entry = plan::thumbnail_job(&item, &dir)
+            .unwrap()
+            .manifest_entry(&dir)
+            .unwrap();
+        let mut manifest = BakeManifest::default();
+        for artifact in [reachable.as_str(), "auto_thumbnails/orphan.jpg"] {
+            manifest.record(artifact.to_string(), entry.clone());
+        }
+        manifest.save(&dir).unwrap();
+        let work_dir = work_dir(&dir, vec![item], None);
+
+        // A dry run only reports
+        let report = work_dir.collect_garbage(false).unwrap();
+        let orphans = report.orphans.iter().map(|o| &o.path).collect::<Vec<_>>();
+        assert_eq!(
+            orphans,
+            vec!["auto_thumbnails/orphan.jpg", "storyboards/gone/sprite.jpg"]
+        );
+        assert!(dir.join("auto_thumbnails/orphan.jpg").exists());
+        assert_eq!(BakeManifest::load(&dir).unwrap().artifacts.len(), 2);
+
+        // Deleting leaves reachable artifacts and anything outside the
+        // directories bake owns
+        work_dir.collect_garbage(true).unwrap();
+        assert!(!dir.join("auto_thumbnails/orphan.jpg").exists());
+        assert!(!dir.join("storyboards").exists());
+        for path in [reachable.as_str(), "a.jpg", "notes/old.jpg"] {
+            assert!(dir.join(path).exists(), "{}", path);
+        }
+        let manifest = BakeManifest::load(&dir).unwrap();
+        assert_eq!(
+            manifest.artifacts.keys().collect::<Vec<_>>(),
+            vec![&reachable]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_gc_keeps_the_all_views_artifacts() {
+        let dir = test_dir("gc-all-view");
+        let (own, merged) = (image_item("a"), image_item("merged"));
+        touch(&dir, &thumbnail(&own));
+        touch(&dir, &thumbnail(&merged));
+        touch(&dir, "auto_thumbnails/orphan.jpg");
+
+        let work_dir = work_dir(&dir, vec![own.clone()], Some(vec![merged]));
+        let report = work_dir.collect_garbage(false).unwrap();
+        let orphans = report.orphans.iter().map(|o| &o.path).collect::<Vec<_>>();
+        assert_eq!(orphans, vec!["auto_thumbnails/orphan.jpg"]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_placeholder() {
+        let dir = test_dir("placeholder");
+        let mut item = CrawlItem::for_test("a", "a", &[]);
+        item.load_placeholder();
+        assert_eq!(item.placeholder, None);
+
+        item.site_settings.work_dir_path = Some(dir.clone());
+        item.load_placeholder();
+        assert_eq!(item.placeholder, None);
+
+        let path = item.placeholder_path(&dir);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, b"jpeg").unwrap();
+        item.load_placeholder();
+        assert_eq!(
+            item.placeholder.as_deref(),
+            Some("data:image/jpeg;base64,anBlZw==")
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
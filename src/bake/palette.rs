@@ -0,0 +1,97 @@
+use std::path::Path;
+
+use super::{ffmpeg, phash::VIDEO_FRAME_POSITION};
+use crate::{color::Rgb, errors::Result};
+
+/// The frame is shrunk to this many pixels square before counting colors;
+/// dominant colors survive the scaling, noise and fine detail don't.
+const SAMPLE_SIZE: usize = 32;
+/// Bits kept per channel when grouping similar colors together.
+const BUCKET_BITS: u32 = 3;
+/// Groups covering less of the frame than this aren't dominant.
+const MIN_SHARE: f64 = 0.05;
+/// At most this many colors are kept per file.
+const MAX_COLORS: usize = 5;
+
+/// Dominant colors of an image, or of the same video frame the perceptual
+/// hash is taken from, most dominant first.
+pub fn extract(path: &Path, duration: Option<f64>) -> Result<Vec<Rgb>> {
+    let mut command = ffmpeg::ffmpeg();
+    if let Some(duration) = duration {
+        command
+            .arg("-ss")
+            .arg(format!("{:.3}", duration * VIDEO_FRAME_POSITION));
+    }
+    let pixels = ffmpeg::run(
+        "ffmpeg",
+        command
+            .arg("-i")
+            .arg(path)
+            .arg("-frames:v")
+            .arg("1")
+            .arg("-vf")
+            .arg(format!(
+                "scale={}:{}:flags=area,format=rgb24",
+                SAMPLE_SIZE, SAMPLE_SIZE
+            ))
+            .arg("-f")
+            .arg("rawvideo")
+            .arg("-"),
+    )?;
+
+    Ok(dominant_colors(&pixels))
+}
+
+/// Group RGB24 pixels into coarse buckets and average the largest ones.
+fn dominant_colors(pixels: &[u8]) -> Vec<Rgb> {
+    let shift = 8 - BUCKET_BITS;
+    let mut buckets = vec![(0usize, [0u64; 3]); 1 << (3 * BUCKET_BITS)];
+    for pixel in pixels.chunks_exact(3) {
+        let index = ((pixel[0] >> shift) as usize) << (2 * BUCKET_BITS)
+            | ((pixel[1] >> shift) as usize) << BUCKET_BITS
+            | (pixel[2] >> shift) as usize;
+        let (count, sums) = &mut buckets[index];
+        *count += 1;
+        for (sum, channel) in sums.iter_mut().zip(pixel) {
+            *sum += *channel as u64;
+        }
+    }
+
+    let total = pixels.len() / 3;
+    buckets.sort_by_key(|(count, _)| std::cmp::Reverse(*count));
+    buckets
+        .into_iter()
+        .take(MAX_COLORS)
+        .filter(|(count, _)| *count > 0 && *count as f64 / total as f64 >= MIN_SHARE)
+        .map(|(count, sums)| {
+            let average = |sum: u64| (sum / count as u64) as u8;
+            Rgb(average(sums[0]), average(sums[1]), average(sums[2]))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dominant_colors() {
+        // Three quarters red-ish, a quarter blue, a speck of green
+        let mut pixels = vec![];
+        for i in 0..100 {
+            let pixel = match i {
+                0 => [0, 255, 0],
+                i if i % 4 == 0 => [20, 40, 200],
+                i if i % 2 == 0 => [200, 10, 10],
+                _ => [210, 20, 20],
+            };
+            pixels.extend_from_slice(&pixel);
+        }
+
+        assert_eq!(
+            dominant_colors(&pixels),
+            vec![Rgb(206, 16, 16), Rgb(20, 40, 200)]
+        );
+        assert!(dominant_colors(&[]).is_empty());
+    }
+}
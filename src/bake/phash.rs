@@ -0,0 +1,99 @@
+use std::path::Path;
+
+use super::ffmpeg;
+use crate::errors::{Error, Result};
+
+/// The frame is shrunk to this many columns, one more than the number of bits
+/// per row, so every pixel has a right-hand neighbour to compare against.
+const HASH_COLUMNS: usize = 9;
+const HASH_ROWS: usize = 8;
+
+/// How far into a video its representative frame is taken, as a fraction of
+/// its length. Skips past fade-ins and title cards without drifting far from
+/// what a thumbnail of the same clip would show.
+pub(super) const VIDEO_FRAME_POSITION: f64 = 0.1;
+
+/// Difference hash of an image, or of one frame of a video. Copies that were
+/// resized, recompressed or lightly cropped hash to values only a few bits
+/// apart; see [`distance`].
+pub fn dhash(path: &Path, duration: Option<f64>) -> Result<u64> {
+    let mut command = ffmpeg::ffmpeg();
+    if let Some(duration) = duration {
+        command
+            .arg("-ss")
+            .arg(format!("{:.3}", duration * VIDEO_FRAME_POSITION));
+    }
+    let pixels = ffmpeg::run(
+        "ffmpeg",
+        command
+            .arg("-i")
+            .arg(path)
+            .arg("-frames:v")
+            .arg("1")
+            .arg("-vf")
+            .arg(format!(
+                "scale={}:{}:flags=area,format=gray",
+                HASH_COLUMNS, HASH_ROWS
+            ))
+            .arg("-f")
+            .arg("rawvideo")
+            .arg("-"),
+    )?;
+
+    dhash_of_pixels(&pixels).ok_or_else(|| Error::Tool {
+        tool: "ffmpeg",
+        message: format!(
+            "produced {} bytes for a {}x{} frame",
+            pixels.len(),
+            HASH_COLUMNS,
+            HASH_ROWS
+        ),
+    })
+}
+
+/// One bit per pixel of a 9x8 grayscale frame: set when the pixel is brighter
+/// than the one to its right.
+fn dhash_of_pixels(pixels: &[u8]) -> Option<u64> {
+    if pixels.len() != HASH_COLUMNS * HASH_ROWS {
+        return None;
+    }
+
+    let mut hash = 0u64;
+    for row in pixels.chunks(HASH_COLUMNS) {
+        for pair in row.windows(2) {
+            hash = (hash << 1) | u64::from(pair[0] > pair[1]);
+        }
+    }
+    Some(hash)
+}
+
+/// Number of differing bits between two hashes.
+pub fn distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dhash_of_pixels() {
+        // Every row gets darker to the right, so every comparison is "brighter"
+        let falling = (0..8)
+            .flat_map(|_| (0..9).rev().map(|x| x as u8 * 10))
+            .collect::<Vec<_>>();
+        assert_eq!(dhash_of_pixels(&falling), Some(u64::MAX));
+
+        let flat = vec![128; 72];
+        assert_eq!(dhash_of_pixels(&flat), Some(0));
+
+        assert_eq!(dhash_of_pixels(&flat[..71]), None);
+    }
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(distance(0, 0), 0);
+        assert_eq!(distance(0b1011, 0b0001), 2);
+        assert_eq!(distance(0, u64::MAX), 64);
+    }
+}
@@ -0,0 +1,55 @@
+use std::path::Path;
+
+use super::{commit_partial, ffmpeg, partial_path, BakeOutcome};
+use crate::{errors::Result, site::FileCrawlType};
+
+/// Width of the placeholder in pixels. Browsers smooth it when stretching it
+/// over the thumbnail's box, which reads as a blurred preview.
+const WIDTH: u32 = 16;
+/// JPEG quality scale, 2 (best) to 31. Detail is lost to the scaling anyway.
+const QUALITY: &str = "10";
+
+/// Fingerprint of the settings used to make a placeholder.
+pub fn settings() -> String {
+    format!("placeholder w={} q={}", WIDTH, QUALITY)
+}
+
+/// Produce a tiny JPEG of an item's preview image, or of a frame a third of
+/// the way into its video, to show while the real thumbnail loads.
+pub fn create(work_dir_path: &Path, source: &FileCrawlType, output: &Path) -> Result<BakeOutcome> {
+    let Some(filename) = source.media_filename() else {
+        return Ok(BakeOutcome::skipped("not an image or video"));
+    };
+
+    let source_path = work_dir_path.join(filename);
+    if !source_path.exists() {
+        return Ok(BakeOutcome::skipped("source file missing"));
+    }
+
+    let mut command = ffmpeg::ffmpeg();
+    if source.is_video() {
+        if ffmpeg::is_audio_only(&source_path)? {
+            return Ok(BakeOutcome::not_needed("audio only"));
+        }
+        let length = ffmpeg::video_length(&source_path)?;
+        command.arg("-ss").arg(format!("{:.3}", length / 3.0));
+    }
+
+    let partial = partial_path(output);
+    ffmpeg::run(
+        "ffmpeg",
+        command
+            .arg("-i")
+            .arg(&source_path)
+            .arg("-frames:v")
+            .arg("1")
+            .arg("-vf")
+            .arg(format!("scale={}:-2:flags=area", WIDTH))
+            .arg("-q:v")
+            .arg(QUALITY)
+            .arg(&partial),
+    )?;
+    commit_partial(&partial, output)?;
+
+    Ok(BakeOutcome::created(work_dir_path, output))
+}
@@ -0,0 +1,623 @@
+use std::{fmt::Display, path::Path};
+
+use super::{
+    config::{BakeConfig, DeepZoomConfig, HlsConfig, ImageFormat},
+    manifest::{modified_secs, BakeManifest, ManifestEntry, SourceFingerprint},
+    deep_zoom, hls, placeholder, storyboard, thumbnail, transcode, variants, BakeOutcome,
+};
+use crate::{
+    collections::GetKey,
+    errors::Result,
+    site::{CrawlItem, FileCrawlType},
+};
+
+/// The kinds of artifact bake knows how to produce.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArtifactKind {
+    Thumbnail,
+    /// A resized copy of the item's preview image, for listing pages
+    PreviewVariant {
+        width: u32,
+        format: ImageFormat,
+    },
+    /// A resized copy of one of the item's images, for detail pages
+    DisplayVariant {
+        width: u32,
+        format: ImageFormat,
+    },
+    /// A tiny image shown in place of the thumbnail while it loads
+    Placeholder,
+    /// A sprite sheet and WebVTT track of frames from one of the item's videos
+    Storyboard,
+    /// A browser-playable mp4 of a video in a format browsers can't play
+    Transcode,
+    /// An adaptive HLS stream of a long video
+    Hls(HlsConfig),
+    /// A tile pyramid of a very large image, for pan and zoom
+    DeepZoom(DeepZoomConfig),
+}
+
+impl ArtifactKind {
+    /// Every directory bake owns, across all kinds.
+    pub const DIRECTORIES: &'static [&'static str] = &[
+        "auto_thumbnails",
+        "auto_display",
+        "auto_placeholders",
+        "storyboards",
+        "transcoded",
+        hls::DIRECTORY,
+        deep_zoom::DIRECTORY,
+    ];
+
+    /// The directory, relative to the work dir, that bake owns for this kind.
+    /// Everything inside it is either reachable from the current items or garbage.
+    pub fn directory(&self) -> &'static str {
+        match self {
+            ArtifactKind::Thumbnail | ArtifactKind::PreviewVariant { .. } => "auto_thumbnails",
+            ArtifactKind::DisplayVariant { .. } => "auto_display",
+            ArtifactKind::Placeholder => "auto_placeholders",
+            ArtifactKind::Storyboard => "storyboards",
+            ArtifactKind::Transcode => "transcoded",
+            ArtifactKind::Hls(_) => hls::DIRECTORY,
+            ArtifactKind::DeepZoom(_) => deep_zoom::DIRECTORY,
+        }
+    }
+
+    pub fn settings(&self, source: &FileCrawlType) -> String {
+        match self {
+            ArtifactKind::Thumbnail => thumbnail::settings(source),
+            ArtifactKind::PreviewVariant { width, format }
+            | ArtifactKind::DisplayVariant { width, format } => variants::settings(*width, *format),
+            ArtifactKind::Placeholder => placeholder::settings(),
+            ArtifactKind::Storyboard => storyboard::settings(),
+            ArtifactKind::Transcode => transcode::settings(),
+            ArtifactKind::Hls(config) => hls::settings(config),
+            ArtifactKind::DeepZoom(config) => deep_zoom::settings(config),
+        }
+    }
+
+    pub fn generate(
+        &self,
+        work_dir_path: &Path,
+        source: &FileCrawlType,
+        output: &Path,
+    ) -> Result<BakeOutcome> {
+        match self {
+            ArtifactKind::Thumbnail => thumbnail::create(work_dir_path, source, output),
+            ArtifactKind::PreviewVariant { width, format }
+            | ArtifactKind::DisplayVariant { width, format } => {
+                variants::create(work_dir_path, source, output, *width, *format)
+            }
+            ArtifactKind::Placeholder => placeholder::create(work_dir_path, source, output),
+            ArtifactKind::Storyboard => storyboard::create(work_dir_path, source, output),
+            ArtifactKind::Transcode => transcode::create(work_dir_path, source, output),
+            ArtifactKind::Hls(config) => hls::create(work_dir_path, source, output, config),
+            ArtifactKind::DeepZoom(config) => {
+                deep_zoom::create(work_dir_path, source, output, config)
+            }
+        }
+    }
+}
+
+impl Display for ArtifactKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArtifactKind::Thumbnail => write!(f, "thumbnail"),
+            ArtifactKind::PreviewVariant { width, format } => {
+                write!(f, "{}w {} preview", width, format.extension())
+            }
+            ArtifactKind::DisplayVariant { width, format } => {
+                write!(f, "{}w {} display image", width, format.extension())
+            }
+            ArtifactKind::Placeholder => write!(f, "placeholder"),
+            ArtifactKind::Storyboard => write!(f, "storyboard"),
+            ArtifactKind::Transcode => write!(f, "transcode"),
+            ArtifactKind::Hls(_) => write!(f, "HLS stream"),
+            ArtifactKind::DeepZoom(_) => write!(f, "deep zoom tiles"),
+        }
+    }
+}
+
+/// One artifact to produce from one source file.
+#[derive(Debug, Clone)]
+pub struct BakeJob {
+    pub item_key: String,
+    pub kind: ArtifactKind,
+    pub source: FileCrawlType,
+    /// Path of the source file, relative to the work dir
+    pub source_path: String,
+    /// Path of the artifact, relative to the work dir
+    pub output: String,
+}
+
+impl BakeJob {
+    pub fn settings(&self) -> String {
+        self.kind.settings(&self.source)
+    }
+
+    pub fn manifest_entry(&self, work_dir_path: &Path) -> Option<ManifestEntry> {
+        Some(ManifestEntry {
+            item_key: self.item_key.clone(),
+            source: SourceFingerprint::of(work_dir_path, &self.source_path)?,
+            settings: self.settings(),
+            not_needed: false,
+        })
+    }
+
+    /// Whether a manifest entry was made from the current source and settings.
+    fn matches(&self, entry: &ManifestEntry, work_dir_path: &Path) -> bool {
+        SourceFingerprint::of(work_dir_path, &self.source_path).as_ref() == Some(&entry.source)
+            && entry.settings == self.settings()
+    }
+
+    /// Compare the artifact on disk and its manifest entry against the
+    /// current source file and settings.
+    pub fn assess(&self, work_dir_path: &Path, manifest: &BakeManifest) -> JobState {
+        let Ok(output_metadata) = std::fs::metadata(work_dir_path.join(&self.output)) else {
+            return match manifest.artifacts.get(&self.output) {
+                Some(entry) if entry.not_needed && self.matches(entry, work_dir_path) => {
+                    JobState::UpToDate
+                }
+                _ => JobState::Missing,
+            };
+        };
+        let Some(source) = SourceFingerprint::of(work_dir_path, &self.source_path) else {
+            // Nothing to regenerate from; keep whatever we already have.
+            return JobState::UpToDate;
+        };
+
+        match manifest.artifacts.get(&self.output) {
+            Some(entry) => {
+                if entry.source.path != source.path {
+                    JobState::Stale(format!("source changed from {}", entry.source.path))
+                } else if entry.source != source {
+                    JobState::Stale("source file modified".to_string())
+                } else if entry.settings != self.settings() {
+                    JobState::Stale("settings changed".to_string())
+                } else {
+                    JobState::UpToDate
+                }
+            }
+            // Artifacts baked before the manifest existed are trusted as long
+            // as they are newer than their source.
+            None => match modified_secs(&output_metadata) {
+                Some(output_modified) if output_modified >= source.modified => JobState::Untracked,
+                _ => JobState::Stale("untracked and older than source".to_string()),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobState {
+    /// The artifact doesn't exist yet
+    Missing,
+    /// The artifact exists but was made from a different source or settings
+    Stale(String),
+    /// The artifact exists and looks current but has no manifest entry
+    Untracked,
+    UpToDate,
+}
+
+impl JobState {
+    pub fn needs_generation(&self) -> bool {
+        matches!(self, JobState::Missing | JobState::Stale(_))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum PlanEntry {
+    Job { job: Box<BakeJob>, state: JobState },
+    Skip { item_key: String, reason: String },
+}
+
+impl PlanEntry {
+    pub fn item_key(&self) -> &str {
+        match self {
+            PlanEntry::Job { job, .. } => &job.item_key,
+            PlanEntry::Skip { item_key, .. } => item_key,
+        }
+    }
+}
+
+/// Everything a bake run would do for one WorkDir.
+#[derive(Debug, Clone)]
+pub struct BakePlan {
+    pub site: String,
+    pub entries: Vec<PlanEntry>,
+}
+
+impl BakePlan {
+    pub fn build(
+        site: String,
+        work_dir_path: &Path,
+        items: &[&CrawlItem],
+        config: &BakeConfig,
+        manifest: &BakeManifest,
+    ) -> Self {
+        let mut entries = vec![];
+        for item in items {
+            match jobs_for_item(item, work_dir_path, config) {
+                Ok(jobs) => entries.extend(jobs.into_iter().map(|job| {
+                    let state = job.assess(work_dir_path, manifest);
+                    PlanEntry::Job {
+                        job: Box::new(job),
+                        state,
+                    }
+                })),
+                Err(reason) => entries.push(PlanEntry::Skip {
+                    item_key: item.key.clone(),
+                    reason,
+                }),
+            }
+        }
+
+        BakePlan { site, entries }
+    }
+
+    pub fn jobs(&self) -> impl Iterator<Item = (&BakeJob, &JobState)> {
+        self.entries.iter().filter_map(|entry| match entry {
+            PlanEntry::Job { job, state } => Some((job.as_ref(), state)),
+            PlanEntry::Skip { .. } => None,
+        })
+    }
+
+    pub fn print(&self) {
+        let mut to_create = 0;
+        let mut to_regenerate = 0;
+        let mut to_adopt = 0;
+        let mut up_to_date = 0;
+        let mut skipped = 0;
+
+        for entry in &self.entries {
+            match entry {
+                PlanEntry::Job { job, state } => match state {
+                    JobState::Missing => {
+                        to_create += 1;
+                        println!(
+                            "create      {} ({} of {})",
+                            job.output, job.kind, job.source_path
+                        );
+                    }
+                    JobState::Stale(reason) => {
+                        to_regenerate += 1;
+                        println!("regenerate  {} ({})", job.output, reason);
+                    }
+                    JobState::Untracked => {
+                        to_adopt += 1;
+                        println!("adopt       {}", job.output);
+                    }
+                    JobState::UpToDate => up_to_date += 1,
+                },
+                PlanEntry::Skip { .. } => skipped += 1,
+            }
+        }
+
+        println!(
+            "Plan for {}: {} to create, {} to regenerate, {} to adopt, {} up to date, {} items skipped",
+            self.site, to_create, to_regenerate, to_adopt, up_to_date, skipped
+        );
+    }
+}
+
+fn relative_to(work_dir_path: &Path, path: &Path) -> String {
+    path.strip_prefix(work_dir_path)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .to_string()
+}
+
+/// The job that generates an item's thumbnail, if it needs one. Explicit
+/// previews are used as they are; only items without one need a thumbnail.
+pub fn thumbnail_job(item: &CrawlItem, work_dir_path: &Path) -> Option<BakeJob> {
+    if !item.previews.is_empty() {
+        return None;
+    }
+    let file = item.first_thumbnailable_file()?;
+    let output = item.calculate_auto_thumbnail_path(work_dir_path, &file);
+    Some(BakeJob {
+        item_key: item.key.clone(),
+        kind: ArtifactKind::Thumbnail,
+        source_path: file.media_filename()?.to_string(),
+        source: file,
+        output: relative_to(work_dir_path, &output),
+    })
+}
+
+/// A job making `kind` from `source`, or `None` if the source isn't an image
+/// or video and so has no file bake could read.
+fn media_job(
+    item: &CrawlItem,
+    kind: ArtifactKind,
+    source: &FileCrawlType,
+    output: String,
+) -> Option<BakeJob> {
+    let Some(source_path) = source.media_filename() else {
+        log::warn!(
+            "Not baking {} for {}: {} isn't an image or video",
+            kind,
+            item.key,
+            source
+        );
+        return None;
+    };
+    Some(BakeJob {
+        item_key: item.key.clone(),
+        kind,
+        source: source.clone(),
+        source_path: source_path.to_string(),
+        output,
+    })
+}
+
+/// Work out which artifacts an item needs, or why it needs none.
+fn jobs_for_item(
+    item: &CrawlItem,
+    work_dir_path: &Path,
+    config: &BakeConfig,
+) -> std::result::Result<Vec<BakeJob>, String> {
+    let mut jobs = thumbnail_job(item, work_dir_path).into_iter().collect::<Vec<_>>();
+    let mut push = |kind: ArtifactKind, source: &FileCrawlType, output: String| {
+        jobs.extend(media_job(item, kind, source, output))
+    };
+
+    if let Some(source) = item
+        .preview_source_image()
+        .or_else(|| item.first_thumbnailable_file())
+    {
+        let output = item.placeholder_path(work_dir_path);
+        push(
+            ArtifactKind::Placeholder,
+            &source,
+            relative_to(work_dir_path, &output),
+        );
+    }
+
+    if let Some(preview) = item.preview_source_image() {
+        for &format in &config.formats {
+            for &width in &config.preview_widths {
+                let output = item.preview_variant_path(work_dir_path, width, format);
+                push(
+                    ArtifactKind::PreviewVariant { width, format },
+                    &preview,
+                    relative_to(work_dir_path, &output),
+                );
+            }
+        }
+    }
+
+    for file in item.flat_files().values() {
+        if file.is_video() && file.is_downloaded() {
+            let output = item.transcoded_video_path(work_dir_path, file.get_key());
+            push(
+                ArtifactKind::Transcode,
+                file,
+                relative_to(work_dir_path, &output),
+            );
+            let output = item.storyboard_path(work_dir_path, file.get_key());
+            push(
+                ArtifactKind::Storyboard,
+                file,
+                relative_to(work_dir_path, &output),
+            );
+            if let Some(hls) = &config.hls {
+                let output = item.hls_path(work_dir_path, file.get_key());
+                push(
+                    ArtifactKind::Hls(hls.clone()),
+                    file,
+                    relative_to(work_dir_path, &output),
+                );
+            }
+        }
+        if !(file.is_image() && file.is_downloaded()) {
+            continue;
+        }
+        if let Some(deep_zoom) = &config.deep_zoom {
+            let output = item.deep_zoom_path(work_dir_path, file.get_key());
+            push(
+                ArtifactKind::DeepZoom(deep_zoom.clone()),
+                file,
+                relative_to(work_dir_path, &output),
+            );
+        }
+        for &format in &config.formats {
+            for &width in &config.display_widths {
+                let output =
+                    item.display_variant_path(work_dir_path, file.get_key(), width, format);
+                push(
+                    ArtifactKind::DisplayVariant { width, format },
+                    file,
+                    relative_to(work_dir_path, &output),
+                );
+            }
+        }
+    }
+
+    if jobs.is_empty() {
+        return Err("no usable files".to_string());
+    }
+    Ok(jobs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bake::test_dir;
+    use std::time::{Duration, SystemTime};
+
+    fn image(key: &str, downloaded: bool) -> FileCrawlType {
+        FileCrawlType::Image {
+            key: key.to_string(),
+            filename: format!("{}.jpg", key),
+            downloaded,
+            url: String::new(),
+        }
+    }
+
+    fn item_with(files: &[FileCrawlType]) -> CrawlItem {
+        let mut item = CrawlItem::for_test("item", "item", &[]);
+        for file in files {
+            item.files.insert(file.get_key().to_string(), file.clone());
+        }
+        item
+    }
+
+    #[test]
+    fn test_variant_jobs() {
+        let mut item = item_with(&[image("a", true), image("b", false)]);
+        item.previews.insert("p".to_string(), image("p", true));
+        let config = BakeConfig {
+            preview_widths: vec![320],
+            display_widths: vec![1280, 1920],
+            formats: vec![ImageFormat::Webp, ImageFormat::Jpeg],
+            ..Default::default()
+        };
+
+        let jobs = jobs_for_item(&item, Path::new(""), &config).unwrap();
+        let planned = jobs
+            .iter()
+            .map(|job| format!("{} of {}", job.kind, job.source_path))
+            .collect::<Vec<_>>();
+        // No thumbnail, since the site's preview serves as one
+        assert_eq!(
+            planned,
+            vec![
+                "placeholder of p.jpg",
+                "320w webp preview of p.jpg",
+                "320w jpg preview of p.jpg",
+                "1280w webp display image of a.jpg",
+                "1920w webp display image of a.jpg",
+                "1280w jpg display image of a.jpg",
+                "1920w jpg display image of a.jpg",
+            ]
+        );
+        let mut outputs = jobs.iter().map(|job| &job.output).collect::<Vec<_>>();
+        outputs.sort();
+        outputs.dedup();
+        assert_eq!(outputs.len(), jobs.len());
+
+        // Without a preview, the first image is thumbnailed and resized
+        item.previews.clear();
+        let jobs = jobs_for_item(&item, Path::new(""), &config).unwrap();
+        assert_eq!(jobs[0].kind, ArtifactKind::Thumbnail);
+        assert!(jobs.iter().all(|job| job.source_path == "a.jpg"));
+
+        // Without a downloaded file there is nothing to bake
+        let item = item_with(&[image("b", false)]);
+        assert_eq!(
+            jobs_for_item(&item, Path::new(""), &config).unwrap_err(),
+            "no usable files"
+        );
+    }
+
+    #[test]
+    fn test_only_media_is_baked() {
+        let text = FileCrawlType::Text {
+            key: "notes".to_string(),
+            content: "hello".to_string(),
+        };
+        let archive = FileCrawlType::Intermediate {
+            key: "zip".to_string(),
+            filename: "a.zip".to_string(),
+            downloaded: false,
+            postprocessing_errors: false,
+            url: String::new(),
+            nested: Default::default(),
+        };
+        let item = item_with(&[text.clone(), archive.clone()]);
+        for source in [&text, &archive] {
+            let output = "out.jpg".to_string();
+            let job = media_job(&item, ArtifactKind::Thumbnail, source, output);
+            assert!(job.is_none(), "{}", source);
+        }
+        assert_eq!(
+            jobs_for_item(&item, Path::new(""), &BakeConfig::default()).unwrap_err(),
+            "no usable files"
+        );
+
+        // Alongside an image, only the image is planned for
+        let item = item_with(&[text, image("a", true), archive]);
+        let jobs = jobs_for_item(&item, Path::new(""), &BakeConfig::default()).unwrap();
+        assert!(jobs.iter().all(|job| job.source_path == "a.jpg"));
+    }
+
+    fn set_modified(path: &Path, time: SystemTime) {
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(time)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_assess() {
+        let dir = test_dir("assess");
+        let item = item_with(&[image("a", true)]);
+        let job = thumbnail_job(&item, &dir).unwrap();
+        let (source, output) = (dir.join("a.jpg"), dir.join(&job.output));
+        let sourced_at = SystemTime::now() - Duration::from_secs(3600);
+        std::fs::write(&source, b"source").unwrap();
+        set_modified(&source, sourced_at);
+        let mut manifest = BakeManifest::default();
+        let assess = |manifest: &BakeManifest| job.assess(&dir, manifest);
+
+        assert_eq!(assess(&manifest), JobState::Missing);
+
+        // Nothing on disk is fine when the generator said it wasn't needed,
+        // as long as that was decided for this source and these settings
+        let mut entry = job.manifest_entry(&dir).unwrap();
+        entry.not_needed = true;
+        manifest.record(job.output.clone(), entry.clone());
+        assert_eq!(assess(&manifest), JobState::UpToDate);
+        entry.settings = "old settings".to_string();
+        manifest.record(job.output.clone(), entry);
+        assert_eq!(assess(&manifest), JobState::Missing);
+
+        // Untracked artifacts are adopted only if newer than their source
+        let mut manifest = BakeManifest::default();
+        std::fs::create_dir_all(output.parent().unwrap()).unwrap();
+        std::fs::write(&output, b"thumbnail").unwrap();
+        set_modified(&output, sourced_at + Duration::from_secs(60));
+        assert_eq!(assess(&manifest), JobState::Untracked);
+        set_modified(&output, sourced_at - Duration::from_secs(60));
+        assert_eq!(
+            assess(&manifest),
+            JobState::Stale("untracked and older than source".to_string())
+        );
+
+        let entry = job.manifest_entry(&dir).unwrap();
+        manifest.record(job.output.clone(), entry.clone());
+        assert_eq!(assess(&manifest), JobState::UpToDate);
+
+        let mut moved = entry.clone();
+        moved.source.path = "b.jpg".to_string();
+        manifest.record(job.output.clone(), moved);
+        assert_eq!(
+            assess(&manifest),
+            JobState::Stale("source changed from b.jpg".to_string())
+        );
+
+        let mut modified = entry.clone();
+        modified.source.modified -= 60;
+        manifest.record(job.output.clone(), modified);
+        assert_eq!(
+            assess(&manifest),
+            JobState::Stale("source file modified".to_string())
+        );
+
+        let mut resettled = entry.clone();
+        resettled.settings = "old settings".to_string();
+        manifest.record(job.output.clone(), resettled);
+        assert_eq!(
+            assess(&manifest),
+            JobState::Stale("settings changed".to_string())
+        );
+
+        // With the source gone there is nothing to regenerate from
+        std::fs::remove_file(&source).unwrap();
+        assert_eq!(assess(&manifest), JobState::UpToDate);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+/// Number of workers to use when the caller didn't ask for a specific amount.
+pub fn default_jobs() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+/// Prints one line per finished task with a running count and an ETA.
+pub struct Progress {
+    total: usize,
+    done: AtomicUsize,
+    started: Instant,
+}
+
+impl Progress {
+    pub fn new(total: usize) -> Self {
+        Progress {
+            total,
+            done: AtomicUsize::new(0),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn tick(&self, label: &str, message: &dyn std::fmt::Display) {
+        let done = self.done.fetch_add(1, Ordering::SeqCst) + 1;
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let remaining = if done < self.total {
+            let per_task = elapsed / done as f64;
+            format!(" eta {}s", (per_task * (self.total - done) as f64).round())
+        } else {
+            String::new()
+        };
+        let width = self.total.to_string().len();
+
+        println!(
+            "[{:>width$}/{}{}] {}: {}",
+            done,
+            self.total,
+            remaining,
+            label,
+            message,
+            width = width
+        );
+    }
+}
+
+/// Run `work` over every task on `jobs` worker threads, returning the results
+/// in the same order as the tasks.
+pub fn run<T, R, F>(tasks: &[T], jobs: usize, work: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new((0..tasks.len()).map(|_| None).collect());
+
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, tasks.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some(task) = tasks.get(index) else {
+                    break;
+                };
+                let result = work(task);
+                results.lock().expect("bake results poisoned")[index] = Some(result);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .expect("bake results poisoned")
+        .into_iter()
+        .map(|result| result.expect("every bake task produces a result"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_results_keep_task_order() {
+        // Earlier tasks take longer, so they finish after later ones
+        let tasks: Vec<u64> = (0..8).collect();
+        let results = run(&tasks, 4, |&n| {
+            std::thread::sleep(Duration::from_millis((8 - n) * 5));
+            n * 10
+        });
+        assert_eq!(results, [0, 10, 20, 30, 40, 50, 60, 70]);
+    }
+
+    #[test]
+    fn test_errors_stay_with_their_tasks() {
+        let tasks = ["1", "x", "3", "", "5"];
+        let results = run(&tasks, 3, |task| task.parse::<u32>());
+        let (ok, err): (Vec<_>, Vec<_>) = results.iter().enumerate().partition(|(_, r)| r.is_ok());
+        assert_eq!(
+            ok.into_iter().map(|(i, _)| i).collect::<Vec<_>>(),
+            [0, 2, 4]
+        );
+        assert_eq!(err.into_iter().map(|(i, _)| i).collect::<Vec<_>>(), [1, 3]);
+        assert_eq!(results[2], Ok(3));
+    }
+
+    #[test]
+    fn test_worker_counts() {
+        let tasks = [1, 2, 3];
+        // Zero workers still runs everything, and more workers than tasks is fine
+        assert_eq!(run(&tasks, 0, |n| n + 1), [2, 3, 4]);
+        assert_eq!(run(&tasks, 16, |n| n + 1), [2, 3, 4]);
+        assert!(run(&[] as &[u32], 4, |n| *n).is_empty());
+    }
+}
@@ -0,0 +1,199 @@
+use std::{collections::BTreeMap, fmt::Display, path::Path};
+
+use serde::Serialize;
+
+/// What happened to a single item during a bake run.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum BakeOutcome {
+    Created { path: String },
+    Skipped { reason: String },
+    /// The generator looked at the source and decided the artifact isn't
+    /// needed, e.g. a video that browsers can already play
+    NotNeeded { reason: String },
+    Failed { reason: String },
+    /// The artifact was already on disk with no manifest entry, and looked
+    /// current, so it was recorded rather than regenerated
+    Adopted { path: String },
+}
+
+impl BakeOutcome {
+    pub fn created(work_dir_path: &Path, output: &Path) -> Self {
+        BakeOutcome::Created {
+            path: output
+                .strip_prefix(work_dir_path)
+                .unwrap_or(output)
+                .to_string_lossy()
+                .to_string(),
+        }
+    }
+
+    pub fn skipped(reason: impl Into<String>) -> Self {
+        BakeOutcome::Skipped {
+            reason: reason.into(),
+        }
+    }
+
+    pub fn not_needed(reason: impl Into<String>) -> Self {
+        BakeOutcome::NotNeeded {
+            reason: reason.into(),
+        }
+    }
+
+    pub fn failed(reason: impl Display) -> Self {
+        BakeOutcome::Failed {
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl Display for BakeOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BakeOutcome::Created { path } => write!(f, "created {}", path),
+            BakeOutcome::Skipped { reason } => write!(f, "skipped ({})", reason),
+            BakeOutcome::NotNeeded { reason } => write!(f, "not needed ({})", reason),
+            BakeOutcome::Failed { reason } => write!(f, "FAILED: {}", reason),
+            BakeOutcome::Adopted { path } => write!(f, "adopted {}", path),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BakeEntry {
+    pub item_key: String,
+    #[serde(flatten)]
+    pub outcome: BakeOutcome,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BakeSummary {
+    pub created: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    /// Untracked artifacts recorded in the manifest as they were
+    pub adopted: usize,
+    /// Files whose metadata was (re)extracted
+    pub metadata_extracted: usize,
+    pub skipped_by_reason: BTreeMap<String, usize>,
+}
+
+/// The structured result of baking one WorkDir.
+#[derive(Debug, Clone, Serialize)]
+pub struct BakeReport {
+    pub site: String,
+    pub summary: BakeSummary,
+    pub entries: Vec<BakeEntry>,
+}
+
+impl BakeReport {
+    pub fn new(site: String, entries: Vec<BakeEntry>) -> Self {
+        let mut summary = BakeSummary::default();
+        for entry in &entries {
+            match entry.outcome {
+                BakeOutcome::Created { .. } => summary.created += 1,
+                BakeOutcome::Skipped { ref reason } | BakeOutcome::NotNeeded { ref reason } => {
+                    summary.skipped += 1;
+                    *summary.skipped_by_reason.entry(reason.clone()).or_insert(0) += 1;
+                }
+                BakeOutcome::Failed { .. } => summary.failed += 1,
+                BakeOutcome::Adopted { .. } => summary.adopted += 1,
+            }
+        }
+
+        BakeReport {
+            site,
+            summary,
+            entries,
+        }
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &BakeEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| matches!(entry.outcome, BakeOutcome::Failed { .. }))
+    }
+
+    pub fn print_summary(&self) {
+        println!(
+            "Baked {}: {} created, {} adopted, {} skipped, {} failed, metadata extracted for {} files",
+            self.site,
+            self.summary.created,
+            self.summary.adopted,
+            self.summary.skipped,
+            self.summary.failed,
+            self.summary.metadata_extracted
+        );
+        for (reason, count) in &self.summary.skipped_by_reason {
+            println!("  skipped {}: {}", reason, count);
+        }
+        for entry in self.failures() {
+            println!("  {}: {}", entry.item_key, entry.outcome);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(item_key: &str, outcome: BakeOutcome) -> BakeEntry {
+        BakeEntry {
+            item_key: item_key.to_string(),
+            outcome,
+        }
+    }
+
+    #[test]
+    fn test_summary_counts() {
+        let report = BakeReport::new(
+            "site".to_string(),
+            vec![
+                entry(
+                    "a",
+                    BakeOutcome::created(Path::new("/wd"), Path::new("/wd/thumbnails/a.jpg")),
+                ),
+                entry("b", BakeOutcome::skipped("up to date")),
+                entry("c", BakeOutcome::skipped("up to date")),
+                entry("d", BakeOutcome::not_needed("already browser-playable")),
+                entry("e", BakeOutcome::failed("ffmpeg exited with 1")),
+                entry(
+                    "g",
+                    BakeOutcome::Adopted {
+                        path: "thumbnails/g.jpg".to_string(),
+                    },
+                ),
+                entry(
+                    "f",
+                    BakeOutcome::created(Path::new("/wd"), Path::new("/elsewhere/f.jpg")),
+                ),
+            ],
+        );
+
+        assert_eq!(report.summary.created, 2);
+        assert_eq!(report.summary.skipped, 3);
+        assert_eq!(report.summary.failed, 1);
+        assert_eq!(report.summary.adopted, 1);
+        assert_eq!(report.summary.skipped_by_reason["up to date"], 2);
+        assert_eq!(
+            report.summary.skipped_by_reason["already browser-playable"],
+            1
+        );
+        assert_eq!(
+            report
+                .failures()
+                .map(|e| e.item_key.as_str())
+                .collect::<Vec<_>>(),
+            ["e"]
+        );
+        assert_eq!(
+            report.entries[0].outcome.to_string(),
+            "created thumbnails/a.jpg"
+        );
+        // Outputs outside the work dir keep their full path
+        assert_eq!(
+            report.entries[6].outcome.to_string(),
+            "created /elsewhere/f.jpg"
+        );
+    }
+}
@@ -0,0 +1,298 @@
+//! Thumbnail generation while serving, for items bake hasn't reached yet.
+//!
+//! Jobs are the same ones `bake` would run, and write to the same paths, so a
+//! later bake run finds the thumbnails already there and adopts them into its
+//! manifest. Nothing is recorded in the manifest here, to avoid racing a bake
+//! run over it.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{plan::thumbnail_job, run_job, BakeJob, BakeOutcome};
+use crate::site::CrawlItem;
+
+/// Pause after each job, so a burst of new items doesn't keep ffmpeg
+/// competing with request handling for the whole time it takes to catch up.
+const PAUSE_BETWEEN_JOBS: Duration = Duration::from_millis(250);
+
+/// How long an output that couldn't be generated is left alone before a
+/// page asking for it queues it again. Its source may have been missing or
+/// ffmpeg may have failed only for the moment.
+const RETRY_AFTER: Duration = Duration::from_secs(10 * 60);
+
+/// Whether an item's thumbnail can be shown yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThumbnailStatus {
+    Ready,
+    /// Queued or being generated
+    Pending,
+    /// The item has nothing to make a thumbnail from, generation failed
+    /// recently, or the service is disabled
+    Unavailable,
+}
+
+struct QueuedJob {
+    work_dir_path: PathBuf,
+    job: BakeJob,
+}
+
+impl QueuedJob {
+    fn output(&self) -> PathBuf {
+        self.work_dir_path.join(&self.job.output)
+    }
+}
+
+#[derive(Default)]
+struct QueueState {
+    queue: VecDeque<QueuedJob>,
+    /// Outputs queued or in progress, so repeated requests don't pile up
+    pending: HashSet<PathBuf>,
+    /// Outputs that couldn't be generated, and when. Retried once
+    /// `RETRY_AFTER` has passed; bake reports why they failed.
+    failed: HashMap<PathBuf, Instant>,
+}
+
+impl QueueState {
+    /// Queue a job unless it is already queued or failed too recently. An
+    /// urgent job goes to the front, moving there if it was already queued.
+    fn push(&mut self, queued: QueuedJob, urgent: bool, now: Instant) -> ThumbnailStatus {
+        let output = queued.output();
+        if let Some(failed_at) = self.failed.get(&output) {
+            if now.duration_since(*failed_at) < RETRY_AFTER {
+                return ThumbnailStatus::Unavailable;
+            }
+            self.failed.remove(&output);
+        }
+        if self.pending.contains(&output) {
+            if urgent {
+                if let Some(position) = self.queue.iter().position(|q| q.output() == output) {
+                    let queued = self.queue.remove(position).unwrap();
+                    self.queue.push_front(queued);
+                }
+            }
+            return ThumbnailStatus::Pending;
+        }
+
+        self.pending.insert(output);
+        if urgent {
+            self.queue.push_front(queued);
+        } else {
+            self.queue.push_back(queued);
+        }
+        ThumbnailStatus::Pending
+    }
+
+    /// Note that the job for `output` is done, and whether it made anything.
+    fn finish(&mut self, output: PathBuf, created: bool, now: Instant) {
+        self.pending.remove(&output);
+        if !created {
+            self.failed.insert(output, now);
+        }
+    }
+}
+
+struct Shared {
+    state: Mutex<QueueState>,
+    available: Condvar,
+}
+
+/// A queue of thumbnail jobs worked through by a fixed number of background
+/// threads. Cheap to clone; clones share the queue.
+#[derive(Clone)]
+pub struct ThumbnailService {
+    shared: Option<Arc<Shared>>,
+}
+
+impl ThumbnailService {
+    /// Start `workers` threads. With no workers the service is disabled and
+    /// every missing thumbnail is reported as unavailable.
+    pub fn new(workers: usize) -> Self {
+        if workers == 0 {
+            return ThumbnailService { shared: None };
+        }
+
+        let shared = Arc::new(Shared {
+            state: Mutex::new(QueueState::default()),
+            available: Condvar::new(),
+        });
+        for _ in 0..workers {
+            let shared = shared.clone();
+            thread::spawn(move || work(&shared));
+        }
+        ThumbnailService {
+            shared: Some(shared),
+        }
+    }
+
+    /// Check on an item's thumbnail for a page that is showing it, moving its
+    /// job to the front of the queue if it still needs one.
+    pub fn request(&self, item: &CrawlItem) -> ThumbnailStatus {
+        if item.thumbnail_path().is_some() {
+            return ThumbnailStatus::Ready;
+        }
+        let Some(work_dir_path) = item.site_settings.work_dir_path.as_ref() else {
+            return ThumbnailStatus::Unavailable;
+        };
+        self.enqueue(work_dir_path, item, true)
+    }
+
+    /// Queue thumbnails for items that just appeared, behind anything a page
+    /// is waiting on.
+    pub fn queue_items<'a>(
+        &self,
+        work_dir_path: &Path,
+        items: impl IntoIterator<Item = &'a CrawlItem>,
+    ) {
+        for item in items {
+            self.enqueue(work_dir_path, item, false);
+        }
+    }
+
+    fn enqueue(&self, work_dir_path: &Path, item: &CrawlItem, urgent: bool) -> ThumbnailStatus {
+        let Some(shared) = &self.shared else {
+            return ThumbnailStatus::Unavailable;
+        };
+        let Some(job) = thumbnail_job(item, work_dir_path) else {
+            return ThumbnailStatus::Unavailable;
+        };
+        if work_dir_path.join(&job.output).exists() {
+            return ThumbnailStatus::Ready;
+        }
+
+        let queued = QueuedJob {
+            work_dir_path: work_dir_path.to_path_buf(),
+            job,
+        };
+        let mut state = shared.state.lock().expect("thumbnail queue poisoned");
+        let status = state.push(queued, urgent, Instant::now());
+        if status == ThumbnailStatus::Pending {
+            shared.available.notify_one();
+        }
+        status
+    }
+}
+
+fn work(shared: &Shared) {
+    loop {
+        let queued = {
+            let mut state = shared.state.lock().expect("thumbnail queue poisoned");
+            loop {
+                match state.queue.pop_front() {
+                    Some(queued) => break queued,
+                    None => {
+                        state = shared
+                            .available
+                            .wait(state)
+                            .expect("thumbnail queue poisoned")
+                    }
+                }
+            }
+        };
+
+        let outcome = run_job(&queued.work_dir_path, &queued.job);
+        if let BakeOutcome::Failed { .. } = outcome {
+            log::warn!("Thumbnail for {}: {}", queued.job.item_key, outcome);
+        }
+        shared
+            .state
+            .lock()
+            .expect("thumbnail queue poisoned")
+            .finish(
+                queued.output(),
+                matches!(outcome, BakeOutcome::Created { .. }),
+                Instant::now(),
+            );
+
+        thread::sleep(PAUSE_BETWEEN_JOBS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bake::ArtifactKind;
+    use crate::site::FileCrawlType;
+
+    fn queued(key: &str) -> QueuedJob {
+        QueuedJob {
+            work_dir_path: PathBuf::from("/wd"),
+            job: BakeJob {
+                item_key: key.to_string(),
+                kind: ArtifactKind::Thumbnail,
+                source: FileCrawlType::Image {
+                    key: "f1".to_string(),
+                    filename: format!("{key}.jpg"),
+                    downloaded: true,
+                    url: String::new(),
+                },
+                source_path: format!("{key}.jpg"),
+                output: format!("thumbnails/{key}.jpg"),
+            },
+        }
+    }
+
+    fn order(state: &QueueState) -> Vec<&str> {
+        state
+            .queue
+            .iter()
+            .map(|q| q.job.item_key.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_queue_order() {
+        let now = Instant::now();
+        let mut state = QueueState::default();
+        for key in ["a", "b", "c"] {
+            assert_eq!(
+                state.push(queued(key), false, now),
+                ThumbnailStatus::Pending
+            );
+        }
+        // Asking again doesn't queue a second copy
+        assert_eq!(
+            state.push(queued("a"), false, now),
+            ThumbnailStatus::Pending
+        );
+        assert_eq!(order(&state), ["a", "b", "c"]);
+
+        // Urgent jobs jump the queue, whether new or already waiting
+        state.push(queued("d"), true, now);
+        assert_eq!(order(&state), ["d", "a", "b", "c"]);
+        state.push(queued("c"), true, now);
+        assert_eq!(order(&state), ["c", "d", "a", "b"]);
+        assert_eq!(state.pending.len(), 4);
+    }
+
+    #[test]
+    fn test_failed_outputs_are_retried_later() {
+        let now = Instant::now();
+        let mut state = QueueState::default();
+        state.push(queued("a"), false, now);
+        state.push(queued("b"), false, now);
+        let a = state.queue.pop_front().unwrap();
+        let b = state.queue.pop_front().unwrap();
+        state.finish(a.output(), false, now);
+        state.finish(b.output(), true, now);
+        assert!(state.pending.is_empty());
+
+        assert_eq!(
+            state.push(queued("a"), true, now),
+            ThumbnailStatus::Unavailable
+        );
+        assert!(state.queue.is_empty());
+
+        let later = now + RETRY_AFTER;
+        assert_eq!(
+            state.push(queued("a"), false, later),
+            ThumbnailStatus::Pending
+        );
+        assert_eq!(order(&state), ["a"]);
+        assert!(state.failed.is_empty());
+    }
+}
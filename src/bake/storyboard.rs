@@ -0,0 +1,149 @@
+use std::{fmt::Write, path::Path};
+
+use super::{commit_partial_dir, ffmpeg, partial_dir_path, BakeOutcome};
+use crate::{errors::Result, site::FileCrawlType};
+
+pub const SPRITE_FILENAME: &str = "sprite.jpg";
+pub const VTT_FILENAME: &str = "storyboard.vtt";
+
+/// Width of each frame in the sprite sheet, in pixels
+const TILE_WIDTH: u32 = 160;
+const TILE_COLUMNS: u32 = 10;
+const MAX_FRAMES: u32 = 100;
+/// Short videos get one frame per this many seconds rather than `MAX_FRAMES`
+const MIN_INTERVAL_SECONDS: f64 = 2.0;
+const SPRITE_QUALITY: &str = "5";
+
+/// Fingerprint of the settings used to generate a storyboard.
+pub fn settings() -> String {
+    format!(
+        "storyboard tile={} cols={} max={} every>={}s q={}",
+        TILE_WIDTH, TILE_COLUMNS, MAX_FRAMES, MIN_INTERVAL_SECONDS, SPRITE_QUALITY
+    )
+}
+
+/// Produce a sprite sheet of evenly spaced frames from a video, plus a WebVTT
+/// track mapping each time range to its tile. `output` is a directory holding
+/// both files.
+pub fn create(work_dir_path: &Path, source: &FileCrawlType, output: &Path) -> Result<BakeOutcome> {
+    let FileCrawlType::Video { filename, .. } = source else {
+        return Ok(BakeOutcome::skipped("not a video"));
+    };
+
+    let video_path = work_dir_path.join(filename);
+    if !video_path.exists() {
+        return Ok(BakeOutcome::skipped("source file missing"));
+    }
+
+    if ffmpeg::is_audio_only(&video_path)? {
+        return Ok(BakeOutcome::not_needed("audio only"));
+    }
+
+    let length = ffmpeg::video_length(&video_path)?;
+    if length <= 0.0 {
+        return Ok(BakeOutcome::skipped("video has no duration"));
+    }
+    let (width, height) = ffmpeg::dimensions(&video_path)?;
+    let tile_height = tile_height(width, height);
+
+    let frames = ((length / MIN_INTERVAL_SECONDS).floor() as u32).clamp(1, MAX_FRAMES);
+    let interval = length / frames as f64;
+    let columns = frames.min(TILE_COLUMNS);
+    let rows = frames.div_ceil(columns);
+
+    // The whole directory is one artifact, so it is assembled next to its
+    // final location and moved into place in one go.
+    let partial = partial_dir_path(output);
+    if partial.exists() {
+        std::fs::remove_dir_all(&partial)?;
+    }
+    std::fs::create_dir_all(&partial)?;
+
+    ffmpeg::run(
+        "ffmpeg",
+        ffmpeg::ffmpeg()
+            .arg("-i")
+            .arg(&video_path)
+            .arg("-an")
+            .arg("-vf")
+            .arg(format!(
+                "fps=1/{:.3},scale={}:{},tile={}x{}",
+                interval, TILE_WIDTH, tile_height, columns, rows
+            ))
+            .arg("-frames:v")
+            .arg("1")
+            .arg("-q:v")
+            .arg(SPRITE_QUALITY)
+            .arg(partial.join(SPRITE_FILENAME)),
+    )?;
+
+    std::fs::write(
+        partial.join(VTT_FILENAME),
+        vtt(frames, interval, columns, tile_height),
+    )?;
+
+    commit_partial_dir(&partial, output)?;
+
+    Ok(BakeOutcome::created(work_dir_path, output))
+}
+
+/// Height of a tile that keeps the video's aspect ratio, rounded to an even
+/// number as the encoder requires.
+fn tile_height(width: u32, height: u32) -> u32 {
+    let scaled = (height as f64 * TILE_WIDTH as f64 / width.max(1) as f64).round() as u32;
+    (scaled / 2 * 2).max(2)
+}
+
+/// A WebVTT thumbnails track with one cue per tile, each pointing at its
+/// region of the sprite sheet with a media fragment.
+fn vtt(frames: u32, interval: f64, columns: u32, tile_height: u32) -> String {
+    let mut vtt = String::from("WEBVTT\n");
+    for frame in 0..frames {
+        let x = (frame % columns) * TILE_WIDTH;
+        let y = (frame / columns) * tile_height;
+        let _ = write!(
+            vtt,
+            "\n{} --> {}\n{}#xywh={},{},{},{}\n",
+            vtt_timestamp(frame as f64 * interval),
+            vtt_timestamp((frame + 1) as f64 * interval),
+            SPRITE_FILENAME,
+            x,
+            y,
+            TILE_WIDTH,
+            tile_height
+        );
+    }
+    vtt
+}
+
+fn vtt_timestamp(seconds: f64) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vtt_timestamp() {
+        assert_eq!(vtt_timestamp(0.0), "00:00:00.000");
+        assert_eq!(vtt_timestamp(61.5), "00:01:01.500");
+        assert_eq!(vtt_timestamp(3723.004), "01:02:03.004");
+    }
+
+    #[test]
+    fn test_vtt_cues_walk_the_sprite_grid() {
+        let vtt = vtt(12, 2.5, 10, 90);
+        assert!(vtt.starts_with("WEBVTT\n"));
+        assert!(vtt.contains("00:00:00.000 --> 00:00:02.500\nsprite.jpg#xywh=0,0,160,90\n"));
+        assert!(vtt.contains("00:00:25.000 --> 00:00:27.500\nsprite.jpg#xywh=0,90,160,90\n"));
+        assert_eq!(vtt.matches("-->").count(), 12);
+    }
+}
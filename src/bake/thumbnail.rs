@@ -0,0 +1,90 @@
+use std::{cmp::min, path::Path};
+
+use super::{commit_partial, ffmpeg, partial_path, BakeOutcome};
+use crate::{errors::Result, site::FileCrawlType};
+
+const IMAGE_FILTER: &str = "scale=320:-1";
+const VIDEO_FILTER: &str = "scale=320:-2,fps=15";
+const VIDEO_CRF: &str = "28";
+/// Upper bound on the length of a video thumbnail clip, in seconds
+const VIDEO_CLIP_SECONDS: u64 = 3;
+
+/// Fingerprint of the settings used to generate a thumbnail of `source`.
+pub fn settings(source: &FileCrawlType) -> String {
+    match source {
+        FileCrawlType::Video { .. } => format!(
+            "thumbnail/video vf={} crf={} clip={}s@1/3",
+            VIDEO_FILTER, VIDEO_CRF, VIDEO_CLIP_SECONDS
+        ),
+        _ => format!("thumbnail/image vf={}", IMAGE_FILTER),
+    }
+}
+
+pub fn create(work_dir_path: &Path, source: &FileCrawlType, output: &Path) -> Result<BakeOutcome> {
+    let partial = partial_path(output);
+
+    match source {
+        FileCrawlType::Video { filename, .. } => {
+            let video_path = work_dir_path.join(filename);
+            if !video_path.exists() {
+                return Ok(BakeOutcome::skipped("source file missing"));
+            }
+
+            if ffmpeg::is_audio_only(&video_path)? {
+                return Ok(BakeOutcome::not_needed("audio only"));
+            }
+
+            let length = ffmpeg::video_length(&video_path)?;
+            let offset = (length / 3.0).round() as u64;
+            let duration = min(offset, VIDEO_CLIP_SECONDS);
+
+            ffmpeg::run(
+                "ffmpeg",
+                ffmpeg::ffmpeg()
+                    .arg("-ss")
+                    .arg(offset.to_string())
+                    .arg("-t")
+                    .arg(duration.to_string())
+                    .arg("-i")
+                    .arg(&video_path)
+                    .arg("-vf")
+                    .arg(VIDEO_FILTER)
+                    .arg("-c:v")
+                    .arg("libx264")
+                    .arg("-preset")
+                    .arg("slow")
+                    .arg("-crf")
+                    .arg(VIDEO_CRF)
+                    .arg("-an")
+                    .arg("-movflags")
+                    .arg("+faststart")
+                    .arg(&partial),
+            )?;
+        }
+
+        FileCrawlType::Image { filename, .. } => {
+            let image_path = work_dir_path.join(filename);
+            if !image_path.exists() {
+                return Ok(BakeOutcome::skipped("source file missing"));
+            }
+
+            ffmpeg::run(
+                "ffmpeg",
+                ffmpeg::ffmpeg()
+                    .arg("-i")
+                    .arg(&image_path)
+                    .arg("-vf")
+                    .arg(IMAGE_FILTER)
+                    .arg(&partial),
+            )?;
+        }
+
+        _ => {
+            return Ok(BakeOutcome::skipped("not an image or video"));
+        }
+    }
+
+    commit_partial(&partial, output)?;
+
+    Ok(BakeOutcome::created(work_dir_path, output))
+}
@@ -0,0 +1,153 @@
+use std::path::Path;
+
+use super::{
+    commit_partial,
+    ffmpeg::{self, CodecProbe},
+    partial_path, BakeOutcome,
+};
+use crate::{errors::Result, site::FileCrawlType};
+
+/// Video codecs every current browser can decode
+const WEB_VIDEO_CODECS: &[&str] = &["h264", "vp8", "vp9", "av1"];
+/// Audio codecs every current browser can decode
+const WEB_AUDIO_CODECS: &[&str] = &["aac", "mp3", "opus", "vorbis", "flac"];
+/// Codecs the mp4 we produce can carry without re-encoding
+const MP4_VIDEO_CODECS: &[&str] = &["h264"];
+const MP4_AUDIO_CODECS: &[&str] = &["aac", "mp3"];
+
+const VIDEO_CRF: &str = "20";
+const VIDEO_PRESET: &str = "medium";
+const AUDIO_BITRATE: &str = "160k";
+
+/// Fingerprint of the settings used to transcode a video.
+pub fn settings() -> String {
+    format!(
+        "transcode h264 crf={} preset={} aac {}",
+        VIDEO_CRF, VIDEO_PRESET, AUDIO_BITRATE
+    )
+}
+
+/// Whether a browser can play a file as it is. The container comes from the
+/// probe, except that matroska and webm share a demuxer, so only files that
+/// claim to be webm are trusted to be one.
+fn is_web_ready(extension: &str, probe: &CodecProbe) -> bool {
+    let container_ok = probe
+        .format_name
+        .split(',')
+        .any(|name| name == "mp4" || (name == "webm" && extension.eq_ignore_ascii_case("webm")));
+    let video_ok = probe
+        .video_codecs
+        .first()
+        .is_some_and(|codec| WEB_VIDEO_CODECS.contains(&codec.as_str()));
+    let audio_ok = probe
+        .audio_codecs
+        .iter()
+        .all(|codec| WEB_AUDIO_CODECS.contains(&codec.as_str()));
+
+    container_ok && video_ok && audio_ok
+}
+
+/// Convert a video browsers can't play into an h264/aac mp4, copying any
+/// streams that are already usable rather than re-encoding them.
+pub fn create(work_dir_path: &Path, source: &FileCrawlType, output: &Path) -> Result<BakeOutcome> {
+    let FileCrawlType::Video { filename, .. } = source else {
+        return Ok(BakeOutcome::skipped("not a video"));
+    };
+
+    let video_path = work_dir_path.join(filename);
+    if !video_path.exists() {
+        return Ok(BakeOutcome::skipped("source file missing"));
+    }
+
+    let probe = ffmpeg::probe_codecs(&video_path)?;
+    let extension = video_path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if is_web_ready(&extension, &probe) {
+        return Ok(BakeOutcome::not_needed("already web-ready"));
+    }
+    let Some(video_codec) = probe.video_codecs.first() else {
+        return Ok(BakeOutcome::not_needed("audio only"));
+    };
+
+    let mut command = ffmpeg::ffmpeg();
+    command
+        .arg("-i")
+        .arg(&video_path)
+        .arg("-map")
+        .arg("0:v:0")
+        .arg("-map")
+        .arg("0:a:0?");
+
+    if MP4_VIDEO_CODECS.contains(&video_codec.as_str()) {
+        command.arg("-c:v").arg("copy");
+    } else {
+        command
+            .arg("-c:v")
+            .arg("libx264")
+            .arg("-preset")
+            .arg(VIDEO_PRESET)
+            .arg("-crf")
+            .arg(VIDEO_CRF)
+            .arg("-pix_fmt")
+            .arg("yuv420p");
+    }
+
+    let audio_copyable = probe
+        .audio_codecs
+        .first()
+        .is_some_and(|codec| MP4_AUDIO_CODECS.contains(&codec.as_str()));
+    if audio_copyable {
+        command.arg("-c:a").arg("copy");
+    } else {
+        command
+            .arg("-c:a")
+            .arg("aac")
+            .arg("-b:a")
+            .arg(AUDIO_BITRATE);
+    }
+
+    let partial = partial_path(output);
+    ffmpeg::run(
+        "ffmpeg",
+        command.arg("-movflags").arg("+faststart").arg(&partial),
+    )?;
+    commit_partial(&partial, output)?;
+
+    Ok(BakeOutcome::created(work_dir_path, output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe(format_name: &str, video: &[&str], audio: &[&str]) -> CodecProbe {
+        CodecProbe {
+            format_name: format_name.to_string(),
+            video_codecs: video.iter().map(|c| c.to_string()).collect(),
+            audio_codecs: audio.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_is_web_ready() {
+        let mp4 = "mov,mp4,m4a,3gp,3g2,mj2";
+        assert!(is_web_ready("mp4", &probe(mp4, &["h264"], &["aac"])));
+        assert!(is_web_ready("mp4", &probe(mp4, &["h264"], &[])));
+        assert!(is_web_ready(
+            "webm",
+            &probe("matroska,webm", &["vp9"], &["opus"])
+        ));
+
+        // Wrong container
+        assert!(!is_web_ready("wmv", &probe("asf", &["wmv2"], &["wmav2"])));
+        assert!(!is_web_ready(
+            "mkv",
+            &probe("matroska,webm", &["h264"], &["aac"])
+        ));
+        // Right container, unplayable codecs
+        assert!(!is_web_ready("mp4", &probe(mp4, &["hevc"], &["aac"])));
+        assert!(!is_web_ready("mp4", &probe(mp4, &["h264"], &["ac3"])));
+    }
+}
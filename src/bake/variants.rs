@@ -0,0 +1,50 @@
+use std::path::Path;
+
+use super::{commit_partial, config::ImageFormat, ffmpeg, partial_path, BakeOutcome};
+use crate::{errors::Result, site::FileCrawlType};
+
+/// Fingerprint of the settings used to produce a resized variant.
+pub fn settings(width: u32, format: ImageFormat) -> String {
+    format!("variant w={} {}", width, format.codec_args().join(" "))
+}
+
+/// Produce a `width` pixel wide copy of an image in `format`. Sources that
+/// are already narrower than `width` are skipped rather than upscaled.
+pub fn create(
+    work_dir_path: &Path,
+    source: &FileCrawlType,
+    output: &Path,
+    width: u32,
+    format: ImageFormat,
+) -> Result<BakeOutcome> {
+    let FileCrawlType::Image { filename, .. } = source else {
+        return Ok(BakeOutcome::skipped("not an image"));
+    };
+
+    let image_path = work_dir_path.join(filename);
+    if !image_path.exists() {
+        return Ok(BakeOutcome::skipped("source file missing"));
+    }
+
+    let (source_width, _) = ffmpeg::dimensions(&image_path)?;
+    if source_width < width {
+        return Ok(BakeOutcome::not_needed("source narrower than variant"));
+    }
+
+    let partial = partial_path(output);
+    ffmpeg::run(
+        "ffmpeg",
+        ffmpeg::ffmpeg()
+            .arg("-i")
+            .arg(&image_path)
+            .arg("-frames:v")
+            .arg("1")
+            .arg("-vf")
+            .arg(format!("scale={}:-2:flags=lanczos", width))
+            .args(format.codec_args())
+            .arg(&partial),
+    )?;
+    commit_partial(&partial, output)?;
+
+    Ok(BakeOutcome::created(work_dir_path, output))
+}
@@ -0,0 +1,278 @@
+//! Colors, as extracted from media by bake and searched for by users.
+//!
+//! Bake records a small palette of dominant colors per image and video (see
+//! `bake::palette`). Searches name either a broad color family such as
+//! `"red"`, which each palette color is sorted into, or an exact `#rrggbb`
+//! color, which matches palette colors close to it.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// How far apart (in [`Rgb::distance`]) a palette color and a searched-for
+/// `#rrggbb` color may be and still match.
+const HEX_TOLERANCE: f64 = 80.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    /// Parse `#rrggbb`, or `rrggbb` without the hash.
+    pub fn parse_hex(hex: &str) -> Option<Self> {
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        Some(Rgb(channel(0)?, channel(2)?, channel(4)?))
+    }
+
+    /// Perceptual distance between two colors, using the "redmean"
+    /// approximation: cheap, and much closer to how different colors look
+    /// than plain RGB distance. Ranges from 0 to about 765.
+    pub fn distance(&self, other: &Rgb) -> f64 {
+        let mean_red = (self.0 as f64 + other.0 as f64) / 2.0;
+        let dr = self.0 as f64 - other.0 as f64;
+        let dg = self.1 as f64 - other.1 as f64;
+        let db = self.2 as f64 - other.2 as f64;
+        ((2.0 + mean_red / 256.0) * dr * dr
+            + 4.0 * dg * dg
+            + (2.0 + (255.0 - mean_red) / 256.0) * db * db)
+            .sqrt()
+    }
+
+    /// Hue in degrees, saturation and lightness, each of the last two 0 to 1.
+    fn hsl(&self) -> (f64, f64, f64) {
+        let r = self.0 as f64 / 255.0;
+        let g = self.1 as f64 / 255.0;
+        let b = self.2 as f64 / 255.0;
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let lightness = (max + min) / 2.0;
+        let delta = max - min;
+        if delta == 0.0 {
+            return (0.0, 0.0, lightness);
+        }
+
+        let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+        let hue = if max == r {
+            60.0 * (((g - b) / delta).rem_euclid(6.0))
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        (hue, saturation, lightness)
+    }
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+}
+
+impl From<Rgb> for String {
+    fn from(rgb: Rgb) -> Self {
+        rgb.to_string()
+    }
+}
+
+impl TryFrom<String> for Rgb {
+    type Error = String;
+
+    fn try_from(hex: String) -> Result<Self, Self::Error> {
+        Rgb::parse_hex(&hex).ok_or_else(|| format!("not a #rrggbb color: {}", hex))
+    }
+}
+
+/// The broad color families searches and the color facet use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum NamedColor {
+    Red,
+    Orange,
+    Yellow,
+    Green,
+    Cyan,
+    Blue,
+    Purple,
+    Pink,
+    Brown,
+    Black,
+    Gray,
+    White,
+}
+
+impl NamedColor {
+    pub const ALL: [NamedColor; 12] = [
+        NamedColor::Red,
+        NamedColor::Orange,
+        NamedColor::Yellow,
+        NamedColor::Green,
+        NamedColor::Cyan,
+        NamedColor::Blue,
+        NamedColor::Purple,
+        NamedColor::Pink,
+        NamedColor::Brown,
+        NamedColor::Black,
+        NamedColor::Gray,
+        NamedColor::White,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NamedColor::Red => "red",
+            NamedColor::Orange => "orange",
+            NamedColor::Yellow => "yellow",
+            NamedColor::Green => "green",
+            NamedColor::Cyan => "cyan",
+            NamedColor::Blue => "blue",
+            NamedColor::Purple => "purple",
+            NamedColor::Pink => "pink",
+            NamedColor::Brown => "brown",
+            NamedColor::Black => "black",
+            NamedColor::Gray => "gray",
+            NamedColor::White => "white",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "grey" => Some(NamedColor::Gray),
+            name => NamedColor::ALL
+                .into_iter()
+                .find(|color| color.name() == name),
+        }
+    }
+
+    /// A typical member of the family, for drawing swatches.
+    pub fn swatch(&self) -> Rgb {
+        match self {
+            NamedColor::Red => Rgb(0xd0, 0x20, 0x20),
+            NamedColor::Orange => Rgb(0xf0, 0x80, 0x20),
+            NamedColor::Yellow => Rgb(0xf0, 0xd0, 0x20),
+            NamedColor::Green => Rgb(0x30, 0xa0, 0x30),
+            NamedColor::Cyan => Rgb(0x20, 0xc0, 0xd0),
+            NamedColor::Blue => Rgb(0x20, 0x50, 0xd0),
+            NamedColor::Purple => Rgb(0x80, 0x30, 0xc0),
+            NamedColor::Pink => Rgb(0xf0, 0x80, 0xc0),
+            NamedColor::Brown => Rgb(0x80, 0x50, 0x20),
+            NamedColor::Black => Rgb(0x10, 0x10, 0x10),
+            NamedColor::Gray => Rgb(0x80, 0x80, 0x80),
+            NamedColor::White => Rgb(0xf8, 0xf8, 0xf8),
+        }
+    }
+
+    /// The family a color belongs to, judged by its hue, or by its lightness
+    /// when it is too dark, pale or washed out to have a noticeable hue.
+    pub fn of(rgb: Rgb) -> Self {
+        let (hue, saturation, lightness) = rgb.hsl();
+        if lightness < 0.12 {
+            return NamedColor::Black;
+        }
+        if lightness > 0.92 {
+            return NamedColor::White;
+        }
+        if saturation < 0.15 {
+            return match lightness {
+                l if l < 0.25 => NamedColor::Black,
+                l if l > 0.8 => NamedColor::White,
+                _ => NamedColor::Gray,
+            };
+        }
+
+        match hue {
+            h if !(15.0..345.0).contains(&h) => {
+                if lightness > 0.7 {
+                    NamedColor::Pink
+                } else if lightness < 0.3 {
+                    NamedColor::Brown
+                } else {
+                    NamedColor::Red
+                }
+            }
+            h if h < 45.0 => {
+                if lightness < 0.4 {
+                    NamedColor::Brown
+                } else {
+                    NamedColor::Orange
+                }
+            }
+            h if h < 70.0 => NamedColor::Yellow,
+            h if h < 165.0 => NamedColor::Green,
+            h if h < 195.0 => NamedColor::Cyan,
+            h if h < 255.0 => NamedColor::Blue,
+            h if h < 290.0 => NamedColor::Purple,
+            _ => NamedColor::Pink,
+        }
+    }
+}
+
+/// What a `(color ...)` search asks for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorQuery {
+    Named(NamedColor),
+    Exact(Rgb),
+}
+
+impl ColorQuery {
+    pub fn parse(arg: &str) -> Option<Self> {
+        if arg.starts_with('#') {
+            Rgb::parse_hex(arg).map(ColorQuery::Exact)
+        } else {
+            NamedColor::from_name(arg).map(ColorQuery::Named)
+        }
+    }
+
+    pub fn matches(&self, rgb: Rgb) -> bool {
+        match self {
+            ColorQuery::Named(color) => NamedColor::of(rgb) == *color,
+            ColorQuery::Exact(target) => target.distance(&rgb) <= HEX_TOLERANCE,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_round_trip() {
+        assert_eq!(Rgb::parse_hex("#aabbcc"), Some(Rgb(0xaa, 0xbb, 0xcc)));
+        assert_eq!(Rgb::parse_hex("AABBCC"), Some(Rgb(0xaa, 0xbb, 0xcc)));
+        assert_eq!(Rgb::parse_hex("#abc"), None);
+        assert_eq!(Rgb::parse_hex("#gg0000"), None);
+        assert_eq!(Rgb(0x0a, 0xff, 0x00).to_string(), "#0aff00");
+    }
+
+    #[test]
+    fn test_named_color_of() {
+        assert_eq!(NamedColor::of(Rgb(220, 30, 30)), NamedColor::Red);
+        assert_eq!(NamedColor::of(Rgb(250, 140, 30)), NamedColor::Orange);
+        assert_eq!(NamedColor::of(Rgb(110, 70, 30)), NamedColor::Brown);
+        assert_eq!(NamedColor::of(Rgb(40, 90, 220)), NamedColor::Blue);
+        assert_eq!(NamedColor::of(Rgb(60, 160, 60)), NamedColor::Green);
+        assert_eq!(NamedColor::of(Rgb(250, 170, 200)), NamedColor::Pink);
+        assert_eq!(NamedColor::of(Rgb(5, 5, 10)), NamedColor::Black);
+        assert_eq!(NamedColor::of(Rgb(128, 130, 128)), NamedColor::Gray);
+        assert_eq!(NamedColor::of(Rgb(250, 250, 245)), NamedColor::White);
+
+        for color in NamedColor::ALL {
+            assert_eq!(NamedColor::of(color.swatch()), color, "{:?}", color);
+        }
+    }
+
+    #[test]
+    fn test_color_query() {
+        assert_eq!(
+            ColorQuery::parse("Grey"),
+            Some(ColorQuery::Named(NamedColor::Gray))
+        );
+        assert_eq!(ColorQuery::parse("mauve"), None);
+
+        let query = ColorQuery::parse("#aabbcc").unwrap();
+        assert!(query.matches(Rgb(0xa0, 0xb8, 0xd0)));
+        assert!(!query.matches(Rgb(0xcc, 0x20, 0x20)));
+    }
+}
@@ -0,0 +1,371 @@
+//! Clusters of items, across every loaded site, whose media is perceptually
+//! the same picture or clip.
+//!
+//! Bake records a perceptual hash for each downloaded image and video (see
+//! `bake::phash`). Two items are duplicates when any of their files hash
+//! within [`MAX_DISTANCE`] bits of each other, and clusters are the
+//! transitive closure of that.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use serde::Serialize;
+
+use crate::bake::phash::distance;
+use crate::site::CrawlItem;
+use crate::thread_safe_work_dir::ThreadSafeWorkDir;
+
+/// Hashes this many bits apart or closer are considered the same media.
+pub const MAX_DISTANCE: u32 = 6;
+
+/// The hash is split into this many blocks of bits for bucketing. Hashes
+/// within range differ in at most `MAX_DISTANCE` blocks, so with one block
+/// more they must agree exactly on at least one, and only hashes sharing a
+/// block need comparing. More blocks would only make them narrower and the
+/// buckets bigger.
+const BLOCKS: u32 = MAX_DISTANCE + 1;
+
+/// One of the `BLOCKS` blocks of `hash`, which between them cover all of its
+/// bits.
+fn block(hash: u64, index: u32) -> u64 {
+    let start = index * 64 / BLOCKS;
+    let end = (index + 1) * 64 / BLOCKS;
+    (hash >> start) & ((1 << (end - start)) - 1)
+}
+
+/// One item in a cluster.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct ItemRef {
+    pub site_slug: String,
+    pub item_key: String,
+}
+
+impl ItemRef {
+    pub fn new(site_slug: &str, item_key: &str) -> Self {
+        ItemRef {
+            site_slug: site_slug.to_string(),
+            item_key: item_key.to_string(),
+        }
+    }
+
+    /// The key the item has in the `all` view.
+    pub fn namespaced_key(&self) -> String {
+        format!("{}/{}", self.site_slug, self.item_key)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClusterMember {
+    pub item: ItemRef,
+    pub title: String,
+    pub source_published: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DuplicateIndex {
+    /// Each cluster has at least two members, the earliest published first.
+    pub clusters: Vec<Vec<ClusterMember>>,
+    #[serde(skip)]
+    cluster_of: HashMap<ItemRef, usize>,
+}
+
+impl DuplicateIndex {
+    pub fn build<'a>(items: impl IntoIterator<Item = &'a CrawlItem>) -> Self {
+        let mut members = vec![];
+        let mut hashes: HashMap<u64, Vec<usize>> = HashMap::new();
+        for item in items {
+            let index = members.len();
+            let item_hashes = item
+                .media
+                .values()
+                .filter_map(|metadata| metadata.phash)
+                // A flat frame hashes to zero, and every blank or solid-color
+                // image in the corpus would otherwise be one cluster.
+                .filter(|hash| *hash != 0)
+                .collect::<Vec<_>>();
+            if item_hashes.is_empty() {
+                continue;
+            }
+            members.push(ClusterMember {
+                item: ItemRef::new(&item.site_settings.site_slug, &item.key),
+                title: item.title.clone(),
+                source_published: item.source_published,
+            });
+            for hash in item_hashes {
+                hashes.entry(hash).or_default().push(index);
+            }
+        }
+
+        let mut sets = DisjointSets::new(members.len());
+
+        // Items sharing an exact hash are duplicates without comparing anything
+        for owners in hashes.values() {
+            for pair in owners.windows(2) {
+                sets.union(pair[0], pair[1]);
+            }
+        }
+
+        let mut buckets: HashMap<(u32, u64), Vec<u64>> = HashMap::new();
+        for hash in hashes.keys() {
+            for index in 0..BLOCKS {
+                buckets
+                    .entry((index, block(*hash, index)))
+                    .or_default()
+                    .push(*hash);
+            }
+        }
+        for bucket in buckets.values() {
+            for (i, a) in bucket.iter().enumerate() {
+                for b in &bucket[i + 1..] {
+                    if distance(*a, *b) <= MAX_DISTANCE {
+                        sets.union(hashes[a][0], hashes[b][0]);
+                    }
+                }
+            }
+        }
+
+        let mut grouped: HashMap<usize, Vec<ClusterMember>> = HashMap::new();
+        for (index, member) in members.into_iter().enumerate() {
+            grouped.entry(sets.find(index)).or_default().push(member);
+        }
+
+        let mut clusters = grouped
+            .into_values()
+            .filter(|cluster| cluster.len() > 1)
+            .map(|mut cluster| {
+                cluster.sort_by(|a, b| {
+                    a.source_published
+                        .cmp(&b.source_published)
+                        .then_with(|| a.item.namespaced_key().cmp(&b.item.namespaced_key()))
+                });
+                cluster
+            })
+            .collect::<Vec<_>>();
+        clusters.sort_by(|a, b| {
+            b.len()
+                .cmp(&a.len())
+                .then_with(|| a[0].item.namespaced_key().cmp(&b[0].item.namespaced_key()))
+        });
+
+        let cluster_of = clusters
+            .iter()
+            .enumerate()
+            .flat_map(|(index, cluster)| cluster.iter().map(move |m| (m.item.clone(), index)))
+            .collect();
+
+        DuplicateIndex {
+            clusters,
+            cluster_of,
+        }
+    }
+
+    /// The other members of an item's cluster, if it has any.
+    pub fn copies_of(&self, item: &ItemRef) -> Vec<ClusterMember> {
+        self.cluster_of
+            .get(item)
+            .map(|index| {
+                self.clusters[*index]
+                    .iter()
+                    .filter(|member| member.item != *item)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Whether an item stands for its cluster when duplicates are collapsed.
+    /// Items with no duplicates always do.
+    pub fn is_canonical(&self, item: &ItemRef) -> bool {
+        match self.cluster_of.get(item) {
+            Some(index) => self.clusters[*index][0].item == *item,
+            None => true,
+        }
+    }
+
+    pub fn print(&self) {
+        for cluster in &self.clusters {
+            println!("{} copies:", cluster.len());
+            for member in cluster {
+                println!("  {}  {}", member.item.namespaced_key(), member.title);
+            }
+        }
+        println!(
+            "{} duplicate clusters covering {} items",
+            self.clusters.len(),
+            self.cluster_of.len()
+        );
+    }
+}
+
+/// Union-find over item indices.
+struct DisjointSets {
+    parent: Vec<usize>,
+}
+
+impl DisjointSets {
+    fn new(len: usize) -> Self {
+        DisjointSets {
+            parent: (0..len).collect(),
+        }
+    }
+
+    fn find(&mut self, mut index: usize) -> usize {
+        while self.parent[index] != index {
+            self.parent[index] = self.parent[self.parent[index]];
+            index = self.parent[index];
+        }
+        index
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parent[b] = a;
+        }
+    }
+}
+
+/// The index, and the `loaded_at` of each WorkDir it was built from.
+type CachedIndex = (Vec<u128>, Arc<DuplicateIndex>);
+
+/// A duplicate index over a set of live WorkDirs. Built up front and rebuilt
+/// by [`SharedDuplicateIndex::refresh`] after any of them reloads, so
+/// requests never wait for a build.
+#[derive(Clone)]
+pub struct SharedDuplicateIndex {
+    work_dirs: Vec<ThreadSafeWorkDir>,
+    cached: Arc<RwLock<CachedIndex>>,
+}
+
+impl SharedDuplicateIndex {
+    pub fn new(work_dirs: Vec<ThreadSafeWorkDir>) -> Self {
+        let cached = Self::build(&work_dirs);
+        SharedDuplicateIndex {
+            work_dirs,
+            cached: Arc::new(RwLock::new(cached)),
+        }
+    }
+
+    /// The index as of the last build.
+    pub fn get(&self) -> Arc<DuplicateIndex> {
+        self.cached.read().unwrap().1.clone()
+    }
+
+    /// Rebuild the index if any of the WorkDirs reloaded since it was built.
+    pub fn refresh(&self) {
+        let loaded_at = self
+            .work_dirs
+            .iter()
+            .map(|work_dir| work_dir.work_dir.read().unwrap().loaded_at)
+            .collect::<Vec<_>>();
+        if self.cached.read().unwrap().0 == loaded_at {
+            return;
+        }
+        let cached = Self::build(&self.work_dirs);
+        *self.cached.write().unwrap() = cached;
+    }
+
+    fn build(work_dirs: &[ThreadSafeWorkDir]) -> CachedIndex {
+        let guards = work_dirs
+            .iter()
+            .map(|work_dir| work_dir.work_dir.read().unwrap())
+            .collect::<Vec<_>>();
+        let loaded_at = guards.iter().map(|wd| wd.loaded_at).collect::<Vec<_>>();
+
+        // From the items the `all` view lists, so a hidden item can't become
+        // the copy a collapsed cluster is listed as
+        let index = DuplicateIndex::build(
+            guards
+                .iter()
+                .flat_map(|wd| wd.items_for_all_view().items.values())
+                .filter(|item| !item.hidden),
+        );
+        (loaded_at, Arc::new(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bake::manifest::SourceFingerprint;
+    use crate::bake::MediaMetadata;
+
+    fn item(site: &str, key: &str, published: i64, hashes: &[u64]) -> CrawlItem {
+        let mut item = CrawlItem::for_test(key, key, &[]);
+        item.source_published = published;
+        item.site_settings.site_slug = site.to_string();
+        for (i, hash) in hashes.iter().enumerate() {
+            item.media.insert(
+                i.to_string(),
+                MediaMetadata {
+                    source: SourceFingerprint {
+                        path: format!("{}/{}", key, i),
+                        size: 0,
+                        modified: 0,
+                    },
+                    version: 0,
+                    width: None,
+                    height: None,
+                    duration: None,
+                    video_codec: None,
+                    audio_codec: None,
+                    frame_rate: None,
+                    phash: Some(*hash),
+                    palette: vec![],
+                },
+            );
+        }
+        item
+    }
+
+    #[test]
+    fn test_clusters_near_hashes_across_sites() {
+        let items = vec![
+            item("a", "1", 20, &[0xF0F0_F0F0_F0F0_F0F0]),
+            // Three bits off the first
+            item("b", "1", 10, &[0xF0F0_F0F0_F0F0_F0F7]),
+            // Chained through a second file to an exact copy of the first
+            item(
+                "c",
+                "1",
+                30,
+                &[0x1234_5678_9ABC_DEF0, 0xF0F0_F0F0_F0F0_F0F0],
+            ),
+            // Unrelated
+            item("c", "2", 40, &[0x0F0F_0F0F_0F0F_0F0F]),
+            // Blank frames are never duplicates
+            item("a", "2", 50, &[0]),
+            item("b", "2", 60, &[0]),
+        ];
+        let index = DuplicateIndex::build(&items);
+
+        assert_eq!(index.clusters.len(), 1);
+        let keys = index.clusters[0]
+            .iter()
+            .map(|m| m.item.namespaced_key())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec!["b/1", "a/1", "c/1"]);
+
+        assert!(index.is_canonical(&ItemRef::new("b", "1")));
+        assert!(!index.is_canonical(&ItemRef::new("a", "1")));
+        assert!(index.is_canonical(&ItemRef::new("c", "2")));
+        assert_eq!(index.copies_of(&ItemRef::new("a", "1")).len(), 2);
+        assert!(index.copies_of(&ItemRef::new("a", "2")).is_empty());
+    }
+
+    #[test]
+    fn test_blocks_cover_every_bit() {
+        let bits = (0..BLOCKS)
+            .map(|index| block(u64::MAX, index).count_ones())
+            .collect::<Vec<_>>();
+        assert_eq!(bits.iter().sum::<u32>(), 64);
+        assert!(bits.iter().all(|bits| (9..=10).contains(bits)));
+
+        // Six bits apart, one in each of six different blocks
+        let hash = 0x0123_4567_89AB_CDEF;
+        let near = hash ^ (1 | 1 << 10 | 1 << 19 | 1 << 28 | 1 << 37 | 1 << 46);
+        let index =
+            DuplicateIndex::build(&[item("a", "1", 0, &[hash]), item("b", "1", 0, &[near])]);
+        assert_eq!(index.clusters.len(), 1);
+    }
+}
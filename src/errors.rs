@@ -8,6 +8,12 @@ pub enum Error {
     #[error("Serde error: {0}")]
     Serde(#[from] serde_json::Error),
 
+    #[error("{tool} {message}")]
+    Tool { tool: &'static str, message: String },
+
+    #[error("Invalid config: {0}")]
+    Config(String),
+
     #[error("{0}")]
     Context(String),
 }
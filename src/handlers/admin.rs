@@ -0,0 +1,175 @@
+use std::{path::PathBuf, sync::Arc};
+
+use actix_web::{get, post, web};
+use maud::{html, Markup};
+use serde::Deserialize;
+
+use crate::handlers::{scripts, SiteSource};
+use crate::reprocessor_diff::{diff_reprocessors, parse_reprocessor_list, ReprocessorDiff};
+use crate::reprocessors::Reprocessor;
+use crate::server_config::ServerConfig;
+use crate::workdir::{Config, WorkDir};
+
+#[derive(Deserialize)]
+pub struct ReprocessorForm {
+    reprocessors: String,
+}
+
+/// The site's current reprocessors, ready to edit and preview.
+#[get("/admin/reprocessors")]
+pub async fn reprocessor_diff_form_handler(
+    site_source: web::Data<SiteSource>,
+) -> Result<Markup, actix_web::Error> {
+    let reprocessors = site_config(&site_source)?.1.reprocessors;
+    let candidate = serde_json::to_string_pretty(&reprocessors)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(reprocessor_diff_page(&site_source, &candidate, None))
+}
+
+/// What a candidate list of reprocessors would do to the site's crawled
+/// items, without changing anything.
+#[post("/admin/reprocessors")]
+pub async fn reprocessor_diff_handler(
+    site_source: web::Data<SiteSource>,
+    form: web::Form<ReprocessorForm>,
+) -> Result<Markup, actix_web::Error> {
+    let diff = match parse_reprocessor_list(&form.reprocessors) {
+        Err(e) => Err(e.to_string()),
+        Ok(reprocessors) => {
+            // Reads crawled.json and runs the whole pipeline
+            let site_source = site_source.clone();
+            web::block(move || run_diff(&site_source, &reprocessors)).await?
+        }
+    };
+    Ok(reprocessor_diff_page(
+        &site_source,
+        &form.reprocessors,
+        Some(diff),
+    ))
+}
+
+/// The WorkDir path and config of a single site, and the server config it
+/// was loaded with. The `all` view has no reprocessors of its own.
+fn site_config(
+    site_source: &SiteSource,
+) -> Result<(PathBuf, Config, Arc<ServerConfig>), actix_web::Error> {
+    let SiteSource::Single(work_dir) = site_source else {
+        return Err(actix_web::error::ErrorNotFound(
+            "Reprocessors are configured per site",
+        ));
+    };
+    let work_dir = work_dir.work_dir.read().expect("work_dir read poisoned");
+    Ok((
+        work_dir.path.to_path_buf(),
+        work_dir.config.clone(),
+        work_dir.server_config.clone(),
+    ))
+}
+
+/// Diff the site's pipeline with `reprocessors` in place of its own. The
+/// server-wide sets it runs are included.
+fn run_diff(
+    site_source: &SiteSource,
+    reprocessors: &[Reprocessor],
+) -> Result<ReprocessorDiff, String> {
+    let (path, config, server_config) = site_config(site_source).map_err(|e| e.to_string())?;
+    let items = WorkDir::load_unprocessed(&path, &config).map_err(|e| e.to_string())?;
+    let pipeline = server_config.pipeline(reprocessors, &config.skip_server_reprocessors);
+    diff_reprocessors(items.items, &pipeline, &server_config.tags, &path)
+        .map_err(|e| e.to_string())
+}
+
+fn reprocessor_diff_page(
+    site_source: &SiteSource,
+    candidate: &str,
+    diff: Option<Result<ReprocessorDiff, String>>,
+) -> Markup {
+    let site_prefix = site_source.slug();
+    html! {
+        (maud::DOCTYPE)
+        html {
+            head {
+                meta charset="utf-8";
+                meta name="viewport" content="width=device-width, initial-scale=1" {}
+                (scripts())
+                title { "Reprocessors" }
+            }
+            body.reprocessor_diff_page {
+                main {
+                    h1 { "Reprocessors for " a href=(format!("/{}/booru/latest", site_prefix)) { (site_prefix) } }
+                    p {
+                        "Runs the reprocessors below against the site's crawled items and lists what each step changes, "
+                        "along with the server-wide reprocessors the site runs before and after them and the server's tag rules. "
+                        "Nothing is saved; edit config.json to keep a change."
+                    }
+                    form method="post" action=(format!("/{}/admin/reprocessors", site_prefix)) {
+                        textarea.reprocessor_candidate name="reprocessors" rows="20" spellcheck="false" { (candidate) }
+                        button type="submit" { "Preview" }
+                    }
+                    @match &diff {
+                        None => {}
+                        Some(Err(message)) => {
+                            pre.reprocessor_diff_error { (message) }
+                        }
+                        Some(Ok(diff)) => {
+                            p.reprocessor_diff_summary {
+                                (diff.items_before) " items before reprocessing, " (diff.items_after) " after"
+                            }
+                            @for step in &diff.steps {
+                                section.reprocessor_step {
+                                    h2 {
+                                        (step.step) ". " code { (step.reprocessor) }
+                                        @if let Some(set) = &step.set {
+                                            span.reprocessor_set { " from server set " code { (set) } }
+                                        }
+                                    }
+                                    @if step.is_empty() {
+                                        p.no_changes { "No changes" }
+                                    }
+                                    @if !step.dropped.is_empty() {
+                                        h3 { "Dropped (" (step.dropped.len()) ")" }
+                                        ul { @for key in &step.dropped { li { code { (key) } } } }
+                                    }
+                                    @if !step.added.is_empty() {
+                                        h3 { "Added (" (step.added.len()) ")" }
+                                        ul { @for key in &step.added { li { code { (key) } } } }
+                                    }
+                                    @if !step.hidden.is_empty() {
+                                        h3 { "Hidden (" (step.hidden.len()) ")" }
+                                        ul { @for key in &step.hidden { li { code { (key) } } } }
+                                    }
+                                    @if !step.tags_renamed.is_empty() || !step.tags_added.is_empty() || !step.tags_removed.is_empty() {
+                                        h3 { "Tags" }
+                                        table.tag_changes {
+                                            @for rename in &step.tags_renamed {
+                                                tr { td { (rename.from) " → " (rename.to) } td { (rename.count) } }
+                                            }
+                                            @for (tag, count) in &step.tags_added {
+                                                tr.tag_added { td { "+" (tag) } td { (count) } }
+                                            }
+                                            @for (tag, count) in &step.tags_removed {
+                                                tr.tag_removed { td { "−" (tag) } td { (count) } }
+                                            }
+                                        }
+                                    }
+                                    @if !step.titles.is_empty() {
+                                        h3 { "Titles (" (step.titles.len()) ")" }
+                                        table.title_changes {
+                                            @for change in &step.titles {
+                                                tr {
+                                                    td { code { (change.key) } }
+                                                    td { del { (change.before) } }
+                                                    td { ins { (change.after) } }
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,17 +1,15 @@
-use chrono::{Month, TimeZone, Utc};
+use chrono::Month;
 use maud::{html, Markup};
 use std::collections::HashMap;
 use urlencoding::encode;
 
 use super::{ArchiveYear, ListingPageConfig, ListingPageMode, PageUrlState, ViewMode};
-use crate::handlers::{calculate_item_index, ExtensionFix, Fa, PaginatorPrefix};
+use crate::handlers::{calculate_item_index, Fa, PaginatorPrefix};
+use crate::collections::GetKey;
 use crate::site::{CrawlItem, CrawlTag, FileCrawlType};
 
 // Helper functions for rendering blog components
 fn blog_post_card(item: &CrawlItem, site_prefix: &str, config: &ListingPageConfig, position_in_page: usize) -> Markup {
-    let time = Utc
-        .timestamp_millis_opt(item.source_published as i64)
-        .unwrap();
     // Use item's source site for asset paths
     let asset_site = &item.site_settings.site_slug;
     let slideshow_index = calculate_item_index(config, position_in_page);
@@ -34,22 +32,25 @@ fn blog_post_card(item: &CrawlItem, site_prefix: &str, config: &ListingPageConfi
                 }
             }
             .post_meta {
-                time datetime=(time.to_rfc3339()) {
-                    (time.format("%B %d, %Y"))
-                }
+                (super::published_date(item.source_published))
             }
             @if let Some(thumb) = item.thumbnail_path() {
                 @if thumb.ends_with(".mp4") {
-                    .post_preview {
+                    .post_preview data-storyboard=[super::preview_storyboard_url(item)] style=[super::placeholder_style(item)] {
                         video.thumbnail_preview autoplay loop muted playsinline {
                             source src=(format!("/{}/assets/{}", asset_site, thumb)) {}
                         }
+                        (super::duration_badge(item))
                     }
                 } @else {
-                    .post_preview {
-                        img src=(format!("/{}/assets/{}", asset_site, thumb)) alt=(item.title) {}
+                    .post_preview style=[super::placeholder_style(item)] {
+                        (super::responsive_image(asset_site, &thumb, &item.preview_variants(), super::PREVIEW_SIZES, &item.title, None, super::preview_dimensions(item)))
                     }
                 }
+            } @else if let Some(pending) = super::pending_thumbnail(item) {
+                .post_preview style=[super::placeholder_style(item)] {
+                    (pending)
+                }
             }
             .post_excerpt {
                 p { (item.description) }
@@ -107,6 +108,7 @@ pub fn render_listing_page(
     let title = match &config.mode {
         ListingPageMode::All => String::new(),
         ListingPageMode::ByTag { tag } => format!("Posts tagged \"{}\"", tag),
+        ListingPageMode::ByMonth { month: 0, .. } => "Posts with an unknown date".to_string(),
         ListingPageMode::ByMonth { year, month } => {
             format!(
                 "Posts from {} {}",
@@ -114,10 +116,11 @@ pub fn render_listing_page(
                 year
             )
         }
-        ListingPageMode::Search { query } => format!("Search: {}", query),
+        ListingPageMode::Search { query, .. } => format!("Search: {}", query),
     };
 
     let content = html! {
+        (super::compiled_search(&config.mode))
         .blog_posts {
             @for (idx, item) in items.iter().enumerate() {
                 (blog_post_card(item, site_prefix, &config, idx))
@@ -138,18 +141,13 @@ pub fn render_detail_page(
     let route = url_state.to_route();
     // Use item's source site for asset paths
     let asset_site = &item.site_settings.site_slug;
-    let time = Utc
-        .timestamp_millis_opt(item.source_published as i64)
-        .unwrap();
 
     let content = html! {
         article.blog_post {
             header.post_header {
                 h1.post_title { (item.title) }
                 .post_meta {
-                    time datetime=(time.to_rfc3339()) {
-                        (time.format("%B %d, %Y"))
-                    }
+                    (super::published_date(item.source_published))
                 }
             }
             .post_content {
@@ -157,17 +155,19 @@ pub fn render_detail_page(
                     FileCrawlType::Image { filename, downloaded, .. } => {
                         @if *downloaded {
                             figure.post_figure {
-                                img.post_image src=(format!("/{}/assets/{}", asset_site, filename)) alt=(item.title) {}
+                                (super::responsive_image(asset_site, filename, &item.display_variants(file.get_key()), super::DISPLAY_SIZES, &item.title, Some("post_image"), item.media_for(file).and_then(|m| m.dimensions())))
+                                (super::file_info(item, file))
                             }
                         }
                     }
                     FileCrawlType::Video { filename, downloaded, .. } => {
                         @if *downloaded {
-                            @let coerced_filename = filename.as_mp4();
+                            @let playable_filename = item.playable_video_path(file.get_key(), filename);
                             figure.post_figure {
-                                video.post_video controls autoplay {
-                                    source src=(format!("/{}/assets/{}", asset_site, coerced_filename)) {}
+                                video.post_video controls autoplay data-storyboard=[super::storyboard_url(item, file)] data-hls=[super::hls_url(item, file)] {
+                                    source src=(format!("/{}/assets/{}", asset_site, playable_filename)) {}
                                 }
+                                (super::file_info(item, file))
                             }
                         }
                     }
@@ -205,6 +205,7 @@ pub fn render_detail_page(
                     "Source: "
                     a href=(item.url) { (item.url) }
                 }
+                (super::also_appears_in(item, "blog"))
             }
         }
     };
@@ -216,6 +217,7 @@ pub fn render_tags_page(
     site_prefix: &str,
     tags: &HashMap<String, usize>,
     tag_order: &Vec<String>,
+    implies: &HashMap<String, Vec<String>>,
     route: &str,
 ) -> Markup {
     let content = html! {
@@ -228,6 +230,15 @@ pub fn render_tags_page(
                             span.tag_name { (tag) }
                             span.tag_count { " (" (tags.get(tag).unwrap_or(&0)) ")" }
                         }
+                        @if let Some(implied) = implies.get(tag) {
+                            span.tag_implies {
+                                " implies: "
+                                @for (i, implied) in implied.iter().enumerate() {
+                                    @if i > 0 { ", " }
+                                    a href=(format!("/{}/blog/tag/{}", site_prefix, encode(implied))) { (implied) }
+                                }
+                            }
+                        }
                     }
                 }
             }
@@ -244,12 +255,16 @@ pub fn render_archive_page(site_prefix: &str, archive: &Vec<ArchiveYear>, route:
             ul.blog_archive_list.full_archive_list {
                 @for year in archive.iter() {
                     li.archive_year {
-                        h3.year_name { (year.year) }
+                        h3.year_name {
+                            @if year.year == 0 { "Date unknown" } @else { (year.year) }
+                        }
                         ul.month_list {
                             @for month in year.months.iter().rev() {
                                 li.archive_month {
                                     a href=(format!("/{}/blog/archive/{}/{:02}", site_prefix, year.year, month.month)) {
-                                        span.month_name { (Month::try_from(month.month).unwrap().name()) }
+                                        span.month_name {
+                                            @if let Ok(name) = Month::try_from(month.month) { (name.name()) } @else { "All posts" }
+                                        }
                                         span.month_count { "(" (month.count) ")" }
                                     }
                                 }
@@ -275,9 +290,6 @@ pub fn render_slideshow_detail_page(
     let route = url_state.to_route();
     // Use item's source site for asset paths
     let asset_site = &item.site_settings.site_slug;
-    let time = Utc
-        .timestamp_millis_opt(item.source_published as i64)
-        .unwrap();
 
     // Get file_id for permalink
     let file_id = item
@@ -303,9 +315,7 @@ pub fn render_slideshow_detail_page(
             header.post_header {
                 h1.post_title { (item.title) }
                 .post_meta {
-                    time datetime=(time.to_rfc3339()) {
-                        (time.format("%B %d, %Y"))
-                    }
+                    (super::published_date(item.source_published))
                 }
             }
             .post_content {
@@ -313,17 +323,19 @@ pub fn render_slideshow_detail_page(
                     FileCrawlType::Image { filename, downloaded, .. } => {
                         @if *downloaded {
                             figure.post_figure {
-                                img.post_image src=(format!("/{}/assets/{}", asset_site, filename)) alt=(item.title) {}
+                                (super::responsive_image(asset_site, filename, &item.display_variants(file.get_key()), super::DISPLAY_SIZES, &item.title, Some("post_image"), item.media_for(file).and_then(|m| m.dimensions())))
+                                (super::file_info(item, file))
                             }
                         }
                     }
                     FileCrawlType::Video { filename, downloaded, .. } => {
                         @if *downloaded {
-                            @let coerced_filename = filename.as_mp4();
+                            @let playable_filename = item.playable_video_path(file.get_key(), filename);
                             figure.post_figure {
-                                video.post_video controls autoplay {
-                                    source src=(format!("/{}/assets/{}", asset_site, coerced_filename)) {}
+                                video.post_video controls autoplay data-storyboard=[super::storyboard_url(item, file)] data-hls=[super::hls_url(item, file)] {
+                                    source src=(format!("/{}/assets/{}", asset_site, playable_filename)) {}
                                 }
+                                (super::file_info(item, file))
                             }
                         }
                     }
@@ -414,10 +426,10 @@ pub fn render_slideshow_full_page(
                     }
                     FileCrawlType::Video { filename, downloaded, .. } => {
                         @if *downloaded {
-                            @let coerced_filename = filename.as_mp4();
+                            @let playable_filename = item.playable_video_path(file.get_key(), filename);
                             figure.post_figure {
-                                video.post_video controls autoplay {
-                                    source src=(format!("/{}/assets/{}", asset_site, coerced_filename)) {}
+                                video.post_video controls autoplay data-storyboard=[super::storyboard_url(item, file)] data-hls=[super::hls_url(item, file)] {
+                                    source src=(format!("/{}/assets/{}", asset_site, playable_filename)) {}
                                 }
                             }
                         }
@@ -2,7 +2,9 @@ use maud::{html, Markup};
 use std::collections::HashMap;
 use urlencoding::encode;
 
-use crate::handlers::{calculate_item_index, ExtensionFix, Fa, PaginatorPrefix};
+use crate::handlers::{calculate_item_index, Fa, PaginatorPrefix};
+use crate::collections::GetKey;
+use crate::color::NamedColor;
 use crate::site::{CrawlItem, CrawlTag, FileCrawlType};
 
 use super::{ArchiveYear, ListingPageConfig, ListingPageMode, PageUrlState, ViewMode};
@@ -50,18 +52,21 @@ fn item_thumbnail(item: &CrawlItem, site_prefix: &str, config: &ListingPageConfi
 
     html! {
         a.item_thumb_container href=(slideshow_url_path) {
-            .item_thumb_img {
+            .item_thumb_img data-storyboard=[super::preview_storyboard_url(item)] style=[super::placeholder_style(item)] {
                 @if let Some(thumb) = item.thumbnail_path() {
                     @if thumb.ends_with(".mp4") {
                         video.thumbnail_preview autoplay loop muted playsinline {
                             source src=(format!("/{}/assets/{}", asset_site, thumb)) {}
                         }
                     } @else {
-                        img src=(format!("/{}/assets/{}", asset_site, thumb)) alt=(item.title) {}
+                        (super::responsive_image(asset_site, &thumb, &item.preview_variants(), super::PREVIEW_SIZES, &item.title, None, super::preview_dimensions(item)))
                     }
+                } @else if let Some(pending) = super::pending_thumbnail(item) {
+                    (pending)
                 } @else {
                     p.no_thumbnail { "No thumbnail" }
                 }
+                (super::duration_badge(item))
             }
             .item_thumb_tags {
                 @for tag in &item.tags {
@@ -84,11 +89,13 @@ pub fn render_listing_page(
     let title = match &config.mode {
         ListingPageMode::All => String::new(),
         ListingPageMode::ByTag { tag } => format!("Items tagged \"{}\"", tag),
+        ListingPageMode::ByMonth { month: 0, .. } => "Items with an unknown date".to_string(),
         ListingPageMode::ByMonth { year, month } => format!("Items from {}/{}", year, month),
-        ListingPageMode::Search { query } => format!("Search: {}", query),
+        ListingPageMode::Search { query, .. } => format!("Search: {}", query),
     };
 
     let content = html! {
+        ( super::compiled_search(&config.mode) )
         ( super::paginator(config.page, config.total, config.per_page, &config.paginator_prefix(site_prefix, "booru")) )
         .item_thumb_grid {
             @for (idx, item) in items.iter().enumerate() {
@@ -119,17 +126,19 @@ pub fn render_detail_page(
                     FileCrawlType::Image { filename, downloaded, .. } => {
                         @if *downloaded {
                             figure.post_figure {
-                                img.post_image src=(format!("/{}/assets/{}", asset_site, filename)) alt=(item.title) {}
+                                (super::responsive_image(asset_site, filename, &item.display_variants(file.get_key()), super::DISPLAY_SIZES, &item.title, Some("post_image"), item.media_for(file).and_then(|m| m.dimensions())))
+                                (super::file_info(item, file))
                             }
                         }
                     }
                     FileCrawlType::Video { filename, downloaded, .. } => {
                         @if *downloaded {
-                            @let coerced_filename = filename.as_mp4();
+                            @let playable_filename = item.playable_video_path(file.get_key(), filename);
                             figure.post_figure {
-                                video.post_video controls autoplay {
-                                    source src=(format!("/{}/assets/{}", asset_site, coerced_filename)) {}
+                                video.post_video controls autoplay data-storyboard=[super::storyboard_url(item, file)] data-hls=[super::hls_url(item, file)] {
+                                    source src=(format!("/{}/assets/{}", asset_site, playable_filename)) {}
                                 }
+                                (super::file_info(item, file))
                             }
                         }
                     }
@@ -166,6 +175,7 @@ pub fn render_detail_page(
                     "Source: "
                     a href=(item.url) { (item.url) }
                 }
+                (super::also_appears_in(item, "booru"))
             }
         }
     };
@@ -177,10 +187,27 @@ pub fn render_tags_page(
     site_prefix: &str,
     tags: &HashMap<String, usize>,
     tag_order: &Vec<String>,
+    implies: &HashMap<String, Vec<String>>,
+    colors: &[(NamedColor, usize)],
     route: &str,
 ) -> Markup {
     let content = html! {
         .tag_list_page {
+            // Only baked items have colors; the facet is left out until some do
+            @if !colors.is_empty() {
+                h2 { "Colors" }
+                ul.color_facet {
+                    @for (color, count) in colors {
+                        li.color_facet_item {
+                            a href=(format!("/{}/booru/search/{}/1", site_prefix, encode(&format!("(color \"{}\")", color.name())))) {
+                                span.color_swatch style=(format!("background-color: {}", color.swatch())) {}
+                                span.tag_name { (color.name()) }
+                                span.tag_count { " (" (count) ")" }
+                            }
+                        }
+                    }
+                }
+            }
             h2 { "Tags" }
             ul.tag_list {
                 @for tag in tag_order {
@@ -189,6 +216,15 @@ pub fn render_tags_page(
                             span.tag_name { (tag) }
                             span.tag_count { " (" (tags.get(tag).unwrap_or(&0)) ")" }
                         }
+                        @if let Some(implied) = implies.get(tag) {
+                            span.tag_implies {
+                                " implies: "
+                                @for (i, implied) in implied.iter().enumerate() {
+                                    @if i > 0 { ", " }
+                                    a href=(format!("/{}/booru/tag/{}", site_prefix, encode(implied))) { (implied) }
+                                }
+                            }
+                        }
                     }
                 }
             }
@@ -212,7 +248,9 @@ pub fn render_archive_page(site_prefix: &str, archive: &Vec<ArchiveYear>, route:
                 @for month in archive_months {
                     li.archive_item {
                         a href=(format!("/{}/booru/archive/{}/{:02}", site_prefix, month.year, month.month)) {
-                            span.archive_date { (format!("{}/{:02}", month.year, month.month)) }
+                            span.archive_date {
+                                @if month.month == 0 { "date unknown" } @else { (format!("{}/{:02}", month.year, month.month)) }
+                            }
                             span.archive_count { " (" (month.count) ")" }
                         }
                     }
@@ -263,17 +301,19 @@ pub fn render_slideshow_detail_page(
                     FileCrawlType::Image { filename, downloaded, .. } => {
                         @if *downloaded {
                             figure.post_figure {
-                                img.post_image src=(format!("/{}/assets/{}", asset_site, filename)) alt=(item.title) {}
+                                (super::responsive_image(asset_site, filename, &item.display_variants(file.get_key()), super::DISPLAY_SIZES, &item.title, Some("post_image"), item.media_for(file).and_then(|m| m.dimensions())))
+                                (super::file_info(item, file))
                             }
                         }
                     }
                     FileCrawlType::Video { filename, downloaded, .. } => {
                         @if *downloaded {
-                            @let coerced_filename = filename.as_mp4();
+                            @let playable_filename = item.playable_video_path(file.get_key(), filename);
                             figure.post_figure {
-                                video.post_video controls autoplay {
-                                    source src=(format!("/{}/assets/{}", asset_site, coerced_filename)) {}
+                                video.post_video controls autoplay data-storyboard=[super::storyboard_url(item, file)] data-hls=[super::hls_url(item, file)] {
+                                    source src=(format!("/{}/assets/{}", asset_site, playable_filename)) {}
                                 }
+                                (super::file_info(item, file))
                             }
                         }
                     }
@@ -363,10 +403,10 @@ pub fn render_slideshow_full_page(
                     }
                     FileCrawlType::Video { filename, downloaded, .. } => {
                         @if *downloaded {
-                            @let coerced_filename = filename.as_mp4();
+                            @let playable_filename = item.playable_video_path(file.get_key(), filename);
                             figure.post_figure {
-                                video.post_video controls autoplay {
-                                    source src=(format!("/{}/assets/{}", asset_site, coerced_filename)) {}
+                                video.post_video controls autoplay data-storyboard=[super::storyboard_url(item, file)] data-hls=[super::hls_url(item, file)] {
+                                    source src=(format!("/{}/assets/{}", asset_site, playable_filename)) {}
                                 }
                             }
                         }
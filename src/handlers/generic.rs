@@ -1,22 +1,26 @@
 use std::collections::HashMap;
 
 use actix_web::{get, web, HttpResponse, Responder};
-use chrono::{DateTime, Datelike, TimeZone, Utc};
 use indexmap::IndexMap;
 use itertools::Itertools;
+use maud::{html, Markup};
 use rand::{seq::SliceRandom, SeedableRng};
 use serde::Deserialize;
 use urlencoding::encode;
 
 use crate::{
+    bake::{ThumbnailService, ThumbnailStatus},
+    color::NamedColor,
+    dupes::SharedDuplicateIndex,
     handlers::WorkDirPrefix,
-    search::{evaluate_search_expr, parse_search_expr},
+    search::{evaluate_search_expr, parse_query, SearchExpr},
+    search_index::SharedSearchIndex,
     site::{CrawlItem, CrawlTag, FileCrawlType},
 };
 use urlencoding::decode;
 
 use super::{
-    ListingPageConfig, ListingPageMode, ListingPageOrdering, PageUrlState, SiteRenderer, SiteRendererType,
+    archive_bucket, ListingPageConfig, ListingPageMode, ListingPageOrdering, PageUrlState, SiteRenderer, SiteRendererType,
     SiteSource, ViewMode,
 };
 
@@ -26,17 +30,23 @@ fn resolve_listing_page(site_source: &SiteSource, mode: &ListingPageMode) -> Vec
     match mode {
         ListingPageMode::All => items,
 
-        ListingPageMode::ByTag { tag } => items
-            .into_iter()
-            .filter(|item| item.tags.iter().map(|t| t.to_string()).any(|t| t == *tag))
-            .collect(),
+        // Matched like a `tag` search, so the tags implying `tag` count too
+        ListingPageMode::ByTag { tag } => {
+            let expr = site_source
+                .server_config()
+                .tags
+                .expand(SearchExpr::Tag(tag.clone()));
+            items
+                .into_iter()
+                .filter(|item| evaluate_search_expr(&expr, item))
+                .collect()
+        }
 
         ListingPageMode::ByMonth { year, month } => items
             .into_iter()
             .filter(|item| {
-                let date = item.source_published;
-                let date = DateTime::from_timestamp_millis(date).unwrap();
-                date.year() as u32 == *year && date.month() as u32 == *month
+                let (item_year, item_month) = archive_bucket(item.source_published);
+                item_year as u32 == *year && item_month as u32 == *month
             })
             .collect(),
 
@@ -243,10 +253,10 @@ pub async fn generic_tags_index_handler(
         None => TagSort::Count,
     };
 
+    let items = site_source.all_items();
     let tags = {
-        let items = site_source.all_items();
         let mut tags: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
-        for item in items {
+        for item in &items {
             for tag in &item.tags {
                 let tag = match tag {
                     CrawlTag::Simple(x) => x,
@@ -258,6 +268,23 @@ pub async fn generic_tags_index_handler(
         tags
     };
 
+    // How many items have each color among their dominant ones
+    let colors = {
+        let mut colors: std::collections::BTreeMap<NamedColor, usize> = Default::default();
+        for item in &items {
+            let item_colors = item
+                .media
+                .values()
+                .flat_map(|metadata| &metadata.palette)
+                .map(|color| NamedColor::of(*color))
+                .collect::<std::collections::BTreeSet<_>>();
+            for color in item_colors {
+                *colors.entry(color).or_insert(0) += 1;
+            }
+        }
+        colors.into_iter().collect::<Vec<_>>()
+    };
+
     let tag_order: Vec<String> = {
         let mut tag_names = tags.keys().cloned().collect::<Vec<_>>();
         match sort {
@@ -271,7 +298,15 @@ pub async fn generic_tags_index_handler(
         tag_names
     };
 
-    renderer.render_tags_page(&site_prefix, &tags, &tag_order, &format!("/tags"))
+    // What each tag implies under the server's tag rules
+    let server_config = site_source.server_config();
+    let implies: std::collections::HashMap<String, Vec<String>> = tag_order
+        .iter()
+        .map(|tag| (tag.clone(), server_config.tags.implies(tag).to_vec()))
+        .filter(|(_, implied)| !implied.is_empty())
+        .collect();
+
+    renderer.render_tags_page(&site_prefix, &tags, &tag_order, &implies, &colors, &format!("/tags"))
 }
 #[get("/tag/{tag}")]
 pub async fn generic_tag_handler(
@@ -352,12 +387,9 @@ pub async fn generic_archive_index_handler(
         let mut archive: HashMap<(i32, u8), usize> = HashMap::new();
 
         for item in items {
-            let time = Utc
-                .timestamp_millis_opt(item.source_published as i64)
-                .unwrap();
-            let year = time.year();
-            let month = time.month() as u8;
-            *archive.entry((year, month)).or_insert(0) += 1;
+            *archive
+                .entry(archive_bucket(item.source_published))
+                .or_insert(0) += 1;
         }
 
         archive
@@ -459,13 +491,17 @@ struct ViewModeQuery {
 pub async fn generic_detail_handler(
     renderer: web::Data<SiteRendererType>,
     site_source: web::Data<SiteSource>,
+    duplicates: web::Data<SharedDuplicateIndex>,
     path: web::Path<(String, String)>,
     query: web::Query<ViewModeQuery>,
 ) -> impl Responder {
     let (id, file_id) = path.into_inner();
     let renderer = renderer.into_inner();
     let site_prefix = site_source.slug();
-    let item = site_source.get_item(&id).unwrap();
+    let mut item = site_source.get_item(&id).unwrap();
+    if let Some(item_ref) = site_source.item_ref(&id) {
+        item.also_appears_in = duplicates.get().copies_of(&item_ref);
+    }
 
     let file = { item.flat_files().get(&file_id).unwrap().clone() };
     let is_full = query.view.as_deref() == Some("full");
@@ -509,6 +545,36 @@ pub async fn serve_crawled_json(
         .body(json))
 }
 
+/// Seconds between polls while a thumbnail is being generated
+const THUMBNAIL_POLL_SECONDS: u32 = 3;
+
+/// What a pending thumbnail swaps itself for: the thumbnail once it exists,
+/// itself again while it is still queued, or the usual "No thumbnail".
+#[get("/thumbnail/{id}")]
+pub async fn thumbnail_fragment_handler(
+    site_source: web::Data<SiteSource>,
+    thumbnails: web::Data<ThumbnailService>,
+    path: web::Path<String>,
+) -> Result<Markup, actix_web::Error> {
+    let id = path.into_inner();
+    let item = site_source
+        .get_item(&id)
+        .ok_or_else(|| actix_web::error::ErrorNotFound("No such item"))?;
+
+    Ok(match thumbnails.request(&item) {
+        ThumbnailStatus::Ready => match item.thumbnail_path() {
+            Some(thumb) => super::thumbnail_media(&item, &thumb),
+            None => html! { p.no_thumbnail { "No thumbnail" } },
+        },
+        ThumbnailStatus::Pending => super::pending_thumbnail_element(
+            &item,
+            &id,
+            &format!("load delay:{}s", THUMBNAIL_POLL_SECONDS),
+        ),
+        ThumbnailStatus::Unavailable => html! { p.no_thumbnail { "No thumbnail" } },
+    })
+}
+
 // Slideshow handlers
 #[get("/latest/slideshow/{i}")]
 pub async fn generic_latest_slideshow_redirect_handler(
@@ -827,6 +893,7 @@ pub async fn generic_oldest_slideshow_handler(
 pub async fn generic_search_slideshow_redirect_handler(
     renderer: web::Data<SiteRendererType>,
     site_source: web::Data<SiteSource>,
+    search_index: web::Data<SharedSearchIndex>,
     path: web::Path<(String, usize)>,
 ) -> impl Responder {
     let (encoded_query, i) = path.into_inner();
@@ -842,24 +909,16 @@ pub async fn generic_search_slideshow_redirect_handler(
         }
     };
 
-    // Parse the s-expression
-    let expr = match parse_search_expr(&decoded_query) {
-        Ok(expr) => expr,
+    // Parse the query, in either syntax
+    let expr = match parse_query(&decoded_query) {
+        Ok(expr) => site_source.server_config().tags.expand(expr),
         Err(e) => {
             return HttpResponse::BadRequest().body(format!("Parse error: {}", e));
         }
     };
 
-    // Get all items and filter
-    let all_items: Vec<CrawlItem> = site_source.all_items();
-    let filtered_items: Vec<CrawlItem> = all_items
-        .into_iter()
-        .filter(|item| evaluate_search_expr(&expr, item))
-        .collect();
-
-    // Sort by source_published (newest first)
-    let mut sorted_items = filtered_items;
-    sorted_items.sort_by_key(|item| -item.source_published);
+    let index = search_index.get();
+    let sorted_items = index.search(&expr);
     
     if sorted_items.is_empty() || i == 0 || i > sorted_items.len() {
         return HttpResponse::NotFound().body("No items found");
@@ -888,6 +947,7 @@ pub async fn generic_search_slideshow_redirect_handler(
 pub async fn generic_search_slideshow_handler(
     renderer: web::Data<SiteRendererType>,
     site_source: web::Data<SiteSource>,
+    search_index: web::Data<SharedSearchIndex>,
     path: web::Path<(String, usize, String)>,
     query: web::Query<ViewModeQuery>,
 ) -> impl Responder {
@@ -916,24 +976,16 @@ pub async fn generic_search_slideshow_handler(
         }
     };
 
-    // Parse the s-expression
-    let expr = match parse_search_expr(&decoded_query) {
-        Ok(expr) => expr,
+    // Parse the query, in either syntax
+    let expr = match parse_query(&decoded_query) {
+        Ok(expr) => site_source.server_config().tags.expand(expr),
         Err(e) => {
             return HttpResponse::BadRequest().body(format!("Parse error: {}", e));
         }
     };
 
-    // Get all items and filter
-    let all_items: Vec<CrawlItem> = site_source.all_items();
-    let filtered_items: Vec<CrawlItem> = all_items
-        .into_iter()
-        .filter(|item| evaluate_search_expr(&expr, item))
-        .collect();
-
-    // Sort by source_published (newest first)
-    let mut sorted_items = filtered_items;
-    sorted_items.sort_by_key(|item| -item.source_published);
+    let index = search_index.get();
+    let sorted_items = index.search(&expr);
 
     if sorted_items.is_empty() {
         return HttpResponse::NotFound().body("No items found");
@@ -976,6 +1028,7 @@ pub async fn generic_search_slideshow_handler(
     let config = ListingPageConfig {
         mode: ListingPageMode::Search {
             query: encoded_query.clone(),
+            compiled: None,
         },
         ordering: ListingPageOrdering::NewestFirst,
         page: 1,
@@ -1,8 +1,10 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
-use chrono::Utc;
+use chrono::{Datelike, Utc};
+use itertools::Itertools;
 use maud::{html, Markup, PreEscaped};
 
+mod admin;
 mod blog;
 mod booru;
 mod common;
@@ -11,12 +13,18 @@ mod reddit;
 mod search;
 mod url_state;
 
+pub use admin::{reprocessor_diff_form_handler, reprocessor_diff_handler};
 pub use common::*;
 pub use generic::*;
 pub use reddit::media_viewer_fragment_handler;
 pub use search::{search_form_handler, search_results_handler};
 pub use url_state::{PageUrlState, PageType, ViewMode};
 
+use crate::bake::ImageVariant;
+use crate::collections::GetKey;
+use crate::color::NamedColor;
+use crate::dupes::{ItemRef, SharedDuplicateIndex};
+use crate::server_config::ServerConfig;
 use crate::site::{CrawlItem, FileCrawlType};
 
 // Shared components
@@ -50,16 +58,212 @@ impl maud::Render for Fa {
     }
 }
 
-pub trait ExtensionFix {
-    fn as_mp4(&self) -> String;
+/// URL of the storyboard track for a video file, if one has been baked.
+pub fn storyboard_url(item: &CrawlItem, file: &FileCrawlType) -> Option<String> {
+    if !file.is_video() {
+        return None;
+    }
+    let vtt = item.storyboard_vtt_path(file.get_key())?;
+    Some(format!("/{}/assets/{}", item.site_settings.site_slug, vtt))
+}
+
+/// URL of the HLS master playlist for a video file, if one has been packaged.
+pub fn hls_url(item: &CrawlItem, file: &FileCrawlType) -> Option<String> {
+    if !file.is_video() {
+        return None;
+    }
+    let master = item.hls_master_path(file.get_key())?;
+    Some(format!("/{}/assets/{}", item.site_settings.site_slug, master))
+}
+
+/// URL of the Deep Zoom descriptor for an image file, if it was large enough
+/// to be tiled.
+pub fn deep_zoom_url(item: &CrawlItem, file: &FileCrawlType) -> Option<String> {
+    if !file.is_image() {
+        return None;
+    }
+    let dzi = item.deep_zoom_dzi_path(file.get_key())?;
+    Some(format!("/{}/assets/{}", item.site_settings.site_slug, dzi))
+}
+
+/// URL of the storyboard track for the video an item's thumbnail is cut from.
+pub fn preview_storyboard_url(item: &CrawlItem) -> Option<String> {
+    storyboard_url(item, &item.first_thumbnailable_file()?)
+}
+
+/// `sizes` for thumbnails in listing grids and cards
+pub const PREVIEW_SIZES: &str = "(max-width: 900px) 50vw, 320px";
+/// `sizes` for images shown in the body of a detail page
+pub const DISPLAY_SIZES: &str = "(max-width: 1200px) 100vw, 1200px";
+
+/// An image that lets the browser pick among bake's resized variants, one
+/// `<source>` per format, falling back to `fallback` when none exist yet.
+pub fn responsive_image(
+    asset_site: &str,
+    fallback: &str,
+    variants: &[ImageVariant],
+    sizes: &str,
+    alt: &str,
+    class: Option<&str>,
+    dimensions: Option<(u32, u32)>,
+) -> Markup {
+    let by_format = variants
+        .iter()
+        .group_by(|variant| variant.format)
+        .into_iter()
+        .map(|(format, group)| {
+            let srcset = group
+                .map(|variant| format!("/{}/assets/{} {}w", asset_site, variant.path, variant.width))
+                .join(", ");
+            (format, srcset)
+        })
+        .collect::<Vec<_>>();
+
+    let img = html! {
+        img class=[class] src=(format!("/{}/assets/{}", asset_site, fallback)) alt=(alt)
+            width=[dimensions.map(|(w, _)| w)] height=[dimensions.map(|(_, h)| h)] {}
+    };
+
+    if by_format.is_empty() {
+        return img;
+    }
+
+    html! {
+        picture {
+            @for (format, srcset) in &by_format {
+                source type=(format.mime_type()) srcset=(srcset) sizes=(sizes) {}
+            }
+            (img)
+        }
+    }
+}
+
+/// Format a length in seconds as `m:ss` or `h:mm:ss`.
+pub fn format_duration(seconds: f64) -> String {
+    let total = seconds.round() as u64;
+    let (hours, minutes, seconds) = (total / 3600, total / 60 % 60, total % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+/// Dimensions of the image a listing thumbnail is made from, so the browser
+/// can reserve space for it before it loads.
+pub fn preview_dimensions(item: &CrawlItem) -> Option<(u32, u32)> {
+    item.media_for(&item.preview_source_image()?)?.dimensions()
+}
+
+/// A length badge for cards whose thumbnail comes from a video.
+pub fn duration_badge(item: &CrawlItem) -> Markup {
+    let duration = item
+        .first_thumbnailable_file()
+        .filter(|file| file.is_video())
+        .and_then(|file| item.media_for(&file)?.duration);
+    html! {
+        @if let Some(duration) = duration {
+            span.duration_badge { (format_duration(duration)) }
+        }
+    }
+}
+
+/// A caption summarising a file's extracted metadata on detail pages.
+pub fn file_info(item: &CrawlItem, file: &FileCrawlType) -> Markup {
+    let Some(metadata) = item.media_for(file) else {
+        return html! {};
+    };
+
+    let mut parts = vec![];
+    if let Some((width, height)) = metadata.dimensions() {
+        parts.push(format!("{}×{}", width, height));
+    }
+    if let Some(duration) = metadata.duration {
+        parts.push(format_duration(duration));
+    }
+    if let Some(frame_rate) = metadata.frame_rate {
+        parts.push(format!("{:.3} fps", frame_rate).replace(".000", ""));
+    }
+    if let Some(codec) = &metadata.video_codec {
+        parts.push(codec.clone());
+    }
+    parts.push(crate::bake::gc::format_bytes(metadata.size()));
+
+    html! {
+        figcaption.file_info { (parts.join(" · ")) }
+    }
+}
+
+/// Inline style showing an item's baked placeholder behind its thumbnail
+/// until the thumbnail loads.
+pub fn placeholder_style(item: &CrawlItem) -> Option<String> {
+    item.placeholder
+        .as_ref()
+        .map(|uri| format!("background-image: url('{}')", uri))
+}
+
+/// An item's thumbnail on its own, as listing cards show it.
+pub fn thumbnail_media(item: &CrawlItem, thumb: &str) -> Markup {
+    let asset_site = &item.site_settings.site_slug;
+    html! {
+        @if thumb.ends_with(".mp4") {
+            video.thumbnail_preview autoplay loop muted playsinline {
+                source src=(format!("/{}/assets/{}", asset_site, thumb)) {}
+            }
+        } @else {
+            (responsive_image(asset_site, thumb, &item.preview_variants(), PREVIEW_SIZES, &item.title, None, preview_dimensions(item)))
+        }
+    }
+}
+
+/// Stand-in for a thumbnail bake hasn't generated yet. Asks the server to
+/// generate it as soon as it is shown, then polls until it is ready and
+/// swaps itself for it. `None` if the item has nothing to make one from.
+pub fn pending_thumbnail(item: &CrawlItem) -> Option<Markup> {
+    if !item.previews.is_empty() {
+        return None;
+    }
+    item.first_thumbnailable_file()?;
+
+    // Always asked of the item's own site, so `all` listings don't need the
+    // namespaced key to be routable
+    let key = item.original_key.as_deref().unwrap_or(&item.key);
+    Some(pending_thumbnail_element(item, key, "load"))
+}
+
+fn pending_thumbnail_element(item: &CrawlItem, key: &str, trigger: &str) -> Markup {
+    html! {
+        .pending_thumbnail
+            hx-get=(format!("/{}/thumbnail/{}", item.site_settings.site_slug, urlencoding::encode(key)))
+            hx-trigger=(trigger)
+            hx-swap="outerHTML" {
+            "Generating thumbnail…"
+        }
+    }
 }
 
-impl ExtensionFix for std::string::String {
-    fn as_mp4(&self) -> String {
-        PathBuf::from(self)
-            .with_extension("mp4")
-            .to_string_lossy()
-            .to_string()
+/// Links to the other copies of an item, in the same renderer. Empty unless
+/// the detail handler found duplicates.
+pub fn also_appears_in(item: &CrawlItem, rendering_prefix: &str) -> Markup {
+    html! {
+        @if !item.also_appears_in.is_empty() {
+            aside.also_appears_in {
+                h3 { "Also appears in" }
+                ul {
+                    @for copy in &item.also_appears_in {
+                        li {
+                            a href=(format!("/{}/{}/item/{}", copy.item.site_slug, rendering_prefix, urlencoding::encode(&copy.item.item_key))) {
+                                span.also_appears_in_site { (copy.item.site_slug) }
+                                " "
+                                (copy.title)
+                            }
+                            " "
+                            (published_date(copy.source_published))
+                        }
+                    }
+                }
+            }
+        }
     }
 }
 
@@ -72,7 +276,23 @@ pub const PRERENDER_RULES: &str = r#"{
     ]
 }"#;
 
+/// The archive month an item is listed under, from its `source_published`.
+/// Items with no known publication date share a bucket of their own, year 0
+/// month 0, which sorts after every real month.
+pub fn archive_bucket(source_published: i64) -> (i32, u8) {
+    if source_published == 0 {
+        return (0, 0);
+    }
+    match chrono::DateTime::from_timestamp_millis(source_published) {
+        Some(time) => (time.year(), time.month() as u8),
+        None => (0, 0),
+    }
+}
+
 pub fn format_year_month(year: i32, month: u8) -> String {
+    if month == 0 {
+        return "Date unknown".to_string();
+    }
     format!(
         "{} {}",
         match month {
@@ -94,7 +314,28 @@ pub fn format_year_month(year: i32, month: u8) -> String {
     )
 }
 
+/// An item's publication date, or "date unknown" when there isn't one.
+pub fn published_date(source_published: i64) -> Markup {
+    let time = chrono::DateTime::from_timestamp_millis(source_published)
+        .filter(|_| source_published != 0);
+    match time {
+        Some(time) => html! {
+            time datetime=(time.to_rfc3339()) {
+                (time.format("%B %d, %Y"))
+            }
+        },
+        None => html! {
+            span.date_unknown { "date unknown" }
+        },
+    }
+}
+
 pub fn timeago(timestamp: u64) -> Markup {
+    if timestamp == 0 {
+        return html! {
+            span.date_unknown { "date unknown" }
+        };
+    }
     let dt =
         chrono::DateTime::from_timestamp_millis(timestamp as i64).unwrap_or_else(|| Utc::now());
 
@@ -148,6 +389,8 @@ pub fn scripts() -> Markup {
         (Css("https://cdnjs.cloudflare.com/ajax/libs/font-awesome/4.4.0/css/font-awesome.css"))
         script src="/res/htmx.min.js" {}
         script src="/res/detail_page.js" {}
+        script src="https://cdnjs.cloudflare.com/ajax/libs/hls.js/1.5.13/hls.min.js" {}
+        script src="/res/media.js" {}
         script src="/res/idiomorph.min.js" {}
         script src="/res/idiomorph-ext.min.js" {}
         script type="speculationrules" {
@@ -257,8 +500,12 @@ pub type ThreadSafeWorkDir = crate::thread_safe_work_dir::ThreadSafeWorkDir;
 pub enum SiteSource {
     /// A single site's WorkDir
     Single(ThreadSafeWorkDir),
-    /// All sites aggregated together
-    All { workdirs: Vec<ThreadSafeWorkDir> },
+    /// All sites aggregated together. When `collapse_duplicates` is set, only
+    /// the earliest published copy of each duplicate cluster is listed.
+    All {
+        workdirs: Vec<ThreadSafeWorkDir>,
+        collapse_duplicates: Option<SharedDuplicateIndex>,
+    },
 }
 
 impl SiteSource {
@@ -280,21 +527,65 @@ impl SiteSource {
         }
     }
 
-    /// Returns all items with SiteSettings already attached.
+    /// The server config the sites were loaded with, for the tag rules
+    pub fn server_config(&self) -> Arc<ServerConfig> {
+        let workdir = match self {
+            SiteSource::Single(workdir) => Some(workdir),
+            SiteSource::All { workdirs, .. } => workdirs.first(),
+        };
+        match workdir {
+            Some(workdir) => workdir.work_dir.read().unwrap().server_config.clone(),
+            None => Arc::default(),
+        }
+    }
+
+    /// When each of the source's WorkDirs was last loaded, to tell whether
+    /// anything built from its items is out of date
+    pub fn loaded_at(&self) -> Vec<u128> {
+        let workdirs = match self {
+            SiteSource::Single(workdir) => std::slice::from_ref(workdir),
+            SiteSource::All { workdirs, .. } => workdirs.as_slice(),
+        };
+        workdirs
+            .iter()
+            .map(|workdir| workdir.work_dir.read().unwrap().loaded_at)
+            .collect()
+    }
+
+    /// Returns all items with SiteSettings already attached, except hidden ones.
     /// For All variant, items have namespaced keys: "{site_slug}/{item.key}"
     pub fn all_items(&self) -> Vec<CrawlItem> {
         match self {
             SiteSource::Single(workdir) => {
                 let wd = workdir.work_dir.read().unwrap();
-                wd.crawled.items.values().cloned().collect()
+                wd.crawled
+                    .items
+                    .values()
+                    .filter(|item| !item.hidden)
+                    .cloned()
+                    .collect()
             }
-            SiteSource::All { workdirs } => {
+            SiteSource::All {
+                workdirs,
+                collapse_duplicates,
+            } => {
+                let duplicates = collapse_duplicates.as_ref().map(|index| index.get());
                 let mut all_items = Vec::new();
                 for workdir in workdirs {
                     let wd = workdir.work_dir.read().unwrap();
                     let site_slug = &wd.config.slug;
-                    for item in wd.crawled.items.values() {
+                    for item in wd
+                        .items_for_all_view()
+                        .values()
+                        .filter(|item| !item.hidden)
+                    {
+                        if let Some(duplicates) = &duplicates {
+                            if !duplicates.is_canonical(&ItemRef::new(site_slug, &item.key)) {
+                                continue;
+                            }
+                        }
                         let mut namespaced_item = item.clone();
+                        namespaced_item.original_key = Some(item.key.clone());
                         // Namespace the key to avoid collisions
                         namespaced_item.key = format!("{}/{}", site_slug, item.key);
                         all_items.push(namespaced_item);
@@ -312,7 +603,7 @@ impl SiteSource {
                 let wd = workdir.work_dir.read().unwrap();
                 wd.crawled.items.get(key).cloned()
             }
-            SiteSource::All { workdirs } => {
+            SiteSource::All { workdirs, .. } => {
                 // Parse the namespaced key: "site_slug/item_key"
                 let parts: Vec<&str> = key.splitn(2, '/').collect();
                 if parts.len() != 2 {
@@ -323,8 +614,9 @@ impl SiteSource {
                 for workdir in workdirs {
                     let wd = workdir.work_dir.read().unwrap();
                     if wd.config.slug == site_slug {
-                        if let Some(item) = wd.crawled.items.get(item_key) {
+                        if let Some(item) = wd.items_for_all_view().get(item_key) {
                             let mut namespaced_item = item.clone();
+                            namespaced_item.original_key = Some(item.key.clone());
                             namespaced_item.key = key.to_string();
                             return Some(namespaced_item);
                         }
@@ -335,6 +627,17 @@ impl SiteSource {
         }
     }
 
+    /// Which site and original key an item key refers to.
+    pub fn item_ref(&self, key: &str) -> Option<ItemRef> {
+        match self {
+            SiteSource::Single(_) => Some(ItemRef::new(&self.slug(), key)),
+            SiteSource::All { .. } => {
+                let (site_slug, item_key) = key.split_once('/')?;
+                Some(ItemRef::new(site_slug, item_key))
+            }
+        }
+    }
+
     /// Get the work directory path for a given site slug (for thumbnail lookups)
     pub fn get_work_dir_path(&self, site_slug: &str) -> Option<PathBuf> {
         match self {
@@ -346,7 +649,7 @@ impl SiteSource {
                     None
                 }
             }
-            SiteSource::All { workdirs } => {
+            SiteSource::All { workdirs, .. } => {
                 for workdir in workdirs {
                     let wd = workdir.work_dir.read().unwrap();
                     if wd.config.slug == site_slug {
@@ -384,7 +687,12 @@ pub enum ListingPageMode {
     All,
     ByTag { tag: String },
     ByMonth { year: u32, month: u32 },
-    Search { query: String },
+    /// `compiled` is the s-expression a search in the infix syntax compiled
+    /// to, shown with its results
+    Search {
+        query: String,
+        compiled: Option<String>,
+    },
 }
 
 #[derive(Clone, Debug)]
@@ -429,7 +737,7 @@ impl PaginatorPrefix for ListingPageConfig {
                     site_prefix, rendering_prefix, year, month
                 )
             }
-            ListingPageMode::Search { query } => {
+            ListingPageMode::Search { query, .. } => {
                 format!("/{}/{}/search/{}", site_prefix, rendering_prefix, query)
             }
         }
@@ -437,6 +745,16 @@ impl PaginatorPrefix for ListingPageConfig {
 }
 
 
+/// The s-expression an infix search compiled to, so people can learn the
+/// longer syntax.
+pub fn compiled_search(mode: &ListingPageMode) -> Markup {
+    html! {
+        @if let ListingPageMode::Search { compiled: Some(compiled), .. } = mode {
+            p.compiled_search { "As an s-expression: " code { (compiled) } }
+        }
+    }
+}
+
 /// Calculate the global 1-indexed position of an item in a listing page
 pub fn calculate_item_index(config: &ListingPageConfig, position_in_page: usize) -> usize {
     (config.page - 1) * config.per_page + position_in_page + 1
@@ -469,6 +787,8 @@ pub trait SiteRenderer {
         site_prefix: &str,
         tags: &HashMap<String, usize>,
         tag_order: &Vec<String>,
+        implies: &HashMap<String, Vec<String>>,
+        colors: &[(NamedColor, usize)],
         route: &str,
     ) -> Markup;
     fn render_archive_page(
@@ -544,13 +864,19 @@ impl SiteRenderer for SiteRendererType {
         site_prefix: &str,
         tags: &HashMap<String, usize>,
         tag_order: &Vec<String>,
+        implies: &HashMap<String, Vec<String>>,
+        colors: &[(NamedColor, usize)],
         route: &str,
     ) -> Markup {
         match self {
-            SiteRendererType::Blog => blog::render_tags_page(site_prefix, tags, tag_order, route),
-            SiteRendererType::Booru => booru::render_tags_page(site_prefix, tags, tag_order, route),
+            SiteRendererType::Blog => {
+                blog::render_tags_page(site_prefix, tags, tag_order, implies, route)
+            }
+            SiteRendererType::Booru => {
+                booru::render_tags_page(site_prefix, tags, tag_order, implies, colors, route)
+            }
             SiteRendererType::Reddit => {
-                reddit::render_tags_page(site_prefix, tags, tag_order, route)
+                reddit::render_tags_page(site_prefix, tags, tag_order, implies, route)
             }
         }
     }
@@ -10,7 +10,7 @@ use super::{
 };
 use crate::collections::GetKey;
 use crate::handlers::{
-    calculate_item_index, format_year_month, timeago, ExtensionFix, Fa,
+    calculate_item_index, format_year_month, timeago, Fa,
     PaginatorPrefix,
 };
 use crate::site::{CrawlItem, CrawlTag, FileCrawlType};
@@ -139,16 +139,21 @@ fn reddit_post_card(
 
                 @if let Some(thumb) = item.thumbnail_path() {
                     @if thumb.ends_with(".mp4") {
-                        .post_preview {
+                        .post_preview style=[super::placeholder_style(item)] {
                             video.thumbnail_preview width="320" height="auto" autoplay loop muted playsinline {
                                 source src=(format!("/{}/assets/{}", asset_site, thumb)) {}
                             }
+                            (super::duration_badge(item))
                         }
                     } @else {
-                        .post_preview {
-                            img src=(format!("/{}/assets/{}", asset_site, thumb)) alt=(item.title) {}
+                        .post_preview style=[super::placeholder_style(item)] {
+                            (super::responsive_image(asset_site, &thumb, &item.preview_variants(), super::PREVIEW_SIZES, &item.title, None, super::preview_dimensions(item)))
                         }
                     }
+                } @else if let Some(pending) = super::pending_thumbnail(item) {
+                    .post_preview style=[super::placeholder_style(item)] {
+                        (pending)
+                    }
                 }
             }
         }
@@ -170,13 +175,14 @@ pub fn render_listing_page(
             ListingPageOrdering::Random => "Random Posts".to_string(),
         },
         ListingPageMode::ByTag { tag } => format!("Posts tagged \"{}\"", tag),
+        ListingPageMode::ByMonth { month: 0, .. } => "Posts with an unknown date".to_string(),
         ListingPageMode::ByMonth { year, month } => {
             format!(
                 "Posts from {}",
                 format_year_month(*year as i32, *month as u8)
             )
         }
-        ListingPageMode::Search { query } => format!("Search: {}", query),
+        ListingPageMode::Search { query, .. } => format!("Search: {}", query),
     };
 
     let content = html! {
@@ -184,6 +190,7 @@ pub fn render_listing_page(
             @if !title.is_empty() && !matches!(config.mode, ListingPageMode::All) {
                 h1.page_title { (title) }
             }
+            (super::compiled_search(&config.mode))
             .reddit_posts {
                 @for (idx, item) in items.iter().enumerate() {
                     (reddit_post_card(item, site_prefix, &config, idx))
@@ -306,7 +313,8 @@ pub fn render_media_viewer(
                 FileCrawlType::Image { filename, downloaded, .. } => {
                     @if *downloaded {
                         figure.post_figure {
-                            img.post_image src=(format!("/{}/assets/{}", asset_site, filename)) alt=(item.title) {}
+                            (super::responsive_image(asset_site, filename, &item.display_variants(file.get_key()), super::DISPLAY_SIZES, &item.title, Some("post_image"), item.media_for(file).and_then(|m| m.dimensions())))
+                            (super::file_info(item, file))
                             a.fullscreen_click_target data-toggle-full data-replace-history href=(toggle_url) {}
                             (post_file_paginator(item, &file, &url_state))
                         }
@@ -314,11 +322,12 @@ pub fn render_media_viewer(
                 }
                 FileCrawlType::Video { filename, downloaded, .. } => {
                     @if *downloaded {
-                        @let coerced_filename = filename.as_mp4();
+                        @let playable_filename = item.playable_video_path(file.get_key(), filename);
                         figure.post_figure {
-                            video.post_video controls autoplay {
-                                source src=(format!("/{}/assets/{}", asset_site, coerced_filename)) {}
+                            video.post_video controls autoplay data-storyboard=[super::storyboard_url(item, file)] data-hls=[super::hls_url(item, file)] {
+                                source src=(format!("/{}/assets/{}", asset_site, playable_filename)) {}
                             }
+                            (super::file_info(item, file))
                             a.fullscreen_link data-toggle-full data-replace-history href=(toggle_url) {
                                 (Fa("expand"))
                             }
@@ -373,17 +382,25 @@ pub fn render_full_media_viewer(
                 FileCrawlType::Image { filename, downloaded, .. } => {
                     @if *downloaded {
                         figure.post_figure {
-                            img.post_image src=(format!("/{}/assets/{}", asset_site, filename)) alt=(item.title) {}
+                            @if let Some(dzi) = super::deep_zoom_url(item, file) {
+                                // Tiles are fetched as the viewer pans and zooms; the display
+                                // image stands in until the viewer has loaded
+                                .deep_zoom_viewer data-dzi=(dzi) {
+                                    (super::responsive_image(asset_site, filename, &item.display_variants(file.get_key()), "100vw", &item.title, Some("post_image"), item.media_for(file).and_then(|m| m.dimensions())))
+                                }
+                            } @else {
+                                img.post_image src=(format!("/{}/assets/{}", asset_site, filename)) alt=(item.title) {}
+                            }
                             (post_file_paginator(item, &file, &url_state.with_view_mode(ViewMode::Full)))
                         }
                     }
                 }
                 FileCrawlType::Video { filename, downloaded, .. } => {
                     @if *downloaded {
-                        @let coerced_filename = filename.as_mp4();
+                        @let playable_filename = item.playable_video_path(file.get_key(), filename);
                         figure.post_figure {
-                            video.post_video controls autoplay {
-                                source src=(format!("/{}/assets/{}", asset_site, coerced_filename)) {}
+                            video.post_video controls autoplay data-storyboard=[super::storyboard_url(item, file)] data-hls=[super::hls_url(item, file)] {
+                                source src=(format!("/{}/assets/{}", asset_site, playable_filename)) {}
                             }
                             (post_file_paginator(item, &file, &url_state.with_view_mode(ViewMode::Full)))
                         }
@@ -460,6 +477,7 @@ pub fn render_detail_page(
                     "Source: "
                     a href=(item.url) { (item.url) }
                 }
+                (super::also_appears_in(item, "r"))
 
                 @if !item.meta.is_object() || !item.meta.as_object().unwrap().is_empty() {
                     .post_meta {
@@ -526,6 +544,7 @@ pub fn render_tags_page(
     site_prefix: &str,
     tags: &HashMap<String, usize>,
     tag_order: &Vec<String>,
+    implies: &HashMap<String, Vec<String>>,
     route: &str,
 ) -> Markup {
     let content = html! {
@@ -538,6 +557,15 @@ pub fn render_tags_page(
                             span.tag_name { (tag) }
                             span.tag_count { " (" (tags.get(tag).unwrap_or(&0)) ")" }
                         }
+                        @if let Some(implied) = implies.get(tag) {
+                            span.tag_implies {
+                                " implies: "
+                                @for (i, implied) in implied.iter().enumerate() {
+                                    @if i > 0 { ", " }
+                                    a href=(format!("/{}/r/tag/{}", site_prefix, encode(implied))) { (implied) }
+                                }
+                            }
+                        }
                     }
                 }
             }
@@ -7,7 +7,8 @@ use crate::handlers::{
     header, scripts, ListingPageConfig, ListingPageMode, ListingPageOrdering, SiteRenderer,
     SiteRendererType, SiteSource,
 };
-use crate::search::{evaluate_search_expr, parse_search_expr};
+use crate::search::{is_s_expression, parse_query};
+use crate::search_index::SharedSearchIndex;
 use crate::site::CrawlItem;
 
 #[derive(Deserialize)]
@@ -58,11 +59,21 @@ pub async fn search_form_handler(
                 main {
                     .search-page-container {
                         form.search-form-container method="get" action=(format!("/{}/{}/search", site_prefix, rendering_prefix)) {
-                            input.search-input type="text" name="q" value=(prefill_value) placeholder="(tag \"foobar\")" autofocus {}
+                            input.search-input type="text" name="q" value=(prefill_value) placeholder="cute -nsfw type:image" autofocus {}
                             button.search-submit type="submit" { "Search" }
                             .search-info-icon {
                                 "help"
                                 .search-tooltip {
+                                    h3 { "Quick Syntax" }
+                                    ul {
+                                        li { code { "cute" } " - items tagged cute" }
+                                        li { code { "-nsfw" } " - items not tagged nsfw" }
+                                        li { code { "\"exact phrase\"" } " - fulltext search for the phrase" }
+                                        li { code { "type:video" } ", " code { "after:\"2 weeks ago\"" } " - any function below as " code { "function:value" } }
+                                        li { code { "cat OR dog" } " - either matches; terms side by side must all match" }
+                                        li { code { "(cat OR dog) -nsfw" } " - parentheses group terms" }
+                                    }
+                                    p { "Results show the s-expression a quick search compiles to." }
                                     h3 { "Available Functions" }
                                     ul {
                                         li { code { "and" } " - all arguments must match (varargs)" }
@@ -79,6 +90,12 @@ pub async fn search_form_handler(
                                         li { code { "after" } " - items published after the given time" }
                                         li { code { "before" } " - items published before the given time" }
                                         li { code { "during" } " - items published during the given time range" }
+                                        li { code { "min-width" } " - any file at least this many pixels wide (baked items only)" }
+                                        li { code { "min-height" } " - any file at least this many pixels tall (baked items only)" }
+                                        li { code { "orientation" } " - any file \"portrait\", \"landscape\", or \"square\" (baked items only)" }
+                                        li { code { "longer-than" } " - any video longer than the given length (baked items only)" }
+                                        li { code { "shorter-than" } " - any video shorter than the given length (baked items only)" }
+                                        li { code { "color" } " - any file with a dominant color: a name like \"red\" or \"gray\", or a hex color like \"#aabbcc\" (baked items only)" }
                                     }
                                     h3 { "Time Formats (for after/before/during)" }
                                     ul {
@@ -91,14 +108,22 @@ pub async fn search_form_handler(
                                         li { "Unix ms: " code { "\"1704067200000\"" } }
                                     }
                                     p.timezone-note { "Times default to US Eastern timezone." }
+                                    h3 { "Length Formats (for longer-than/shorter-than)" }
+                                    ul {
+                                        li { code { "\"90s\"" } ", " code { "\"5m\"" } ", " code { "\"1h30m\"" } ", " code { "\"1:30\"" } ", or plain seconds" }
+                                    }
                                     h3 { "Examples" }
                                     ul {
+                                        li { code { "cute -nsfw type:video site:r-aww after:\"2 weeks ago\"" } }
                                         li { code { "(tag \"foobar\")" } }
                                         li { code { "(and (tag \"cute\") (type \"image\"))" } }
                                         li { code { "(after \"2 weeks ago\")" } }
                                         li { code { "(during \"last month\")" } }
                                         li { code { "(during \"January\")" } }
                                         li { code { "(and (site \"r-aww\") (during \"2024\"))" } }
+                                        li { code { "(and (min-width 1920) (orientation \"landscape\"))" } }
+                                        li { code { "(longer-than \"5m\")" } }
+                                        li { code { "(and (color \"blue\") (type \"image\"))" } }
                                     }
                                 }
                             }
@@ -116,6 +141,7 @@ pub async fn search_form_handler(
 pub async fn search_results_handler(
     renderer: web::Data<SiteRendererType>,
     site_source: web::Data<SiteSource>,
+    search_index: web::Data<SharedSearchIndex>,
     path: web::Path<(String, usize)>,
 ) -> impl Responder {
     let (encoded_query, page) = path.into_inner();
@@ -135,8 +161,8 @@ pub async fn search_results_handler(
         }
     };
 
-    // Parse the s-expression
-    let expr = match parse_search_expr(&decoded_query) {
+    // Parse the query, in either syntax
+    let expr = match parse_query(&decoded_query) {
         Ok(expr) => expr,
         Err(e) => {
             return error_page(
@@ -147,38 +173,27 @@ pub async fn search_results_handler(
         }
     };
 
-    // Get all items and filter
-    let all_items: Vec<CrawlItem> = site_source.all_items();
-
-    let filtered_items: Vec<CrawlItem> = all_items
-        .into_iter()
-        .filter(|item| evaluate_search_expr(&expr, item))
-        .collect();
+    let compiled = (!is_s_expression(&decoded_query)).then(|| expr.to_string());
+    let expr = site_source.server_config().tags.expand(expr);
 
-    // Sort by source_published (newest first)
-    let mut sorted_items = filtered_items;
-    sorted_items.sort_by_key(|item| -item.source_published);
+    let index = search_index.get();
+    let matches = index.search(&expr);
 
     // Paginate
     let per_page = 15;
-    let total = sorted_items.len();
-    let start = (page - 1) * per_page;
-    let end = if start + per_page > sorted_items.len() {
-        sorted_items.len()
-    } else {
-        start + per_page
-    };
-
-    let paginated_items = if start >= sorted_items.len() {
-        Vec::new()
-    } else {
-        sorted_items[start..end].to_vec()
-    };
+    let total = matches.len();
+    let paginated_items: Vec<CrawlItem> = matches
+        .into_iter()
+        .skip((page - 1) * per_page)
+        .take(per_page)
+        .cloned()
+        .collect();
 
     // Create a ListingPageConfig for rendering
     let config = ListingPageConfig {
         mode: ListingPageMode::Search {
             query: encoded_query.clone(),
+            compiled,
         },
         ordering: ListingPageOrdering::NewestFirst,
         page,
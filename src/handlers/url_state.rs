@@ -156,7 +156,7 @@ impl PageUrlState {
                     ListingPageMode::ByMonth { year, month } => {
                         format!("/{}/archive/{}/{}/slideshow/{}", self.rendering_prefix, year, month, index)
                     }
-                    ListingPageMode::Search { query } => {
+                    ListingPageMode::Search { query, .. } => {
                         format!("/{}/search/{}/slideshow/{}", self.rendering_prefix, encode(query), index)
                     }
                 };
@@ -204,7 +204,7 @@ impl PageUrlState {
                     ListingPageMode::ByMonth { year, month } => {
                         format!("/{}/archive/{}/{}/slideshow/{}", self.rendering_prefix, year, month, index)
                     }
-                    ListingPageMode::Search { query } => {
+                    ListingPageMode::Search { query, .. } => {
                         format!("/{}/search/{}/slideshow/{}", self.rendering_prefix, encode(query), index)
                     }
                 };
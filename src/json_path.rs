@@ -0,0 +1,199 @@
+//! Paths into an item's `meta` JSON, for reprocessors that read specific
+//! values out of it.
+//!
+//! Two syntaxes are accepted:
+//!
+//! - **Dotted paths**: `meta.author`, `meta.categories[*]`, `meta.media[0].url`,
+//!   `meta["odd key"]`. The leading `meta` is optional. `[*]` selects every
+//!   element of an array (or value of an object), so one path can select
+//!   several values.
+//! - **JSON pointers** (RFC 6901): `/author`, `/media/0/url`. Always select at
+//!   most one value.
+
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+    Wildcard,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Selector {
+    Segments(Vec<Segment>),
+    Pointer(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonPath(Selector);
+
+impl JsonPath {
+    pub fn parse(path: &str) -> Result<Self, String> {
+        let path = path.trim();
+        if path.starts_with('/') {
+            return Ok(JsonPath(Selector::Pointer(path.to_string())));
+        }
+
+        let mut segments = vec![];
+        let mut key = String::new();
+        // Whether the last segment ended with `]` or `*` rather than a key,
+        // so a following `.` doesn't leave an empty key behind
+        let mut after_selector = false;
+        let mut chars = path.chars().peekable();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '.' => {
+                    if key.is_empty() && !after_selector {
+                        return Err(format!("empty key in path {:?}", path));
+                    }
+                    if !key.is_empty() {
+                        segments.push(Segment::Key(std::mem::take(&mut key)));
+                    }
+                    after_selector = false;
+                }
+                '[' => {
+                    if !key.is_empty() {
+                        segments.push(Segment::Key(std::mem::take(&mut key)));
+                    }
+                    let mut inner = String::new();
+                    loop {
+                        match chars.next() {
+                            Some(']') => break,
+                            Some(ch) => inner.push(ch),
+                            None => return Err(format!("unclosed '[' in path {:?}", path)),
+                        }
+                    }
+                    let inner = inner.trim();
+                    segments.push(if inner == "*" {
+                        Segment::Wildcard
+                    } else if let Some(quoted) = inner
+                        .strip_prefix('"')
+                        .and_then(|inner| inner.strip_suffix('"'))
+                    {
+                        Segment::Key(quoted.to_string())
+                    } else {
+                        Segment::Index(inner.parse().map_err(|_| {
+                            format!("expected an index, * or a quoted key in [{}]", inner)
+                        })?)
+                    });
+                    after_selector = true;
+                }
+                '*' if key.is_empty() && chars.peek().is_none_or(|next| *next == '.') => {
+                    segments.push(Segment::Wildcard);
+                    after_selector = true;
+                }
+                _ if after_selector => {
+                    return Err(format!(
+                        "expected '.' or '[' after a selector in {:?}",
+                        path
+                    ));
+                }
+                ch => key.push(ch),
+            }
+        }
+        if !key.is_empty() {
+            segments.push(Segment::Key(key));
+        } else if !after_selector {
+            return Err(format!("path {:?} ends without a key", path));
+        }
+
+        if segments.first() == Some(&Segment::Key("meta".to_string())) {
+            segments.remove(0);
+        }
+        if segments.is_empty() {
+            return Err(format!("path {:?} selects nothing", path));
+        }
+        Ok(JsonPath(Selector::Segments(segments)))
+    }
+
+    /// Every value the path selects, in document order.
+    pub fn select<'a>(&self, value: &'a Value) -> Vec<&'a Value> {
+        let segments = match &self.0 {
+            Selector::Pointer(pointer) => return value.pointer(pointer).into_iter().collect(),
+            Selector::Segments(segments) => segments,
+        };
+
+        let mut current = vec![value];
+        for segment in segments {
+            current = current
+                .into_iter()
+                .flat_map(|value| -> Vec<&Value> {
+                    match (segment, value) {
+                        (Segment::Key(key), Value::Object(map)) => {
+                            map.get(key).into_iter().collect()
+                        }
+                        (Segment::Index(index), Value::Array(array)) => {
+                            array.get(*index).into_iter().collect()
+                        }
+                        (Segment::Wildcard, Value::Array(array)) => array.iter().collect(),
+                        (Segment::Wildcard, Value::Object(map)) => map.values().collect(),
+                        _ => vec![],
+                    }
+                })
+                .collect();
+        }
+        current
+    }
+}
+
+/// A selected value as tag or title text. Strings, numbers and booleans have
+/// one; `null`, arrays and objects don't.
+pub fn value_as_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.trim().to_string()).filter(|s| !s.is_empty()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Null | Value::Array(_) | Value::Object(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn select(path: &str, value: &Value) -> Vec<Value> {
+        JsonPath::parse(path)
+            .unwrap()
+            .select(value)
+            .into_iter()
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn test_select() {
+        let meta = json!({
+            "author": "someone",
+            "subreddit": "aww",
+            "categories": ["cats", "kittens"],
+            "media": [{ "url": "a" }, { "url": "b" }],
+            "odd key": 1,
+        });
+
+        assert_eq!(select("meta.author", &meta), vec![json!("someone")]);
+        assert_eq!(select("subreddit", &meta), vec![json!("aww")]);
+        assert_eq!(
+            select("meta.categories[*]", &meta),
+            vec![json!("cats"), json!("kittens")]
+        );
+        assert_eq!(select("meta.media[1].url", &meta), vec![json!("b")]);
+        assert_eq!(
+            select("meta.media.*.url", &meta),
+            vec![json!("a"), json!("b")]
+        );
+        assert_eq!(select(r#"meta["odd key"]"#, &meta), vec![json!(1)]);
+        assert_eq!(select("/media/0/url", &meta), vec![json!("a")]);
+        assert!(select("meta.missing[*]", &meta).is_empty());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(JsonPath::parse("meta").is_err());
+        assert!(JsonPath::parse("meta..author").is_err());
+        assert!(JsonPath::parse("meta.categories[").is_err());
+        assert!(JsonPath::parse("meta.categories[x]").is_err());
+        assert!(JsonPath::parse("meta.author.").is_err());
+    }
+}
@@ -48,12 +48,20 @@ macro_rules! serve_static_file {
 
 pub mod bake;
 pub mod collections;
+pub mod color;
+pub mod dupes;
 pub mod errors;
 pub mod handlers;
+pub mod json_path;
+pub mod reprocessor_diff;
 pub mod reprocessors;
+pub mod script;
 pub mod search;
+pub mod search_index;
 pub mod serde;
+pub mod server_config;
 pub mod site;
+pub mod tag_rules;
 pub mod thread_safe_work_dir;
 pub mod timestring;
 pub mod workdir;
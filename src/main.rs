@@ -12,11 +12,14 @@ use chrono::Utc;
 use clap::Parser;
 use opentelemetry::global;
 use opentelemetry_sdk::metrics::MeterProvider;
-use site_server::bake::Bake;
+use site_server::bake::{pool, Bake, BakeOptions, ThumbnailService};
 use std::io::Read;
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::{thread, time::Duration};
 
 use site_server::{
+    dupes::{DuplicateIndex, SharedDuplicateIndex},
     errors,
     handlers::{
         self, generic_archive_index_handler, generic_archive_page_handler,
@@ -31,10 +34,16 @@ use site_server::{
         generic_random_handler, generic_random_slideshow_handler,
         generic_search_slideshow_handler, generic_tag_handler,
         generic_tag_page_handler, generic_tag_slideshow_handler,
-        generic_tags_index_handler, media_viewer_fragment_handler, search_form_handler,
-        search_results_handler, serve_crawled_json, SiteRenderer, SiteSource,
+        generic_tags_index_handler, media_viewer_fragment_handler, reprocessor_diff_form_handler,
+        reprocessor_diff_handler, search_form_handler,
+        search_results_handler, serve_crawled_json, thumbnail_fragment_handler, SiteRenderer,
+        SiteSource,
     },
-    serve_static_file, thread_safe_work_dir, workdir,
+    reprocessor_diff::{diff_reprocessors, parse_reprocessor_list},
+    search_index::SharedSearchIndex,
+    serve_static_file,
+    server_config::ServerConfig,
+    thread_safe_work_dir, workdir,
 };
 
 use handlers::{date_time_element, ThreadSafeWorkDir, WorkDirPrefix};
@@ -47,14 +56,68 @@ use workdir::WorkDir;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// JSON file with settings shared by every site, such as server-wide
+    /// reprocessors
+    #[arg(long, global = true)]
+    server_config: Option<PathBuf>,
 }
 
 struct StartTime(i64);
 
 #[derive(clap::Subcommand)]
 enum Commands {
-    Serve { work_dirs: Vec<String> },
-    Bake { work_dirs: Vec<String> },
+    Serve {
+        work_dirs: Vec<String>,
+        /// List only the earliest copy of each duplicate cluster in the `all` view
+        #[arg(long)]
+        collapse_duplicates: bool,
+        /// Threads generating thumbnails that bake hasn't made yet (0 disables)
+        #[arg(long, default_value_t = 1)]
+        thumbnail_workers: usize,
+        /// Serve the admin pages, like /{site}/admin/reprocessors. Set
+        /// BASIC_AUTH_USERNAME and BASIC_AUTH_PASSWORD too unless only
+        /// trusted users can reach the server
+        #[arg(long)]
+        admin: bool,
+    },
+    Bake {
+        work_dirs: Vec<String>,
+        /// Number of items to bake in parallel (defaults to the number of CPUs)
+        #[arg(short, long)]
+        jobs: Option<usize>,
+        /// Also write the summary of the run to this file as JSON
+        #[arg(long)]
+        report: Option<PathBuf>,
+        /// Print what would be generated or regenerated, without running ffmpeg
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Find baked artifacts no longer reachable from any item (dry run unless --delete)
+    Gc {
+        work_dirs: Vec<String>,
+        /// Actually remove the unreachable artifacts
+        #[arg(long)]
+        delete: bool,
+    },
+    /// List clusters of perceptually duplicate items across the given WorkDirs
+    Dupes {
+        work_dirs: Vec<String>,
+        /// Print the clusters as JSON instead
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show what each reprocessor, server-wide ones included, does to a
+    /// WorkDir's crawled items
+    ReprocessorDiff {
+        work_dir: PathBuf,
+        /// JSON file with the reprocessors to try instead of those in
+        /// config.json: a list of them, or a config with one
+        #[arg(long)]
+        reprocessors: Option<PathBuf>,
+        /// Print the changes as JSON instead
+        #[arg(long)]
+        json: bool,
+    },
 }
 
 #[get("/healthz")]
@@ -178,39 +241,180 @@ async fn run() -> errors::Result<()> {
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
 
     let cli = Cli::parse();
+    let server_config = Arc::new(match &cli.server_config {
+        Some(path) => ServerConfig::load(path)?,
+        None => ServerConfig::default(),
+    });
 
     match &cli.command {
-        Commands::Bake { work_dirs } => {
+        Commands::Bake {
+            work_dirs,
+            jobs,
+            report,
+            dry_run,
+        } => {
             println!("Loading WorkDirs...");
             let mut work_dirs_vec = vec![];
             for work_dir in work_dirs.into_iter() {
                 println!("Loading WorkDir: {}", work_dir);
-                let work_dir = WorkDir::new(work_dir.to_string()).expect("Failed to load WorkDir");
+                let work_dir = WorkDir::new(work_dir.to_string(), server_config.clone())
+                    .expect("Failed to load WorkDir");
                 work_dirs_vec.push(work_dir);
             }
 
+            if *dry_run {
+                for work_dir in work_dirs_vec.iter() {
+                    work_dir.bake_plan().print();
+                }
+                return Ok(());
+            }
+
+            let options = BakeOptions {
+                jobs: jobs.unwrap_or_else(pool::default_jobs),
+            };
+
+            let mut reports = vec![];
             for work_dir in work_dirs_vec.iter() {
                 println!("Baking WorkDir: {}", work_dir.config.label);
-                work_dir.bake_all();
+                reports.push(work_dir.bake_all(&options));
+            }
+
+            for report in reports.iter() {
+                report.print_summary();
+            }
+
+            if let Some(report_path) = report {
+                let file = std::fs::File::create(report_path)?;
+                serde_json::to_writer_pretty(file, &reports)?;
+                println!("Wrote bake report to {}", report_path.display());
+            }
+
+            Ok(())
+        }
+
+        Commands::Gc { work_dirs, delete } => {
+            for work_dir in work_dirs.iter() {
+                println!("Loading WorkDir: {}", work_dir);
+                let work_dir = WorkDir::new(work_dir.to_string(), server_config.clone())
+                    .expect("Failed to load WorkDir");
+                work_dir.collect_garbage(*delete)?.print();
+            }
+
+            Ok(())
+        }
+
+        Commands::Dupes { work_dirs, json } => {
+            let mut work_dirs_vec = vec![];
+            for work_dir in work_dirs.iter() {
+                eprintln!("Loading WorkDir: {}", work_dir);
+                let work_dir = WorkDir::new(work_dir.to_string(), server_config.clone())
+                    .expect("Failed to load WorkDir");
+                work_dirs_vec.push(work_dir);
+            }
+
+            let index = DuplicateIndex::build(
+                work_dirs_vec
+                    .iter()
+                    .flat_map(|work_dir| work_dir.items_for_all_view().items.values())
+                    .filter(|item| !item.hidden),
+            );
+            if *json {
+                serde_json::to_writer_pretty(std::io::stdout(), &index.clusters)?;
+                println!();
+            } else {
+                index.print();
+            }
+
+            Ok(())
+        }
+
+        Commands::ReprocessorDiff {
+            work_dir,
+            reprocessors,
+            json,
+        } => {
+            let config = WorkDir::load_config(work_dir)?;
+            let reprocessors = match reprocessors {
+                Some(path) => parse_reprocessor_list(&std::fs::read_to_string(path)?)?,
+                None => config.reprocessors.clone(),
+            };
+            let items = WorkDir::load_unprocessed(work_dir, &config)?;
+
+            let pipeline = server_config.pipeline(&reprocessors, &config.skip_server_reprocessors);
+            let diff =
+                diff_reprocessors(items.items, &pipeline, &server_config.tags, work_dir)?;
+            if *json {
+                serde_json::to_writer_pretty(std::io::stdout(), &diff)?;
+                println!();
+            } else {
+                diff.print();
             }
 
             Ok(())
         }
 
-        Commands::Serve { work_dirs } => {
+        Commands::Serve {
+            work_dirs,
+            collapse_duplicates,
+            thumbnail_workers,
+            admin,
+        } => {
+            let thumbnails = ThumbnailService::new(*thumbnail_workers);
+
             println!("Loading WorkDirs...");
             let mut work_dirs_vec = vec![];
             for work_dir in work_dirs.into_iter() {
                 println!("Loading WorkDir: {}", work_dir);
-                let work_dir = WorkDir::new(work_dir.to_string()).expect("Failed to load WorkDir");
+                let work_dir = WorkDir::new(work_dir.to_string(), server_config.clone())
+                    .expect("Failed to load WorkDir");
                 let threadsafe_work_dir = ThreadSafeWorkDirImpl::new(work_dir);
-                let update_clone = threadsafe_work_dir.clone();
                 work_dirs_vec.push(threadsafe_work_dir);
+            }
 
-                // Spawn a thread to watch the workdir for changes
+            let duplicates = SharedDuplicateIndex::new(work_dirs_vec.clone());
+            let collapse_duplicates = *collapse_duplicates;
+
+            // Shared by every worker, so each search index is built once
+            let site_sources = work_dirs_vec
+                .iter()
+                .map(|workdir| SiteSource::Single(workdir.clone()))
+                .chain(Some(SiteSource::All {
+                    workdirs: work_dirs_vec.clone(),
+                    collapse_duplicates: collapse_duplicates.then(|| duplicates.clone()),
+                }))
+                .map(|site_source| (site_source.clone(), SharedSearchIndex::new(site_source)))
+                .collect::<Vec<(SiteSource, SharedSearchIndex)>>();
+
+            // Spawn a thread to watch the workdirs for changes, and to rebuild
+            // the indexes over their items after a reload rather than in the
+            // first request that needs them
+            {
+                let work_dirs_vec = work_dirs_vec.clone();
+                let duplicates = duplicates.clone();
+                let site_sources = site_sources.clone();
+                let thumbnails = thumbnails.clone();
                 thread::spawn(move || loop {
                     thread::sleep(Duration::from_secs(60));
-                    update_clone.check_for_updates();
+                    for work_dir in &work_dirs_vec {
+                        let added = work_dir.check_for_updates();
+                        if !added.is_empty() {
+                            let wd = work_dir.work_dir.read().unwrap();
+                            thumbnails.queue_items(
+                                &wd.path,
+                                added.iter().filter_map(|key| {
+                                    wd.crawled
+                                        .items
+                                        .get(key)
+                                        .or_else(|| wd.items_for_all_view().items.get(key))
+                                }),
+                            );
+                        }
+                    }
+                    // The duplicates first, as the `all` view's items depend on them
+                    duplicates.refresh();
+                    for (_, search_index) in &site_sources {
+                        search_index.refresh();
+                    }
                 });
             }
 
@@ -222,6 +426,9 @@ async fn run() -> errors::Result<()> {
             let provider = MeterProvider::builder().with_reader(exporter).build();
             global::set_meter_provider(provider);
 
+            let admin = *admin;
+
+
             let listen_address = std::env::var("LISTEN_ADDRESS").unwrap_or("127.0.0.1".to_owned());
 
             log::info!("Starting HTTP server at http://{}:8080", listen_address);
@@ -246,6 +453,8 @@ async fn run() -> errors::Result<()> {
                         .build(),
                     )
                     .app_data(web::Data::new(work_dirs_vec.clone()))
+                    .app_data(web::Data::new(duplicates.clone()))
+                    .app_data(web::Data::new(thumbnails.clone()))
                     .app_data(web::Data::new(StartTime(Utc::now().timestamp_millis())))
                     .wrap(
                         middleware::Logger::default()
@@ -255,6 +464,7 @@ async fn run() -> errors::Result<()> {
                     .service(serve_static_file!("styles.css"))
                     .service(serve_static_file!("page-transitions.css"))
                     .service(serve_static_file!("detail_page.js"))
+                    .service(serve_static_file!("media.js"))
                     .service(serve_static_file!("idiomorph.min.js"))
                     .service(serve_static_file!("idiomorph-ext.min.js"))
                     .service(serve_static_file!("htmx.min.js"))
@@ -268,16 +478,8 @@ async fn run() -> errors::Result<()> {
                     handlers::SiteRendererType::Reddit,
                 ];
 
-                let site_sources = work_dirs_vec
-                    .iter()
-                    .map(|workdir| SiteSource::Single(workdir.clone()))
-                    .chain(Some(SiteSource::All {
-                        workdirs: work_dirs_vec.clone(),
-                    }))
-                    .collect::<Vec<SiteSource>>();
-
                 // Register individual site routes
-                for site_source in site_sources.iter() {
+                for (site_source, search_index) in site_sources.iter() {
                     let slug = site_source.slug();
 
                     // Ordering matters, do more specific routes first
@@ -285,6 +487,7 @@ async fn run() -> errors::Result<()> {
                         app = app.service(
                             web::scope(&format!("{}/{}", slug, renderer.get_prefix()))
                                 .app_data(web::Data::new(site_source.clone()))
+                                .app_data(web::Data::new(search_index.clone()))
                                 .app_data(web::Data::new(renderer.clone()))
                                 .app_data(web::Data::new(WorkDirPrefix(slug.clone())))
                                 .service(generic_index_handler)
@@ -324,6 +527,14 @@ async fn run() -> errors::Result<()> {
                             .app_data(web::Data::new(site_source.clone()))
                             .app_data(web::Data::new(WorkDirPrefix(slug.clone())))
                             .service(serve_crawled_json)
+                            .service(thumbnail_fragment_handler)
+                            .configure(|scope| {
+                                if admin {
+                                    scope
+                                        .service(reprocessor_diff_form_handler)
+                                        .service(reprocessor_diff_handler);
+                                }
+                            })
                             // Only add the assets route if the site source provides an assets path
                             .configure(|scope| {
                                 if let Some(assets_path) = site_source.get_assets_path() {
@@ -348,8 +559,8 @@ async fn run() -> errors::Result<()> {
 
 #[actix_web::main]
 async fn main() {
-    if let Err(ref _e) = run().await {
-        // _e.print();
+    if let Err(ref e) = run().await {
+        eprintln!("Error: {}", e);
         ::std::process::exit(1);
     }
 }
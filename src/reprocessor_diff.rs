@@ -0,0 +1,313 @@
+//! What a list of reprocessors does to a site's items, step by step, so
+//! changes to the `reprocessors` in config.json can be checked before a
+//! reload picks them up.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::Path,
+};
+
+use indexmap::IndexMap;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    errors::{Error, Result},
+    reprocessors::Reprocessor,
+    server_config::PipelineStep,
+    site::CrawlItem,
+    tag_rules::TagRules,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TagRename {
+    pub from: String,
+    pub to: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TitleChange {
+    pub key: String,
+    pub before: String,
+    pub after: String,
+}
+
+/// The changes one reprocessor made to the items it was given.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StepDiff {
+    /// Position of the reprocessor in the list, from 1
+    pub step: usize,
+    /// The reprocessor's type, or `tag-rules` for the server's tag rules
+    pub reprocessor: String,
+    /// The server-wide set the reprocessor is from, if it isn't the site's
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub set: Option<String>,
+    /// Keys of items that are gone afterwards, including merged ones
+    pub dropped: Vec<String>,
+    /// Keys of items that are new afterwards, such as merged or split ones
+    pub added: Vec<String>,
+    pub hidden: Vec<String>,
+    /// Number of items each tag was added to
+    pub tags_added: BTreeMap<String, usize>,
+    /// Number of items each tag was removed from
+    pub tags_removed: BTreeMap<String, usize>,
+    pub tags_renamed: Vec<TagRename>,
+    pub titles: Vec<TitleChange>,
+}
+
+impl StepDiff {
+    pub fn is_empty(&self) -> bool {
+        self.dropped.is_empty()
+            && self.added.is_empty()
+            && self.hidden.is_empty()
+            && self.tags_added.is_empty()
+            && self.tags_removed.is_empty()
+            && self.tags_renamed.is_empty()
+            && self.titles.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReprocessorDiff {
+    pub items_before: usize,
+    pub items_after: usize,
+    pub steps: Vec<StepDiff>,
+}
+
+/// A candidate list of reprocessors: either the list itself as JSON, or a
+/// whole config.json to take the list from.
+pub fn parse_reprocessor_list(json: &str) -> Result<Vec<Reprocessor>> {
+    let invalid = |e: serde_json::Error| Error::Config(format!("reprocessors: {}", e));
+    let mut value: Value = serde_json::from_str(json).map_err(invalid)?;
+    if let Some(reprocessors) = value.get_mut("reprocessors") {
+        value = reprocessors.take();
+    }
+    serde_json::from_value(value).map_err(invalid)
+}
+
+/// Run the steps of a site's pipeline over `items` one at a time, recording
+/// what each one changed, then the server's tag rules as a last step named
+/// `tag-rules`, as loading the WorkDir does. Fails if any of them doesn't
+/// compile. Scripts are looked for in `dir`, the site's WorkDir.
+pub fn diff_reprocessors(
+    mut items: IndexMap<String, CrawlItem>,
+    pipeline: &[PipelineStep],
+    tag_rules: &TagRules,
+    dir: &Path,
+) -> Result<ReprocessorDiff> {
+    for step in pipeline {
+        step.reprocessor.compile(dir, tag_rules)?;
+    }
+
+    let items_before = items.len();
+    let mut steps = vec![];
+    for (index, pipeline_step) in pipeline.iter().enumerate() {
+        let before = items.clone();
+        pipeline_step.reprocessor.apply(&mut items);
+        let mut step = diff_items(&before, &items);
+        step.step = index + 1;
+        step.reprocessor = pipeline_step.reprocessor.name().to_string();
+        step.set = pipeline_step.set.map(str::to_string);
+        steps.push(step);
+    }
+    if !tag_rules.is_empty() {
+        let before = items.clone();
+        tag_rules.apply(&mut items);
+        let mut step = diff_items(&before, &items);
+        step.step = steps.len() + 1;
+        step.reprocessor = "tag-rules".to_string();
+        steps.push(step);
+    }
+
+    Ok(ReprocessorDiff {
+        items_before,
+        items_after: items.len(),
+        steps,
+    })
+}
+
+fn diff_items(
+    before: &IndexMap<String, CrawlItem>,
+    after: &IndexMap<String, CrawlItem>,
+) -> StepDiff {
+    let mut diff = StepDiff {
+        dropped: before
+            .keys()
+            .filter(|key| !after.contains_key(*key))
+            .cloned()
+            .collect(),
+        added: after
+            .keys()
+            .filter(|key| !before.contains_key(*key))
+            .cloned()
+            .collect(),
+        ..Default::default()
+    };
+
+    let mut renames: IndexMap<(String, String), usize> = IndexMap::new();
+    for (key, old) in before.iter() {
+        let Some(new) = after.get(key) else {
+            continue;
+        };
+
+        if new.hidden && !old.hidden {
+            diff.hidden.push(key.clone());
+        }
+        if new.title != old.title {
+            diff.titles.push(TitleChange {
+                key: key.clone(),
+                before: old.title.clone(),
+                after: new.title.clone(),
+            });
+        }
+
+        let old_tags: Vec<String> = old.tags.iter().map(|tag| tag.to_string()).collect();
+        let new_tags: Vec<String> = new.tags.iter().map(|tag| tag.to_string()).collect();
+        let old_set: HashSet<&String> = old_tags.iter().collect();
+        let new_set: HashSet<&String> = new_tags.iter().collect();
+        let removed: Vec<&String> = old_tags
+            .iter()
+            .filter(|tag| !new_set.contains(tag))
+            .collect();
+        let added: Vec<&String> = new_tags
+            .iter()
+            .filter(|tag| !old_set.contains(tag))
+            .collect();
+
+        // As many tags gone as appeared is taken to be renames, paired up in
+        // order. Reprocessors that map tags keep their positions.
+        if !removed.is_empty() && removed.len() == added.len() {
+            for (from, to) in removed.into_iter().zip(added) {
+                *renames.entry((from.clone(), to.clone())).or_default() += 1;
+            }
+        } else {
+            for tag in removed {
+                *diff.tags_removed.entry(tag.clone()).or_default() += 1;
+            }
+            for tag in added {
+                *diff.tags_added.entry(tag.clone()).or_default() += 1;
+            }
+        }
+    }
+
+    diff.tags_renamed = renames
+        .into_iter()
+        .map(|((from, to), count)| TagRename { from, to, count })
+        .collect();
+    diff.tags_renamed
+        .sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.from.cmp(&b.from)));
+    diff
+}
+
+impl ReprocessorDiff {
+    pub fn print(&self) {
+        for step in &self.steps {
+            let label = match &step.set {
+                Some(set) => format!("{} (server set {})", step.reprocessor, set),
+                None => step.reprocessor.clone(),
+            };
+            if step.is_empty() {
+                println!("{}. {}: no changes", step.step, label);
+                continue;
+            }
+
+            println!("{}. {}:", step.step, label);
+            for key in &step.dropped {
+                println!("  dropped   {}", key);
+            }
+            for key in &step.added {
+                println!("  added     {}", key);
+            }
+            for key in &step.hidden {
+                println!("  hidden    {}", key);
+            }
+            for rename in &step.tags_renamed {
+                println!(
+                    "  tag       {} -> {} ({} items)",
+                    rename.from, rename.to, rename.count
+                );
+            }
+            for (tag, count) in &step.tags_added {
+                println!("  tag       +{} ({} items)", tag, count);
+            }
+            for (tag, count) in &step.tags_removed {
+                println!("  tag       -{} ({} items)", tag, count);
+            }
+            for change in &step.titles {
+                println!(
+                    "  title     {}: {:?} -> {:?}",
+                    change.key, change.before, change.after
+                );
+            }
+        }
+        println!(
+            "{} items before reprocessing, {} after",
+            self.items_before, self.items_after
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn item(key: &str, title: &str, tags: &[&str]) -> (String, CrawlItem) {
+        (key.to_string(), CrawlItem::for_test(key, title, tags))
+    }
+
+    #[test]
+    fn test_diff_attributes_changes_to_steps() {
+        let items = IndexMap::from([
+            item("a", "Cat [HD]", &["kitty", "wip"]),
+            item("b", "Dog", &["kitty", "nsfw"]),
+        ]);
+        let reprocessors: Vec<Reprocessor> = serde_json::from_value(json!([
+            { "type": "map-tags", "mappings": { "kitty": "cat" } },
+            { "type": "remove-tags", "tags": ["wip"] },
+            { "type": "filter-out-items-with-tag", "tags": ["nsfw"] },
+            { "type": "rewrite-title", "pattern": r"\s*\[HD\]", "replacement": "" },
+        ]))
+        .unwrap();
+
+        let pipeline: Vec<PipelineStep> = reprocessors
+            .iter()
+            .map(|reprocessor| PipelineStep {
+                set: None,
+                reprocessor,
+            })
+            .collect();
+        let diff =
+            diff_reprocessors(items, &pipeline, &TagRules::default(), Path::new(".")).unwrap();
+        assert_eq!((diff.items_before, diff.items_after), (2, 1));
+
+        let [map, remove, filter, rewrite] = &diff.steps[..] else {
+            panic!("expected a diff for each step");
+        };
+        assert_eq!(
+            map.tags_renamed,
+            vec![TagRename {
+                from: "kitty".to_string(),
+                to: "cat".to_string(),
+                count: 2
+            }]
+        );
+        assert_eq!(
+            remove.tags_removed,
+            BTreeMap::from([("wip".to_string(), 1)])
+        );
+        assert_eq!(filter.dropped, vec!["b"]);
+        assert_eq!(rewrite.titles[0].after, "Cat");
+        assert_eq!(rewrite.step, 4);
+    }
+
+    #[test]
+    fn test_parse_reprocessor_list() {
+        let list = r#"[{ "type": "normalize-tags" }]"#;
+        let config = r#"{ "slug": "x", "reprocessors": [{ "type": "normalize-tags" }] }"#;
+        assert_eq!(parse_reprocessor_list(list).unwrap().len(), 1);
+        assert_eq!(parse_reprocessor_list(config).unwrap().len(), 1);
+        assert!(parse_reprocessor_list(r#"[{ "type": "bogus" }]"#).is_err());
+    }
+}
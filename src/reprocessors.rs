@@ -1,9 +1,154 @@
+use chrono::Utc;
 use indexmap::IndexMap;
+use regex::{Regex, RegexBuilder};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Duration;
 
+use crate::errors::{Error, Result};
+use crate::json_path::{value_as_text, JsonPath};
+use crate::script::{self, ScriptRunner};
+use crate::search::{evaluate_search_expr, parse_query, SearchExpr};
 use crate::site::{CrawlItem, CrawlTag, FileCrawlType, FormattedText};
+use crate::tag_rules::TagRules;
+use crate::timestring;
+
+/// A value in a reprocessor's config that is written as a string and
+/// compiled into something else, like a regex or a query. Compiled by
+/// [`Reprocessor::compile`] when the config is loaded, so a bad config is
+/// reported then rather than while items are being processed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(
+    from = "String",
+    into = "String",
+    bound(serialize = "T: Clone", deserialize = "")
+)]
+pub struct Compiled<T> {
+    source: String,
+    compiled: OnceLock<T>,
+}
+
+impl<T> Compiled<T> {
+    fn compile_with<E>(
+        &self,
+        compile: impl FnOnce(&str) -> std::result::Result<T, E>,
+    ) -> std::result::Result<&T, E> {
+        if let Some(compiled) = self.compiled.get() {
+            return Ok(compiled);
+        }
+        let compiled = compile(&self.source)?;
+        Ok(self.compiled.get_or_init(|| compiled))
+    }
+
+    fn get(&self) -> &T {
+        self.compiled
+            .get()
+            .expect("reprocessor configs are compiled when they are loaded")
+    }
+}
+
+impl<T> From<String> for Compiled<T> {
+    fn from(source: String) -> Self {
+        Compiled {
+            source,
+            compiled: OnceLock::new(),
+        }
+    }
+}
+
+impl<T> From<Compiled<T>> for String {
+    fn from(compiled: Compiled<T>) -> Self {
+        compiled.source
+    }
+}
+
+/// A regular expression in a reprocessor's config. Case-insensitive like the
+/// rest of the reprocessors' matching; `(?-i)` opts out.
+pub type Pattern = Compiled<Regex>;
+
+impl Pattern {
+    fn compile(&self) -> std::result::Result<&Regex, regex::Error> {
+        self.compile_with(|source| RegexBuilder::new(source).case_insensitive(true).build())
+    }
+
+    fn regex(&self) -> &Regex {
+        self.get()
+    }
+}
+
+/// A search expression in a reprocessor's config, in the same language as
+/// the search page.
+pub type Query = Compiled<SearchExpr>;
+
+impl Query {
+    fn compile(
+        &self,
+        tags: &TagRules,
+    ) -> std::result::Result<&SearchExpr, crate::search::ParseError> {
+        self.compile_with(|source| parse_query(source).map(|expr| tags.expand(expr)))
+    }
+
+    fn matches(&self, item: &CrawlItem) -> bool {
+        evaluate_search_expr(self.get(), item)
+    }
+}
+
+/// A path into an item's meta, like `meta.categories[*]` or a JSON pointer;
+/// see [`crate::json_path`].
+pub type MetaPath = Compiled<JsonPath>;
+
+impl MetaPath {
+    fn compile(&self) -> std::result::Result<&JsonPath, String> {
+        self.compile_with(JsonPath::parse)
+    }
+
+    /// The selected values, with any arrays among them flattened one level,
+    /// so `meta.categories` works as well as `meta.categories[*]`.
+    fn values<'a>(&self, item: &'a CrawlItem) -> Vec<&'a Value> {
+        self.get()
+            .select(&item.meta)
+            .into_iter()
+            .flat_map(|value| match value {
+                Value::Array(values) => values.iter().collect(),
+                value => vec![value],
+            })
+            .collect()
+    }
+}
+
+/// A Rhai script in a reprocessor's config, as a path inside the WorkDir
+/// (or inside the server config's directory, for server-wide reprocessors).
+pub type ScriptFile = Compiled<rhai::AST>;
+
+impl ScriptFile {
+    fn compile(&self, dir: &Path) -> std::result::Result<&rhai::AST, String> {
+        self.compile_with(|source| script::compile(dir, source))
+    }
+
+    fn ast(&self) -> &rhai::AST {
+        self.get()
+    }
+}
+
+/// A pattern and what to replace its matches with. The replacement can refer
+/// to capture groups as `$1` or `${name}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rewrite {
+    pub pattern: Pattern,
+    pub replacement: String,
+}
+
+impl Rewrite {
+    fn apply(&self, text: &str) -> String {
+        self.pattern
+            .regex()
+            .replace_all(text, self.replacement.as_str())
+            .into_owned()
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
@@ -20,9 +165,333 @@ pub enum Reprocessor {
     NormalizeTags,
     #[serde(rename = "filter-out-items-with-tag")]
     FilterOutItemsWithTag { tags: Vec<String> },
+    /// Each tag is rewritten by the first mapping whose pattern matches it.
+    /// Tags rewritten to nothing are removed.
+    #[serde(rename = "map-tags-regex")]
+    MapTagsRegex { mappings: Vec<Rewrite> },
+    #[serde(rename = "remove-tags-regex")]
+    RemoveTagsRegex { patterns: Vec<Pattern> },
+    #[serde(rename = "rewrite-title")]
+    RewriteTitle(Rewrite),
+    #[serde(rename = "rewrite-description")]
+    RewriteDescription(Rewrite),
+    #[serde(rename = "tag-matching")]
+    TagMatching { query: Query, tags: Vec<String> },
+    #[serde(rename = "remove-matching")]
+    RemoveMatching { query: Query },
+    /// Matching items stay reachable by URL but are left out of listings
+    #[serde(rename = "hide-matching")]
+    HideMatching { query: Query },
+    /// `field` is `title`, `url`, `description` (as plain text), or
+    /// `meta.<key>`, where `<key>` may itself be a dotted path into the meta
+    #[serde(rename = "set-field")]
+    SetField {
+        query: Query,
+        field: String,
+        value: Value,
+    },
+    /// Each string, number or boolean the path selects becomes a tag, in
+    /// `group` if one is given
+    #[serde(rename = "tags-from-meta")]
+    TagsFromMeta {
+        path: MetaPath,
+        #[serde(default)]
+        group: Option<String>,
+    },
+    /// The first value the path selects replaces the title
+    #[serde(rename = "title-from-meta")]
+    TitleFromMeta { path: MetaPath },
+    /// The first value the path selects that reads as a time replaces
+    /// `source_published`. Accepts the search page's date formats (as UTC)
+    /// and Unix timestamps in seconds or milliseconds.
+    #[serde(rename = "published-from-meta")]
+    PublishedFromMeta { path: MetaPath },
+    /// For items whose source gave no publication date, try each fallback in
+    /// turn and use the first date found. Items still without one are shown
+    /// as "date unknown".
+    #[serde(rename = "date-policy")]
+    DatePolicy { fallbacks: Vec<DateFallback> },
+    /// Items that share a group key become one item, with the members'
+    /// files one after another and their tags combined. See
+    /// [`merge_group`] for how the rest of the fields are combined. An item
+    /// alone in its group is left as it is.
+    #[serde(rename = "merge-items")]
+    MergeItems { by: GroupKey },
+    /// Each image and video of an item (of those matching `query`, if given)
+    /// becomes an item of its own. Items with fewer than two are left alone.
+    #[serde(rename = "split-items")]
+    SplitItems {
+        #[serde(default)]
+        query: Option<Query>,
+    },
+    /// Files whose filename matches one of `patterns`, or of one of `kinds`,
+    /// are dropped. Like the other file reprocessors, also applies to the
+    /// files nested in intermediate files. Intermediate files left with
+    /// nothing nested in them, and items left with no files, are dropped
+    /// too.
+    #[serde(rename = "remove-files")]
+    RemoveFiles {
+        #[serde(default)]
+        patterns: Vec<Pattern>,
+        #[serde(default)]
+        kinds: Vec<FileKind>,
+    },
+    /// The first file whose filename matches moves to the front. With
+    /// `preview` set, an image also becomes the item's preview. That is the
+    /// full-size file, as with previews crawled from the site: bake resizes
+    /// it into the thumbnails listings show, and a listing only falls back
+    /// to the file itself until bake has run.
+    #[serde(rename = "promote-file")]
+    PromoteFile {
+        pattern: Pattern,
+        #[serde(default)]
+        preview: bool,
+    },
+    #[serde(rename = "sort-files")]
+    SortFiles { by: FileOrder },
+    /// Runs a Rhai script against each item; see [`crate::script`] for what
+    /// it can change. Items the script fails for are left as they were.
+    #[serde(rename = "script")]
+    Script {
+        file: ScriptFile,
+        #[serde(default = "default_max_operations")]
+        max_operations: u64,
+        /// For each item
+        #[serde(default = "default_timeout_ms")]
+        timeout_ms: u64,
+    },
+}
+
+fn default_max_operations() -> u64 {
+    100_000
+}
+
+fn default_timeout_ms() -> u64 {
+    100
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileKind {
+    Image,
+    Video,
+    Intermediate,
+    Text,
+}
+
+impl FileKind {
+    fn of(file: &FileCrawlType) -> Self {
+        match file {
+            FileCrawlType::Image { .. } => FileKind::Image,
+            FileCrawlType::Video { .. } => FileKind::Video,
+            FileCrawlType::Intermediate { .. } => FileKind::Intermediate,
+            FileCrawlType::Text { .. } => FileKind::Text,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileOrder {
+    /// By filename, with runs of digits compared as numbers, so `2.jpg`
+    /// comes before `10.jpg`
+    Natural,
+    /// Most pixels first, going by the metadata bake extracted. Files
+    /// without any keep their order, after the rest.
+    LargestFirst,
+}
+
+/// Where `merge-items` finds the key items are grouped by.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "from")]
+pub enum GroupKey {
+    /// The first value the path selects, such as `meta.gallery_id`
+    #[serde(rename = "meta")]
+    Meta { path: MetaPath },
+    /// The title's first capture group, or the whole match if the pattern
+    /// has none, ignoring case. Items whose title doesn't match are left alone.
+    #[serde(rename = "title")]
+    Title { pattern: Pattern },
+}
+
+impl GroupKey {
+    fn of(&self, item: &CrawlItem) -> Option<String> {
+        match self {
+            GroupKey::Meta { path } => path.values(item).into_iter().find_map(value_as_text),
+            GroupKey::Title { pattern } => {
+                let captures = pattern.regex().captures(&item.title)?;
+                let group = captures.get(1).or_else(|| captures.get(0))?;
+                Some(group.as_str().trim().to_lowercase()).filter(|group| !group.is_empty())
+            }
+        }
+    }
+}
+
+/// Somewhere `date-policy` can look for a publication date.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "from")]
+pub enum DateFallback {
+    /// When the crawler first saw the item
+    #[serde(rename = "first-seen")]
+    FirstSeen,
+    /// As for `published-from-meta`
+    #[serde(rename = "meta")]
+    Meta { path: MetaPath },
+    /// A date such as `2024-01-15` or `20240115` in the title, as UTC
+    #[serde(rename = "title")]
+    Title,
+    /// A date such as `2024/01/15` in the URL, as UTC
+    #[serde(rename = "url")]
+    Url,
+}
+
+impl DateFallback {
+    fn published(&self, item: &CrawlItem) -> Option<i64> {
+        match self {
+            DateFallback::FirstSeen => Some(item.first_seen as i64).filter(|seen| *seen != 0),
+            DateFallback::Meta { path } => path.values(item).into_iter().find_map(parse_published),
+            DateFallback::Title => timestring::find_date(&item.title, chrono_tz::UTC),
+            DateFallback::Url => timestring::find_date(&item.url, chrono_tz::UTC),
+        }
+    }
 }
 
 impl Reprocessor {
+    /// The `type` the reprocessor has in config.json.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Reprocessor::SortVideosFirst => "sort-videos-first",
+            Reprocessor::AddTags { .. } => "add-tags",
+            Reprocessor::MapTags { .. } => "map-tags",
+            Reprocessor::RemoveTags { .. } => "remove-tags",
+            Reprocessor::NormalizeTags => "normalize-tags",
+            Reprocessor::FilterOutItemsWithTag { .. } => "filter-out-items-with-tag",
+            Reprocessor::MapTagsRegex { .. } => "map-tags-regex",
+            Reprocessor::RemoveTagsRegex { .. } => "remove-tags-regex",
+            Reprocessor::RewriteTitle(_) => "rewrite-title",
+            Reprocessor::RewriteDescription(_) => "rewrite-description",
+            Reprocessor::TagMatching { .. } => "tag-matching",
+            Reprocessor::RemoveMatching { .. } => "remove-matching",
+            Reprocessor::HideMatching { .. } => "hide-matching",
+            Reprocessor::SetField { .. } => "set-field",
+            Reprocessor::TagsFromMeta { .. } => "tags-from-meta",
+            Reprocessor::TitleFromMeta { .. } => "title-from-meta",
+            Reprocessor::PublishedFromMeta { .. } => "published-from-meta",
+            Reprocessor::DatePolicy { .. } => "date-policy",
+            Reprocessor::MergeItems { .. } => "merge-items",
+            Reprocessor::SplitItems { .. } => "split-items",
+            Reprocessor::RemoveFiles { .. } => "remove-files",
+            Reprocessor::PromoteFile { .. } => "promote-file",
+            Reprocessor::SortFiles { .. } => "sort-files",
+            Reprocessor::Script { .. } => "script",
+        }
+    }
+
+    /// Compile the reprocessor's patterns and script and parse its query or
+    /// meta path, if it has any. A script's path is relative to `dir`, and
+    /// `tag` terms in a query are expanded through `tags`, as they are when
+    /// searching. Must be called before `apply`.
+    pub fn compile(&self, dir: &Path, tags: &TagRules) -> Result<()> {
+        let paths: Vec<&MetaPath> = match self {
+            Reprocessor::TagsFromMeta { path, .. }
+            | Reprocessor::TitleFromMeta { path }
+            | Reprocessor::PublishedFromMeta { path }
+            | Reprocessor::MergeItems {
+                by: GroupKey::Meta { path },
+            } => vec![path],
+            Reprocessor::DatePolicy { fallbacks } => fallbacks
+                .iter()
+                .filter_map(|fallback| match fallback {
+                    DateFallback::Meta { path } => Some(path),
+                    _ => None,
+                })
+                .collect(),
+            _ => vec![],
+        };
+        for path in paths {
+            path.compile().map_err(|e| {
+                Error::Config(format!(
+                    "{} reprocessor has an invalid path {:?}: {}",
+                    self.name(),
+                    path.source,
+                    e
+                ))
+            })?;
+        }
+        if let Some(query) = self.query() {
+            query.compile(tags).map_err(|e| {
+                Error::Config(format!(
+                    "{} reprocessor has an invalid query {:?}: {}",
+                    self.name(),
+                    query.source,
+                    e
+                ))
+            })?;
+        }
+        if let Reprocessor::Script { file, .. } = self {
+            file.compile(dir).map_err(|e| {
+                Error::Config(format!(
+                    "script reprocessor can't load {:?}: {}",
+                    file.source, e
+                ))
+            })?;
+        }
+        if let Reprocessor::SetField { field, value, .. } = self {
+            let is_text_field = matches!(field.as_str(), "title" | "url" | "description");
+            if !is_text_field && !field.starts_with("meta.") {
+                return Err(Error::Config(format!(
+                    "set-field reprocessor can't set {:?}; use title, url, description, or meta.<key>",
+                    field
+                )));
+            }
+            if is_text_field && !value.is_string() {
+                return Err(Error::Config(format!(
+                    "set-field reprocessor needs a string value for {}",
+                    field
+                )));
+            }
+        }
+
+        let patterns: Vec<&Pattern> = match self {
+            Reprocessor::MapTagsRegex { mappings } => {
+                mappings.iter().map(|mapping| &mapping.pattern).collect()
+            }
+            Reprocessor::RemoveTagsRegex { patterns }
+            | Reprocessor::RemoveFiles { patterns, .. } => patterns.iter().collect(),
+            Reprocessor::PromoteFile { pattern, .. } => vec![pattern],
+            Reprocessor::RewriteTitle(rewrite) | Reprocessor::RewriteDescription(rewrite) => {
+                vec![&rewrite.pattern]
+            }
+            Reprocessor::MergeItems {
+                by: GroupKey::Title { pattern },
+            } => vec![pattern],
+            _ => vec![],
+        };
+
+        for pattern in patterns {
+            pattern.compile().map_err(|e| {
+                Error::Config(format!(
+                    "{} reprocessor has an invalid pattern {:?}: {}",
+                    self.name(),
+                    pattern.source,
+                    e
+                ))
+            })?;
+        }
+        Ok(())
+    }
+
+    fn query(&self) -> Option<&Query> {
+        match self {
+            Reprocessor::TagMatching { query, .. }
+            | Reprocessor::RemoveMatching { query }
+            | Reprocessor::HideMatching { query }
+            | Reprocessor::SetField { query, .. }
+            | Reprocessor::SplitItems { query: Some(query) } => Some(query),
+            _ => None,
+        }
+    }
+
     pub fn apply(&self, items: &mut IndexMap<String, CrawlItem>) {
         match self {
             Reprocessor::SortVideosFirst => {
@@ -129,7 +598,533 @@ impl Reprocessor {
                     })
                 });
             }
+            Reprocessor::MapTagsRegex { mappings } => {
+                for item in items.values_mut() {
+                    item.tags = std::mem::take(&mut item.tags)
+                        .into_iter()
+                        .filter_map(|tag| {
+                            let tag_value = tag.to_string();
+                            let Some(mapping) = mappings
+                                .iter()
+                                .find(|mapping| mapping.pattern.regex().is_match(&tag_value))
+                            else {
+                                return Some(tag);
+                            };
+                            let mapped_value = mapping.apply(&tag_value);
+                            (!mapped_value.trim().is_empty())
+                                .then_some(CrawlTag::Simple(mapped_value))
+                        })
+                        .collect();
+                }
+            }
+            Reprocessor::RemoveTagsRegex { patterns } => {
+                for item in items.values_mut() {
+                    item.tags.retain(|tag| {
+                        let tag_value = tag.to_string();
+                        !patterns
+                            .iter()
+                            .any(|pattern| pattern.regex().is_match(&tag_value))
+                    });
+                }
+            }
+            Reprocessor::RewriteTitle(rewrite) => {
+                for item in items.values_mut() {
+                    item.title = rewrite.apply(&item.title);
+                }
+            }
+            Reprocessor::RewriteDescription(rewrite) => {
+                for item in items.values_mut() {
+                    match &mut item.description {
+                        FormattedText::Markdown { value }
+                        | FormattedText::Plaintext { value }
+                        | FormattedText::Html { value } => *value = rewrite.apply(value),
+                    }
+                }
+            }
+            Reprocessor::TagMatching { query, tags } => {
+                for item in items.values_mut().filter(|item| query.matches(item)) {
+                    for tag in tags {
+                        let tag_exists = item
+                            .tags
+                            .iter()
+                            .any(|t| t.to_string().to_lowercase() == tag.to_lowercase());
+                        if !tag_exists {
+                            item.tags.push(CrawlTag::Simple(tag.clone()));
+                        }
+                    }
+                }
+            }
+            Reprocessor::RemoveMatching { query } => {
+                items.retain(|_, item| !query.matches(item));
+            }
+            Reprocessor::HideMatching { query } => {
+                for item in items.values_mut().filter(|item| query.matches(item)) {
+                    item.hidden = true;
+                }
+            }
+            Reprocessor::SetField {
+                query,
+                field,
+                value,
+            } => {
+                for item in items.values_mut().filter(|item| query.matches(item)) {
+                    set_field(item, field, value);
+                }
+            }
+            Reprocessor::TagsFromMeta { path, group } => {
+                for item in items.values_mut() {
+                    let values = path
+                        .values(item)
+                        .into_iter()
+                        .filter_map(value_as_text)
+                        .collect::<Vec<_>>();
+                    for value in values {
+                        let tag_exists = item
+                            .tags
+                            .iter()
+                            .any(|tag| tag.to_string().to_lowercase() == value.to_lowercase());
+                        if tag_exists {
+                            continue;
+                        }
+                        item.tags.push(match group {
+                            Some(group) => CrawlTag::Detailed {
+                                group: group.clone(),
+                                value,
+                            },
+                            None => CrawlTag::Simple(value),
+                        });
+                    }
+                }
+            }
+            Reprocessor::TitleFromMeta { path } => {
+                for item in items.values_mut() {
+                    if let Some(title) = path.values(item).into_iter().find_map(value_as_text) {
+                        item.title = title;
+                    }
+                }
+            }
+            Reprocessor::PublishedFromMeta { path } => {
+                for item in items.values_mut() {
+                    if let Some(published) = path.values(item).into_iter().find_map(parse_published)
+                    {
+                        item.source_published = published;
+                    }
+                }
+            }
+            Reprocessor::DatePolicy { fallbacks } => {
+                for item in items.values_mut() {
+                    if item.source_published != 0 {
+                        continue;
+                    }
+                    if let Some(published) = fallbacks
+                        .iter()
+                        .find_map(|fallback| fallback.published(item))
+                    {
+                        item.source_published = published;
+                    }
+                }
+            }
+            Reprocessor::MergeItems { by } => {
+                // Hashed, as the group can be any text but the key ends up
+                // in URLs
+                let grouped: Vec<(String, CrawlItem, Option<String>)> = std::mem::take(items)
+                    .into_iter()
+                    .map(|(key, item)| {
+                        let group = by
+                            .of(&item)
+                            .map(|group| format!("merged-{:x}", md5::compute(group)));
+                        (key, item, group)
+                    })
+                    .collect();
+                let mut sizes: HashMap<String, usize> = HashMap::new();
+                for group in grouped.iter().filter_map(|(_, _, group)| group.clone()) {
+                    *sizes.entry(group).or_default() += 1;
+                }
+
+                // Items without a partner keep their own key, and so their
+                // permalink
+                let mut merged = IndexMap::new();
+                let mut groups: HashMap<String, Vec<CrawlItem>> = HashMap::new();
+                for (key, item, group) in grouped {
+                    match group.filter(|group| sizes[group] > 1) {
+                        Some(merged_key) => {
+                            groups
+                                .entry(merged_key.clone())
+                                .or_default()
+                                .push(item.clone());
+                            // Holds the merged item's place in the order,
+                            // where its first member was
+                            merged.entry(merged_key).or_insert(item);
+                        }
+                        None => {
+                            merged.insert(key, item);
+                        }
+                    }
+                }
+                for (merged_key, members) in groups {
+                    let item = merge_group(merged_key.clone(), members);
+                    merged.insert(merged_key, item);
+                }
+                *items = merged;
+            }
+            Reprocessor::SplitItems { query } => {
+                let mut split = IndexMap::new();
+                for (key, item) in std::mem::take(items) {
+                    if query.as_ref().is_some_and(|query| !query.matches(&item)) {
+                        split.insert(key, item);
+                        continue;
+                    }
+                    for part in split_item(item) {
+                        split.insert(part.key.clone(), part);
+                    }
+                }
+                *items = split;
+            }
+            Reprocessor::RemoveFiles { patterns, kinds } => {
+                // Items left without any files are dropped, as items crawled
+                // without any are
+                let remove = |file: &FileCrawlType| {
+                    kinds.contains(&FileKind::of(file))
+                        || patterns
+                            .iter()
+                            .any(|pattern| pattern.regex().is_match(file_name(file)))
+                };
+                items.retain(|_, item| {
+                    let had_files = !item.files.is_empty();
+                    remove_files(&mut item.files, &remove);
+                    !had_files || !item.files.is_empty()
+                });
+            }
+            Reprocessor::PromoteFile { pattern, preview } => {
+                for item in items.values_mut() {
+                    let Some((key, file)) = promote_file(&mut item.files, pattern.regex()) else {
+                        continue;
+                    };
+                    if *preview && file.is_image() {
+                        item.previews.shift_insert(0, key, file);
+                    }
+                }
+            }
+            Reprocessor::SortFiles { by } => {
+                for item in items.values_mut() {
+                    let media = &item.media;
+                    for_each_file_list(&mut item.files, &mut |files| match by {
+                        FileOrder::Natural => {
+                            files.sort_by(|_, a, _, b| natural_cmp(file_name(a), file_name(b)))
+                        }
+                        FileOrder::LargestFirst => files.sort_by_cached_key(|key, _| {
+                            let pixels = media.get(key).and_then(|metadata| {
+                                Some(metadata.width? as u64 * metadata.height? as u64)
+                            });
+                            std::cmp::Reverse(pixels)
+                        }),
+                    });
+                }
+            }
+            Reprocessor::Script {
+                file,
+                max_operations,
+                timeout_ms,
+            } => {
+                let runner = ScriptRunner::new(*max_operations, Duration::from_millis(*timeout_ms));
+                for item in items.values_mut() {
+                    if let Err(e) = runner.run(file.ast(), item) {
+                        log::warn!(
+                            "{}: script {} failed for item {}: {}",
+                            item.site_settings.site_slug,
+                            file.source,
+                            item.key,
+                            e
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Call `f` on a list of files and on the nested files of each intermediate
+/// file in it, however deep.
+fn for_each_file_list(
+    files: &mut IndexMap<String, FileCrawlType>,
+    f: &mut impl FnMut(&mut IndexMap<String, FileCrawlType>),
+) {
+    f(files);
+    for file in files.values_mut() {
+        if let FileCrawlType::Intermediate { nested, .. } = file {
+            for_each_file_list(nested, f);
+        }
+    }
+}
+
+/// Drop the files `remove` picks out from `files` and the lists nested in
+/// it, then the intermediate files that leaves with nothing nested in them.
+fn remove_files(
+    files: &mut IndexMap<String, FileCrawlType>,
+    remove: &impl Fn(&FileCrawlType) -> bool,
+) {
+    files.retain(|_, file| {
+        if remove(file) {
+            return false;
+        }
+        match file {
+            FileCrawlType::Intermediate { nested, .. } if !nested.is_empty() => {
+                remove_files(nested, remove);
+                !nested.is_empty()
+            }
+            _ => true,
+        }
+    });
+}
+
+/// What file patterns are matched against: the filename, or the key of
+/// inline text.
+fn file_name(file: &FileCrawlType) -> &str {
+    match file {
+        FileCrawlType::Image { filename, .. }
+        | FileCrawlType::Video { filename, .. }
+        | FileCrawlType::Intermediate { filename, .. } => filename,
+        FileCrawlType::Text { key, .. } => key,
+    }
+}
+
+/// Move the first file whose filename matches to the front of its list,
+/// and any intermediate file it is nested in to the front of theirs.
+/// Returns the file that was moved.
+fn promote_file(
+    files: &mut IndexMap<String, FileCrawlType>,
+    regex: &Regex,
+) -> Option<(String, FileCrawlType)> {
+    for index in 0..files.len() {
+        let (key, file) = files.get_index_mut(index)?;
+        let promoted = if regex.is_match(file_name(file)) {
+            Some((key.clone(), file.clone()))
+        } else if let FileCrawlType::Intermediate { nested, .. } = file {
+            promote_file(nested, regex)
+        } else {
+            None
+        };
+        if promoted.is_some() {
+            files.move_index(index, 0);
+            return promoted;
+        }
+    }
+    None
+}
+
+/// Compare strings with runs of ASCII digits compared by their value.
+fn natural_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return std::cmp::Ordering::Equal,
+            (None, Some(_)) => return std::cmp::Ordering::Less,
+            (Some(_), None) => return std::cmp::Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let take_number = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut digits = String::new();
+                    while let Some(digit) = chars.next_if(|c| c.is_ascii_digit()) {
+                        digits.push(digit);
+                    }
+                    digits
+                };
+                let x = take_number(&mut a);
+                let y = take_number(&mut b);
+                let (x_trimmed, y_trimmed) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                let ordering = x_trimmed
+                    .len()
+                    .cmp(&y_trimmed.len())
+                    .then_with(|| x_trimmed.cmp(y_trimmed))
+                    .then_with(|| x.len().cmp(&y.len()));
+                if ordering.is_ne() {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                if ordering.is_ne() {
+                    return ordering;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+/// Combine the items `merge-items` grouped under `key`. Members are taken
+/// in order of publication, and the first one provides the title, URL,
+/// description and meta. Keys only depend on the group and member keys, so
+/// permalinks to the merged item and its files survive reloads.
+fn merge_group(key: String, mut members: Vec<CrawlItem>) -> CrawlItem {
+    members.sort_by_key(|member| {
+        (
+            member.source_published == 0,
+            member.source_published,
+            member.key.clone(),
+        )
+    });
+
+    let mut merged = members[0].clone();
+    merged.key = key;
+    merged.files = IndexMap::new();
+    merged.previews = IndexMap::new();
+    merged.media = HashMap::new();
+    merged.tags = vec![];
+    merged.source_published = 0;
+    for member in members.iter() {
+        // File keys are only unique within an item, so each member's are
+        // prefixed with its key
+        let prefix = |file_key: &str| format!("{}:{}", member.key, file_key);
+        for (file_key, file) in member.files.iter() {
+            merged
+                .files
+                .insert(prefix(file_key), prefix_file_keys(file, &member.key));
+        }
+        for (file_key, file) in member.previews.iter() {
+            merged
+                .previews
+                .insert(prefix(file_key), prefix_file_keys(file, &member.key));
+        }
+        for (file_key, metadata) in member.media.iter() {
+            merged.media.insert(prefix(file_key), metadata.clone());
+        }
+        for tag in member.tags.iter() {
+            if !merged.tags.contains(tag) {
+                merged.tags.push(tag.clone());
+            }
+        }
+        if merged.source_published == 0 {
+            merged.source_published = member.source_published;
+        }
+        merged.first_seen = merged.first_seen.min(member.first_seen);
+        merged.last_seen = merged.last_seen.max(member.last_seen);
+        merged.seen_in_last_refresh |= member.seen_in_last_refresh;
+        merged.hidden &= member.hidden;
+    }
+    merged
+}
+
+/// A copy of `file` with its key, and those of any nested files, prefixed
+/// with `item_key`.
+fn prefix_file_keys(file: &FileCrawlType, item_key: &str) -> FileCrawlType {
+    let mut file = file.clone();
+    match &mut file {
+        FileCrawlType::Image { key, .. }
+        | FileCrawlType::Video { key, .. }
+        | FileCrawlType::Text { key, .. } => *key = format!("{}:{}", item_key, key),
+        FileCrawlType::Intermediate { key, nested, .. } => {
+            *key = format!("{}:{}", item_key, key);
+            *nested = nested
+                .iter()
+                .map(|(nested_key, nested_file)| {
+                    (
+                        format!("{}:{}", item_key, nested_key),
+                        prefix_file_keys(nested_file, item_key),
+                    )
+                })
+                .collect();
+        }
+    }
+    file
+}
+
+/// Split an item into one item per image or video, keyed
+/// `{item key}:{file key}`. Any other files stay with the first part.
+fn split_item(item: CrawlItem) -> Vec<CrawlItem> {
+    let files = item.flat_files();
+    let (media_files, other_files): (Vec<_>, Vec<_>) = files
+        .into_iter()
+        .partition(|(_, file)| file.is_image() || file.is_video());
+    if media_files.len() < 2 {
+        return vec![item];
+    }
+
+    let count = media_files.len();
+    media_files
+        .into_iter()
+        .enumerate()
+        .map(|(index, (file_key, file))| {
+            let mut part = item.clone();
+            part.key = format!("{}:{}", item.key, file_key);
+            if !item.title.is_empty() {
+                part.title = format!("{} ({}/{})", item.title, index + 1, count);
+            }
+            part.media = HashMap::new();
+            if let Some(metadata) = item.media.get(&file_key) {
+                part.media.insert(file_key.clone(), metadata.clone());
+            }
+            part.previews = IndexMap::new();
+            part.files = IndexMap::from([(file_key, file)]);
+            if index == 0 {
+                for (file_key, file) in other_files.iter() {
+                    part.files.insert(file_key.clone(), file.clone());
+                    if let Some(metadata) = item.media.get(file_key) {
+                        part.media.insert(file_key.clone(), metadata.clone());
+                    }
+                }
+            }
+            part
+        })
+        .collect()
+}
+
+/// Set one of the fields `set-field` allows. The field and value were
+/// checked by `Reprocessor::compile`.
+fn set_field(item: &mut CrawlItem, field: &str, value: &Value) {
+    let text = || value.as_str().unwrap_or_default().to_string();
+    match field {
+        "title" => item.title = text(),
+        "url" => item.url = text(),
+        "description" => item.description = FormattedText::Plaintext { value: text() },
+        _ => {
+            let path = field.strip_prefix("meta.").unwrap_or(field);
+            let mut target = &mut item.meta;
+            for key in path.split('.') {
+                if !target.is_object() {
+                    *target = Value::Object(Default::default());
+                }
+                target = target
+                    .as_object_mut()
+                    .expect("just made an object")
+                    .entry(key)
+                    .or_insert(Value::Null);
+            }
+            *target = value.clone();
+        }
+    }
+}
+
+/// Timestamps below this are taken to be in seconds rather than milliseconds.
+/// In milliseconds it is early 1973; in seconds, the year 5138.
+const SECONDS_TIMESTAMP_LIMIT: i64 = 100_000_000_000;
+
+/// A meta value as milliseconds since the epoch.
+fn parse_published(value: &Value) -> Option<i64> {
+    let from_number = |timestamp: i64| {
+        if timestamp.abs() < SECONDS_TIMESTAMP_LIMIT {
+            timestamp * 1000
+        } else {
+            timestamp
+        }
+    };
+
+    match value {
+        Value::Number(n) => n
+            .as_i64()
+            .or_else(|| n.as_f64().map(|f| f as i64))
+            .map(from_number),
+        Value::String(s) => {
+            if let Ok(timestamp) = s.trim().parse::<i64>() {
+                return Some(from_number(timestamp));
+            }
+            let now = Utc::now().with_timezone(&chrono_tz::UTC);
+            timestring::parse(s, now, chrono_tz::UTC)
+                .ok()
+                .map(|spec| spec.for_before())
         }
+        _ => None,
     }
 }
 
@@ -169,3 +1164,364 @@ pub fn search_json_value_recursive(value: &Value, search_text: &str) -> bool {
         Value::Bool(_) | Value::Null => false,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collections::GetKey;
+    use serde_json::json;
+
+    fn items(title: &str, tags: &[&str]) -> IndexMap<String, CrawlItem> {
+        IndexMap::from([("a".to_string(), CrawlItem::for_test("a", title, tags))])
+    }
+
+    /// A test item, published at `published` and with an image file for each
+    /// of `files`
+    fn item_with_files(key: &str, title: &str, published: i64, files: &[&str]) -> CrawlItem {
+        let mut item = CrawlItem::for_test(key, title, &[key]);
+        item.source_published = published;
+        for file in files {
+            item.files.insert(
+                file.to_string(),
+                FileCrawlType::Image {
+                    key: file.to_string(),
+                    filename: format!("{}.jpg", file),
+                    downloaded: true,
+                    url: String::new(),
+                },
+            );
+        }
+        item
+    }
+
+    fn reprocessor(config: Value) -> Reprocessor {
+        let reprocessor: Reprocessor = serde_json::from_value(config).unwrap();
+        reprocessor
+            .compile(Path::new("."), &TagRules::default())
+            .unwrap();
+        reprocessor
+    }
+
+    fn tags(items: &IndexMap<String, CrawlItem>) -> Vec<String> {
+        items["a"].tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    #[test]
+    fn test_map_tags_regex() {
+        let mut items = items("", &["Artist:Someone", "meta:wip", "cat"]);
+        reprocessor(json!({
+            "type": "map-tags-regex",
+            "mappings": [
+                { "pattern": "^artist:(.+)$", "replacement": "by ${1}" },
+                { "pattern": "^meta:.*$", "replacement": "" },
+            ],
+        }))
+        .apply(&mut items);
+        assert_eq!(tags(&items), vec!["by Someone", "cat"]);
+    }
+
+    #[test]
+    fn test_remove_tags_regex() {
+        let mut items = items("", &["tagme", "needs_tags", "cat"]);
+        reprocessor(json!({
+            "type": "remove-tags-regex",
+            "patterns": ["^tagme$", "^needs_"],
+        }))
+        .apply(&mut items);
+        assert_eq!(tags(&items), vec!["cat"]);
+    }
+
+    #[test]
+    fn test_rewrite_title() {
+        let mut items = items("Sunset timelapse [1080P]", &[]);
+        reprocessor(json!({
+            "type": "rewrite-title",
+            "pattern": r"\s*\[\d+p\]$",
+            "replacement": "",
+        }))
+        .apply(&mut items);
+        assert_eq!(items["a"].title, "Sunset timelapse");
+    }
+
+    #[test]
+    fn test_tag_and_set_field_matching() {
+        let mut items = items("Live from the studio", &["music"]);
+        items["a"].site_settings.site_slug = "x".to_string();
+        let query = r#"(and (site "x") (title "live"))"#;
+
+        reprocessor(json!({ "type": "tag-matching", "query": query, "tags": ["stream", "Music"] }))
+            .apply(&mut items);
+        assert_eq!(tags(&items), vec!["music", "stream"]);
+
+        reprocessor(json!({ "type": "set-field", "query": query, "field": "meta.source.kind", "value": "stream" }))
+            .apply(&mut items);
+        assert_eq!(items["a"].meta, json!({ "source": { "kind": "stream" } }));
+
+        reprocessor(json!({ "type": "hide-matching", "query": "(tag \"stream\")" }))
+            .apply(&mut items);
+        assert!(items["a"].hidden);
+
+        reprocessor(json!({ "type": "remove-matching", "query": "(site \"y\")" }))
+            .apply(&mut items);
+        assert_eq!(items.len(), 1);
+    }
+
+    #[test]
+    fn test_fields_from_meta() {
+        let mut items = items("untitled", &["cats"]);
+        items["a"].meta = json!({
+            "post": { "title": "A cat", "created": "2024-01-15T00:00:00Z" },
+            "subreddit": "aww",
+            "categories": ["Cats", "kittens"],
+        });
+
+        reprocessor(json!({ "type": "tags-from-meta", "path": "meta.categories" }))
+            .apply(&mut items);
+        reprocessor(
+            json!({ "type": "tags-from-meta", "path": "/subreddit", "group": "subreddit" }),
+        )
+        .apply(&mut items);
+        reprocessor(json!({ "type": "title-from-meta", "path": "meta.post.title" }))
+            .apply(&mut items);
+        reprocessor(json!({ "type": "published-from-meta", "path": "meta.post.created" }))
+            .apply(&mut items);
+
+        assert_eq!(
+            items["a"].tags,
+            vec![
+                CrawlTag::Simple("cats".to_string()),
+                CrawlTag::Simple("kittens".to_string()),
+                CrawlTag::Detailed {
+                    group: "subreddit".to_string(),
+                    value: "aww".to_string()
+                },
+            ]
+        );
+        assert_eq!(items["a"].title, "A cat");
+        assert_eq!(items["a"].source_published, 1705276800000);
+
+        assert_eq!(parse_published(&json!(1705276800)), Some(1705276800000));
+        assert_eq!(
+            parse_published(&json!("1705276800000")),
+            Some(1705276800000)
+        );
+    }
+
+    #[test]
+    fn test_date_policy() {
+        let policy = reprocessor(json!({
+            "type": "date-policy",
+            "fallbacks": [
+                { "from": "meta", "path": "meta.created" },
+                { "from": "title" },
+                { "from": "first-seen" },
+            ],
+        }));
+
+        let mut titled = items("Trip photos 2024-01-15", &[]);
+        policy.apply(&mut titled);
+        assert_eq!(titled["a"].source_published, 1705276800000);
+
+        // A date from the source is left alone
+        titled["a"].source_published = 1;
+        policy.apply(&mut titled);
+        assert_eq!(titled["a"].source_published, 1);
+
+        // Nothing to go on, including first_seen, leaves the date unknown
+        let mut undated = items("Trip photos", &[]);
+        policy.apply(&mut undated);
+        assert_eq!(undated["a"].source_published, 0);
+
+        undated["a"].first_seen = 1705276800000;
+        undated["a"].meta = json!({ "created": 1700000000 });
+        policy.apply(&mut undated);
+        assert_eq!(undated["a"].source_published, 1700000000000);
+    }
+
+    #[test]
+    fn test_merge_items() {
+        let mut items: IndexMap<String, CrawlItem> = [
+            item_with_files("b", "Beach day (2/2)", 2000, &["0"]),
+            item_with_files("other", "Something else", 1500, &["0"]),
+            item_with_files("a", "Beach Day (1/2)", 1000, &["0"]),
+            item_with_files("c", "Alone (1/1)", 3000, &["0"]),
+        ]
+        .into_iter()
+        .map(|item| (item.key.clone(), item))
+        .collect();
+
+        reprocessor(json!({
+            "type": "merge-items",
+            "by": { "from": "title", "pattern": r"^(.+) \(\d+/\d+\)$" },
+        }))
+        .apply(&mut items);
+
+        let merged_key = format!("merged-{:x}", md5::compute("beach day"));
+        assert_eq!(
+            items.keys().collect::<Vec<_>>(),
+            vec![&merged_key, "other", "c"]
+        );
+        assert_eq!(items["c"].files.keys().collect::<Vec<_>>(), vec!["0"]);
+        let merged = &items[&merged_key];
+        assert_eq!(merged.title, "Beach Day (1/2)");
+        assert_eq!(merged.source_published, 1000);
+        assert_eq!(merged.files.keys().collect::<Vec<_>>(), vec!["a:0", "b:0"]);
+        assert_eq!(merged.files["b:0"].get_key(), "b:0");
+        assert_eq!(
+            merged.tags,
+            vec![
+                CrawlTag::Simple("a".to_string()),
+                CrawlTag::Simple("b".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_items() {
+        let mut items = IndexMap::from([
+            (
+                "dump".to_string(),
+                item_with_files("dump", "Dump", 0, &["x", "y"]),
+            ),
+            (
+                "single".to_string(),
+                item_with_files("single", "One", 0, &["z"]),
+            ),
+        ]);
+
+        reprocessor(json!({ "type": "split-items" })).apply(&mut items);
+
+        assert_eq!(
+            items.keys().collect::<Vec<_>>(),
+            vec!["dump:x", "dump:y", "single"]
+        );
+        assert_eq!(items["dump:y"].title, "Dump (2/2)");
+        assert_eq!(items["dump:y"].files.keys().collect::<Vec<_>>(), vec!["y"]);
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        let mut names = vec![
+            "img10.jpg",
+            "IMG2.jpg",
+            "img1.jpg",
+            "cover.jpg",
+            "img02.jpg",
+        ];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            vec![
+                "cover.jpg",
+                "img1.jpg",
+                "IMG2.jpg",
+                "img02.jpg",
+                "img10.jpg"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_file_reprocessors() {
+        let mut items = IndexMap::from([(
+            "a".to_string(),
+            item_with_files("a", "", 0, &["10", "2", "2_thumb", "cover"]),
+        )]);
+        let archive: FileCrawlType = serde_json::from_value(json!({
+            "type": "IntermediateFile",
+            "key": "zip",
+            "filename": "set.zip",
+            "downloaded": true,
+            "url": "",
+            "nested": [
+                { "type": "ImageFile", "key": "z1", "filename": "x_thumb.jpg", "downloaded": true, "url": "" },
+                { "type": "ImageFile", "key": "z2", "filename": "x.jpg", "downloaded": true, "url": "" },
+            ],
+        }))
+        .unwrap();
+        items["a"].files.insert("zip".to_string(), archive);
+        let thumbs: FileCrawlType = serde_json::from_value(json!({
+            "type": "IntermediateFile",
+            "key": "thumbs",
+            "filename": "thumbs.zip",
+            "downloaded": true,
+            "url": "",
+            "nested": [
+                { "type": "ImageFile", "key": "t1", "filename": "t_thumb.jpg", "downloaded": true, "url": "" },
+            ],
+        }))
+        .unwrap();
+        items["a"].files.insert("thumbs".to_string(), thumbs);
+
+        // An archive with nothing left in it goes too
+        reprocessor(json!({ "type": "remove-files", "patterns": [r"_thumb\.jpg$"] }))
+            .apply(&mut items);
+        assert!(!items["a"].files.contains_key("thumbs"));
+        reprocessor(json!({ "type": "sort-files", "by": "natural" })).apply(&mut items);
+        assert_eq!(
+            items["a"].flat_files().keys().collect::<Vec<_>>(),
+            vec!["2", "10", "cover", "z2"]
+        );
+
+        reprocessor(json!({ "type": "promote-file", "pattern": "^cover", "preview": true }))
+            .apply(&mut items);
+        assert_eq!(
+            items["a"].files.keys().collect::<Vec<_>>(),
+            vec!["cover", "2", "10", "zip"]
+        );
+        assert_eq!(
+            items["a"].previews.keys().collect::<Vec<_>>(),
+            vec!["cover"]
+        );
+
+        reprocessor(json!({ "type": "remove-files", "kinds": ["intermediate"] })).apply(&mut items);
+        assert_eq!(items["a"].files.len(), 3);
+
+        // Without `preview`, only the order changes
+        reprocessor(json!({ "type": "promote-file", "pattern": "^10" })).apply(&mut items);
+        assert_eq!(
+            items["a"].files.keys().collect::<Vec<_>>(),
+            vec!["10", "cover", "2"]
+        );
+        assert_eq!(
+            items["a"].previews.keys().collect::<Vec<_>>(),
+            vec!["cover"]
+        );
+
+        // Nothing is left to show of an item without files
+        items.insert("b".to_string(), item_with_files("b", "", 0, &["b_thumb"]));
+        reprocessor(json!({ "type": "remove-files", "patterns": [r"_thumb\.jpg$"] }))
+            .apply(&mut items);
+        assert_eq!(items.keys().collect::<Vec<_>>(), vec!["a"]);
+    }
+
+    #[test]
+    fn test_invalid_query_names_reprocessor() {
+        let reprocessor: Reprocessor = serde_json::from_value(json!({
+            "type": "hide-matching",
+            "query": "(bogus \"x\")",
+        }))
+        .unwrap();
+        let message = reprocessor
+            .compile(Path::new("."), &TagRules::default())
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("hide-matching"), "{}", message);
+        assert!(message.contains("bogus"), "{}", message);
+    }
+
+    #[test]
+    fn test_invalid_pattern_names_reprocessor() {
+        let reprocessor: Reprocessor = serde_json::from_value(json!({
+            "type": "remove-tags-regex",
+            "patterns": ["(unclosed"],
+        }))
+        .unwrap();
+        let message = reprocessor
+            .compile(Path::new("."), &TagRules::default())
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("remove-tags-regex"), "{}", message);
+        assert!(message.contains("(unclosed"), "{}", message);
+    }
+}
@@ -0,0 +1,228 @@
+// Storyboard scrubbing for video thumbnails, seek previews for the video
+// player, HLS playback, deep zoom for very large images, and clearing
+// thumbnail placeholders once the real thumbnail has loaded. Elements with a data-storyboard attribute point at a WebVTT track
+// whose cues reference tiles of a sprite sheet (sprite.jpg#xywh=x,y,w,h).
+(() => {
+  const storyboards = new Map();
+
+  const parseTimestamp = (text) => {
+    const [h, m, s] = text.trim().split(':');
+    return Number(h) * 3600 + Number(m) * 60 + Number(s);
+  };
+
+  const parseVtt = (text, baseUrl) => {
+    const cues = [];
+    for (const block of text.split(/\n\n+/)) {
+      const lines = block.trim().split('\n');
+      const timing = lines.findIndex((line) => line.includes('-->'));
+      if (timing === -1 || !lines[timing + 1]) continue;
+
+      const [start, end] = lines[timing].split('-->').map(parseTimestamp);
+      const [file, fragment] = lines[timing + 1].split('#xywh=');
+      const [x, y, w, h] = fragment.split(',').map(Number);
+      cues.push({ start, end, url: new URL(file, baseUrl).href, x, y, w, h });
+    }
+
+    // Size of the whole sprite sheet, needed to scale it as a background
+    const sheetWidth = Math.max(...cues.map((cue) => cue.x + cue.w));
+    const sheetHeight = Math.max(...cues.map((cue) => cue.y + cue.h));
+    return cues.map((cue) => ({ ...cue, sheetWidth, sheetHeight }));
+  };
+
+  const loadStoryboard = (url) => {
+    if (!storyboards.has(url)) {
+      const absolute = new URL(url, window.location.href).href;
+      storyboards.set(
+        url,
+        fetch(absolute)
+          .then((response) => (response.ok ? response.text() : ''))
+          .then((text) => parseVtt(text, absolute))
+          .catch(() => [])
+      );
+    }
+    return storyboards.get(url);
+  };
+
+  const cueAtFraction = (cues, fraction) => {
+    const index = Math.min(cues.length - 1, Math.max(0, Math.floor(fraction * cues.length)));
+    return cues[index];
+  };
+
+  const cueAtTime = (cues, time) =>
+    cues.find((cue) => time >= cue.start && time < cue.end) || cues[cues.length - 1];
+
+  // Paint a tile into an element, scaled to the element's width.
+  const paintCue = (element, cue, width) => {
+    const scale = width / cue.w;
+    element.style.backgroundImage = `url("${cue.url}")`;
+    element.style.backgroundPosition = `${-cue.x * scale}px ${-cue.y * scale}px`;
+    element.style.backgroundSize = `${cue.sheetWidth * scale}px ${cue.sheetHeight * scale}px`;
+    element.style.height = `${cue.h * scale}px`;
+  };
+
+  const formatTime = (seconds) => {
+    const total = Math.floor(seconds);
+    const h = Math.floor(total / 3600);
+    const m = Math.floor(total / 60) % 60;
+    const s = String(total % 60).padStart(2, '0');
+    return h > 0 ? `${h}:${String(m).padStart(2, '0')}:${s}` : `${m}:${s}`;
+  };
+
+  // Hover scrubbing for thumbnails in listings. Uses delegation so it keeps
+  // working after htmx swaps page content.
+  document.addEventListener('mousemove', async (e) => {
+    const container = e.target.closest && e.target.closest('[data-storyboard]:not(video)');
+    if (!container) return;
+
+    const cues = await loadStoryboard(container.dataset.storyboard);
+    if (!cues.length) return;
+
+    let overlay = container.querySelector('.storyboard_scrub');
+    if (!overlay) {
+      overlay = document.createElement('div');
+      overlay.className = 'storyboard_scrub';
+      container.appendChild(overlay);
+    }
+
+    const rect = container.getBoundingClientRect();
+    const cue = cueAtFraction(cues, (e.clientX - rect.left) / rect.width);
+    paintCue(overlay, cue, rect.width);
+    overlay.hidden = false;
+  });
+
+  document.addEventListener('mouseout', (e) => {
+    const container = e.target.closest && e.target.closest('[data-storyboard]:not(video)');
+    if (!container || container.contains(e.relatedTarget)) return;
+    const overlay = container.querySelector('.storyboard_scrub');
+    if (overlay) overlay.hidden = true;
+  });
+
+  // Seek bar with frame previews under videos on detail pages.
+  const attachSeekBar = async (video) => {
+    if (video.dataset.storyboardAttached) return;
+    video.dataset.storyboardAttached = 'true';
+
+    const cues = await loadStoryboard(video.dataset.storyboard);
+    if (!cues.length) return;
+
+    const bar = document.createElement('div');
+    bar.className = 'storyboard_seek';
+    const progress = document.createElement('div');
+    progress.className = 'storyboard_seek_progress';
+    const preview = document.createElement('div');
+    preview.className = 'storyboard_seek_preview';
+    preview.hidden = true;
+    const label = document.createElement('span');
+    preview.appendChild(label);
+    bar.append(progress, preview);
+    video.insertAdjacentElement('afterend', bar);
+
+    const duration = () => video.duration || cues[cues.length - 1].end;
+    const timeAt = (clientX) => {
+      const rect = bar.getBoundingClientRect();
+      const fraction = Math.min(1, Math.max(0, (clientX - rect.left) / rect.width));
+      return { fraction, time: fraction * duration() };
+    };
+
+    video.addEventListener('timeupdate', () => {
+      progress.style.width = `${(video.currentTime / duration()) * 100}%`;
+    });
+
+    bar.addEventListener('mousemove', (e) => {
+      const { fraction, time } = timeAt(e.clientX);
+      paintCue(preview, cueAtTime(cues, time), 160);
+      label.textContent = formatTime(time);
+      preview.style.left = `${fraction * 100}%`;
+      preview.hidden = false;
+    });
+
+    bar.addEventListener('mouseleave', () => {
+      preview.hidden = true;
+    });
+
+    bar.addEventListener('click', (e) => {
+      video.currentTime = timeAt(e.clientX).time;
+    });
+  };
+
+  // Adaptive streaming for videos with an HLS package. Safari plays HLS
+  // natively, elsewhere hls.js is used, and anything else keeps the mp4
+  // <source> the video was rendered with.
+  const attachHls = (video) => {
+    if (video.dataset.hlsAttached) return;
+    video.dataset.hlsAttached = 'true';
+
+    const url = video.dataset.hls;
+    if (window.Hls && window.Hls.isSupported()) {
+      const hls = new window.Hls();
+      hls.on(window.Hls.Events.ERROR, (_event, data) => {
+        if (data.fatal) {
+          // Give up on the stream and go back to the plain file
+          hls.destroy();
+          video.removeAttribute('src');
+          video.load();
+        }
+      });
+      hls.loadSource(url);
+      hls.attachMedia(video);
+    } else if (video.canPlayType('application/vnd.apple.mpegurl')) {
+      video.src = url;
+    }
+  };
+
+  // Pan and zoom over a baked tile pyramid. OpenSeadragon is only fetched
+  // once a page actually has a tiled image, and only the tiles in view are
+  // requested. If it can't load, the display image inside stays put.
+  const OPENSEADRAGON = 'https://cdnjs.cloudflare.com/ajax/libs/openseadragon/4.1.0';
+  let openSeadragon;
+  const loadOpenSeadragon = () => {
+    openSeadragon ||= new Promise((resolve, reject) => {
+      const script = document.createElement('script');
+      script.src = `${OPENSEADRAGON}/openseadragon.min.js`;
+      script.onload = () => resolve(window.OpenSeadragon);
+      script.onerror = reject;
+      document.head.appendChild(script);
+    });
+    return openSeadragon;
+  };
+
+  const attachDeepZoom = async (container) => {
+    if (container.dataset.dziAttached) return;
+    container.dataset.dziAttached = 'true';
+
+    let OpenSeadragon;
+    try {
+      OpenSeadragon = await loadOpenSeadragon();
+    } catch {
+      return;
+    }
+    container.replaceChildren();
+    OpenSeadragon({
+      element: container,
+      tileSources: new URL(container.dataset.dzi, window.location.href).href,
+      prefixUrl: `${OPENSEADRAGON}/images/`,
+      showNavigationControl: false,
+      visibilityRatio: 1,
+      constrainDuringPan: true,
+    });
+  };
+
+  const attachPlayers = (root) => {
+    root.querySelectorAll('video[data-storyboard]').forEach(attachSeekBar);
+    root.querySelectorAll('video[data-hls]').forEach(attachHls);
+    root.querySelectorAll('[data-dzi]').forEach(attachDeepZoom);
+  };
+
+  // Thumbnails are laid over a blurry inline placeholder. Once one has
+  // loaded, drop the placeholder so it can't show through transparent images.
+  // Load events don't bubble, so listen in the capture phase.
+  const clearPlaceholder = (e) => {
+    const container = e.target.closest?.('.item_thumb_img, .post_preview');
+    if (container) container.style.backgroundImage = '';
+  };
+  document.addEventListener('load', clearPlaceholder, true);
+  document.addEventListener('loadeddata', clearPlaceholder, true);
+
+  document.addEventListener('DOMContentLoaded', () => attachPlayers(document));
+  document.addEventListener('htmx:load', (e) => attachPlayers(e.target));
+})();
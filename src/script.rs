@@ -0,0 +1,266 @@
+//! The sandbox the `script` reprocessor runs [Rhai](https://rhai.rs) scripts
+//! in. A script sees the item it is run for as `item`, a map with these
+//! fields, and changes it in place:
+//!
+//! - `title`, a string
+//! - `tags`, strings, or `#{ group, value }` maps for grouped tags
+//! - `meta`, as in crawled.json
+//! - `description`, a `#{ format, value }` map, `format` being `markdown`,
+//!   `plaintext` or `html`
+//! - `source_published`, `first_seen` and `last_seen`, in milliseconds
+//! - `files`, the keys of the item's files: reorder them to reorder the
+//!   files, or leave keys out to drop files
+//! - `key` and `url`, which can be read but not changed
+//!
+//! ```rhai
+//! item.title.replace(" [HD]", "");
+//! if item.meta.nsfw == true { item.tags.push("nsfw"); }
+//! ```
+//!
+//! Scripts can't import modules or `eval` code, and each run is stopped
+//! after a number of operations or a time limit.
+
+use std::{
+    path::{Component, Path},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use indexmap::IndexMap;
+use rhai::{
+    module_resolvers::DummyModuleResolver,
+    serde::{from_dynamic, to_dynamic},
+    Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST,
+};
+use serde::de::DeserializeOwned;
+
+use crate::site::CrawlItem;
+
+/// An engine with nothing reaching outside the script: no modules, no
+/// `eval`, and `print` and `debug` going to the log.
+fn sandboxed_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+    engine.set_max_call_levels(32);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(1 << 20);
+    engine.set_max_array_size(10_000);
+    engine.set_max_map_size(10_000);
+    engine.on_print(|text| log::info!("script: {}", text));
+    engine.on_debug(|text, _, position| log::debug!("script at {}: {}", position, text));
+    engine
+}
+
+/// The largest script file [`compile`] reads.
+const MAX_SCRIPT_SIZE: u64 = 1 << 20;
+
+/// Read and compile the script at `source`, a relative path that has to
+/// stay inside `dir` once symlinks are followed.
+pub fn compile(dir: &Path, source: &str) -> Result<AST, String> {
+    let outside = || format!("{:?} is not a file inside {}", source, dir.display());
+    if !Path::new(source)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(outside());
+    }
+    let root = dir.canonicalize().map_err(|e| e.to_string())?;
+    let path = root
+        .join(source)
+        .canonicalize()
+        .map_err(|e| e.to_string())?;
+    let metadata = path.metadata().map_err(|e| e.to_string())?;
+    if !path.starts_with(&root) || !metadata.is_file() {
+        return Err(outside());
+    }
+    if metadata.len() > MAX_SCRIPT_SIZE {
+        return Err(format!(
+            "{:?} is larger than {} bytes",
+            source, MAX_SCRIPT_SIZE
+        ));
+    }
+
+    let script = std::fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    sandboxed_engine()
+        .compile(script)
+        .map_err(|e| e.to_string())
+}
+
+/// Runs scripts within a limit on operations and on time, counted for
+/// each item separately.
+pub struct ScriptRunner {
+    engine: Engine,
+    started: Arc<Mutex<Instant>>,
+    timeout: Duration,
+}
+
+impl ScriptRunner {
+    pub fn new(max_operations: u64, timeout: Duration) -> Self {
+        let mut engine = sandboxed_engine();
+        engine.set_max_operations(max_operations);
+
+        let started = Arc::new(Mutex::new(Instant::now()));
+        let run_started = started.clone();
+        engine.on_progress(move |_| {
+            let elapsed = run_started.lock().expect("script timer poisoned").elapsed();
+            (elapsed > timeout).then_some(Dynamic::UNIT)
+        });
+        ScriptRunner {
+            engine,
+            started,
+            timeout,
+        }
+    }
+
+    /// Run `ast` for `item`. On errors the item is left as it was.
+    pub fn run(&self, ast: &AST, item: &mut CrawlItem) -> Result<(), String> {
+        let mut scope = Scope::new();
+        scope.push("item", item_to_map(item)?);
+
+        *self.started.lock().expect("script timer poisoned") = Instant::now();
+        self.engine
+            .run_ast_with_scope(&mut scope, ast)
+            .map_err(|e| match *e {
+                EvalAltResult::ErrorTerminated(..) => {
+                    format!("ran for longer than {:?}", self.timeout)
+                }
+                e => e.to_string(),
+            })?;
+
+        let map = scope
+            .get_value::<Map>("item")
+            .ok_or("item is no longer a map")?;
+        update_item(item, map)
+    }
+}
+
+fn item_to_map(item: &CrawlItem) -> Result<Map, String> {
+    let convert = |e: Box<EvalAltResult>| e.to_string();
+    let mut map = Map::new();
+    map.insert("key".into(), item.key.clone().into());
+    map.insert("url".into(), item.url.clone().into());
+    map.insert("title".into(), item.title.clone().into());
+    map.insert("tags".into(), to_dynamic(&item.tags).map_err(convert)?);
+    map.insert("meta".into(), to_dynamic(&item.meta).map_err(convert)?);
+    map.insert(
+        "description".into(),
+        to_dynamic(&item.description).map_err(convert)?,
+    );
+    map.insert("source_published".into(), item.source_published.into());
+    map.insert("first_seen".into(), (item.first_seen as i64).into());
+    map.insert("last_seen".into(), (item.last_seen as i64).into());
+    let files: Array = item.files.keys().cloned().map(Dynamic::from).collect();
+    map.insert("files".into(), files.into());
+    Ok(map)
+}
+
+/// Copy the script's changes back into `item`, all of them or, if any
+/// field is invalid, none.
+fn update_item(item: &mut CrawlItem, map: Map) -> Result<(), String> {
+    let field = |name: &str| {
+        map.get(name)
+            .ok_or_else(|| format!("item.{} was removed", name))
+    };
+    fn convert<T: DeserializeOwned>(name: &str, value: &Dynamic) -> Result<T, String> {
+        from_dynamic(value).map_err(|e| format!("item.{}: {}", name, e))
+    }
+    let date = |name: &str| {
+        field(name)?
+            .as_int()
+            .map_err(|found| format!("item.{} must be an integer, not {}", name, found))
+    };
+
+    let title: String = convert("title", field("title")?)?;
+    let tags = convert("tags", field("tags")?)?;
+    let meta = convert("meta", field("meta")?)?;
+    let description = convert("description", field("description")?)?;
+    let source_published = date("source_published")?;
+    let first_seen = date("first_seen")?;
+    let last_seen = date("last_seen")?;
+
+    let mut files = IndexMap::new();
+    for key in convert::<Vec<String>>("files", field("files")?)? {
+        let file = item
+            .files
+            .get(&key)
+            .ok_or_else(|| format!("item.files: the item has no file {:?}", key))?;
+        files.insert(key, file.clone());
+    }
+
+    item.title = title;
+    item.tags = tags;
+    item.meta = meta;
+    item.description = description;
+    item.source_published = source_published;
+    item.first_seen = first_seen.max(0) as u64;
+    item.last_seen = last_seen.max(0) as u64;
+    item.files = files;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::site::{CrawlTag, FileCrawlType};
+    use serde_json::json;
+
+    fn run(script: &str, item: &mut CrawlItem) -> Result<(), String> {
+        let runner = ScriptRunner::new(10_000, Duration::from_secs(1));
+        let ast = sandboxed_engine().compile(script).unwrap();
+        runner.run(&ast, item)
+    }
+
+    #[test]
+    fn test_script_changes_item() {
+        let mut item = CrawlItem::for_test("a", "Cat [HD]", &["kitty"]);
+        item.meta = json!({ "nsfw": true });
+        item.first_seen = 5;
+        item.last_seen = 5;
+        item.tags.push(CrawlTag::Detailed {
+            group: "artist".to_string(),
+            value: "bob".to_string(),
+        });
+        for key in ["1", "2"] {
+            item.files.insert(
+                key.to_string(),
+                FileCrawlType::Image {
+                    key: key.to_string(),
+                    filename: format!("{}.jpg", key),
+                    downloaded: true,
+                    url: String::new(),
+                },
+            );
+        }
+
+        run(
+            r#"
+                item.title.replace(" [HD]", "");
+                if item.meta.nsfw { item.tags.push("nsfw"); }
+                item.source_published = item.first_seen;
+                item.files.reverse();
+            "#,
+            &mut item,
+        )
+        .unwrap();
+        assert_eq!(item.title, "Cat");
+        assert_eq!(item.tags.len(), 3);
+        assert_eq!(item.source_published, 5);
+        assert_eq!(item.files.keys().collect::<Vec<_>>(), ["2", "1"]);
+
+        // A failed run leaves the item alone
+        assert!(run(r#"item.title = "x"; item.files.push("3");"#, &mut item).is_err());
+        assert!(run("loop {}", &mut item).is_err());
+        assert_eq!(item.title, "Cat");
+        assert!(sandboxed_engine().compile(r#"eval("1")"#).is_err());
+    }
+
+    #[test]
+    fn test_scripts_stay_inside_their_dir() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        for source in ["../x", "/etc/passwd", "src/../../x", "src"] {
+            let error = compile(dir, source).unwrap_err();
+            assert!(error.contains("is not a file inside"), "{}", error);
+        }
+    }
+}
@@ -1,9 +1,14 @@
-//! S-expression search parser and evaluator.
+//! Search parser and evaluator. Searches are written as s-expressions, like
+//! `(and (tag "cute") (not (tag "nsfw")))`, or in the shorter infix syntax
+//! of [`parse_infix_search`], like `cute -nsfw`, which compiles to the same
+//! [`SearchExpr`].
 //!
 //! NOTE: When adding or modifying search functions in this file, you must also
 //! update the documentation and examples in `src/handlers/search.rs` (the search
 //! form tooltip that shows available functions and examples to users).
 
+use crate::bake::{MediaMetadata, Orientation};
+use crate::color::ColorQuery;
 use crate::reprocessors::{extract_text_from_formatted_text, search_json_value_recursive};
 use crate::site::{CrawlItem, FileCrawlType};
 use crate::timestring;
@@ -12,7 +17,7 @@ use chrono_tz::America::New_York;
 use chrono_tz::Tz;
 
 /// The timezone used for interpreting time strings in search queries.
-const SEARCH_TIMEZONE: Tz = New_York;
+pub(crate) const SEARCH_TIMEZONE: Tz = New_York;
 
 #[derive(Debug, Clone)]
 pub enum SearchExpr {
@@ -30,6 +35,12 @@ pub enum SearchExpr {
     After(String),  // Flexible time string
     Before(String), // Flexible time string
     During(String), // Flexible time string (must be a range)
+    MinWidth(u32),
+    MinHeight(u32),
+    Orientation(Orientation),
+    LongerThan(f64),  // Seconds
+    ShorterThan(f64), // Seconds
+    Color(ColorQuery),
 }
 
 #[derive(Debug, Clone)]
@@ -55,6 +66,86 @@ impl std::fmt::Display for ParseError {
 
 impl std::error::Error for ParseError {}
 
+/// Writes the expression as an s-expression that parses back to it.
+impl std::fmt::Display for SearchExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (function_name, arg) = match self {
+            SearchExpr::And(exprs) => return write_list(f, "and", exprs),
+            SearchExpr::Or(exprs) => return write_list(f, "or", exprs),
+            SearchExpr::Not(expr) => return write!(f, "(not {})", expr),
+            SearchExpr::MinWidth(pixels) => return write!(f, "(min-width {})", pixels),
+            SearchExpr::MinHeight(pixels) => return write!(f, "(min-height {})", pixels),
+            SearchExpr::Tag(arg) => ("tag", arg.clone()),
+            SearchExpr::Type(arg) => ("type", arg.clone()),
+            SearchExpr::Site(arg) => ("site", arg.clone()),
+            SearchExpr::Fulltext(arg) => ("fulltext", arg.clone()),
+            SearchExpr::Title(arg) => ("title", arg.clone()),
+            SearchExpr::Meta(arg) => ("meta", arg.clone()),
+            SearchExpr::Desc(arg) => ("desc", arg.clone()),
+            SearchExpr::Url(arg) => ("url", arg.clone()),
+            SearchExpr::After(arg) => ("after", arg.clone()),
+            SearchExpr::Before(arg) => ("before", arg.clone()),
+            SearchExpr::During(arg) => ("during", arg.clone()),
+            SearchExpr::Orientation(orientation) => {
+                let orientation = match orientation {
+                    Orientation::Portrait => "portrait",
+                    Orientation::Landscape => "landscape",
+                    Orientation::Square => "square",
+                };
+                ("orientation", orientation.to_string())
+            }
+            SearchExpr::LongerThan(seconds) => ("longer-than", format!("{}s", seconds)),
+            SearchExpr::ShorterThan(seconds) => ("shorter-than", format!("{}s", seconds)),
+            SearchExpr::Color(ColorQuery::Named(color)) => ("color", color.name().to_string()),
+            SearchExpr::Color(ColorQuery::Exact(rgb)) => ("color", rgb.to_string()),
+        };
+        let arg = arg.replace('\\', "\\\\").replace('"', "\\\"");
+        write!(f, "({} \"{}\")", function_name, arg)
+    }
+}
+
+fn write_list(
+    f: &mut std::fmt::Formatter<'_>,
+    operator: &str,
+    exprs: &[SearchExpr],
+) -> std::fmt::Result {
+    write!(f, "({}", operator)?;
+    for expr in exprs {
+        write!(f, " {}", expr)?;
+    }
+    write!(f, ")")
+}
+
+/// Parse a search in either syntax. Input starting with `(` and the name of
+/// a function, or with `(` and a word followed by a string or another `(`,
+/// is taken to be an s-expression, so misspelled functions are reported as
+/// such. Anything else is infix.
+pub fn parse_query(input: &str) -> Result<SearchExpr, ParseError> {
+    if is_s_expression(input) {
+        parse_search_expr(input)
+    } else {
+        parse_infix_search(input)
+    }
+}
+
+pub fn is_s_expression(input: &str) -> bool {
+    let Some(rest) = input.trim_start().strip_prefix('(') else {
+        return false;
+    };
+    let rest = rest.trim_start();
+    let name_end = rest
+        .find(|c: char| !c.is_alphanumeric() && c != '-')
+        .unwrap_or(rest.len());
+    let (name, after) = rest.split_at(name_end);
+    let name = name.to_lowercase();
+    if name.is_empty() || !after.starts_with(|c: char| c.is_whitespace() || c == '"' || c == '(') {
+        return false;
+    }
+    let is_function =
+        matches!(name.as_str(), "and" | "or" | "not") || FUNCTIONS.contains(&name.as_str());
+    is_function || after.trim_start().starts_with(['"', '('])
+}
+
 pub fn parse_search_expr(input: &str) -> Result<SearchExpr, ParseError> {
     let tokens = tokenize(input)?;
     let (expr, remaining_pos) = parse_expr(&tokens, 0)?;
@@ -210,8 +301,7 @@ fn parse_expr(tokens: &[Token], start: usize) -> Result<(SearchExpr, usize), Par
                     pos = new_pos + 1;
                     Ok((SearchExpr::Not(Box::new(expr)), pos))
                 }
-                "tag" | "type" | "site" | "fulltext" | "title" | "meta" | "desc" | "url"
-                | "after" | "before" | "during" => {
+                name if FUNCTIONS.contains(&name) => {
                     if pos >= tokens.len() {
                         return Err(ParseError::UnexpectedEnd);
                     }
@@ -240,61 +330,7 @@ fn parse_expr(tokens: &[Token], start: usize) -> Result<(SearchExpr, usize), Par
                     }
                     pos += 1;
 
-                    let expr = match function_name_lower.as_str() {
-                        "tag" => SearchExpr::Tag(arg),
-                        "type" => {
-                            let type_lower = arg.to_lowercase();
-                            if type_lower != "image"
-                                && type_lower != "video"
-                                && type_lower != "text"
-                            {
-                                return Err(ParseError::InvalidArgument(format!(
-                                    "type must be 'image', 'video', or 'text', got: {}",
-                                    arg
-                                )));
-                            }
-                            SearchExpr::Type(type_lower)
-                        }
-                        "site" => SearchExpr::Site(arg),
-                        "fulltext" => SearchExpr::Fulltext(arg),
-                        "title" => SearchExpr::Title(arg),
-                        "meta" => SearchExpr::Meta(arg),
-                        "desc" => SearchExpr::Desc(arg),
-                        "url" => SearchExpr::Url(arg),
-                        "after" => {
-                            // Validate the time string can be parsed
-                            let now = Utc::now().with_timezone(&SEARCH_TIMEZONE);
-                            if timestring::parse(&arg, now, SEARCH_TIMEZONE).is_err() {
-                                return Err(ParseError::InvalidTimestamp(arg));
-                            }
-                            SearchExpr::After(arg)
-                        }
-                        "before" => {
-                            // Validate the time string can be parsed
-                            let now = Utc::now().with_timezone(&SEARCH_TIMEZONE);
-                            if timestring::parse(&arg, now, SEARCH_TIMEZONE).is_err() {
-                                return Err(ParseError::InvalidTimestamp(arg));
-                            }
-                            SearchExpr::Before(arg)
-                        }
-                        "during" => {
-                            // Validate the time string can be parsed AND is a range
-                            let now = Utc::now().with_timezone(&SEARCH_TIMEZONE);
-                            match timestring::parse(&arg, now, SEARCH_TIMEZONE) {
-                                Ok(spec) if spec.is_range() => SearchExpr::During(arg),
-                                Ok(_) => {
-                                    return Err(ParseError::InvalidArgument(format!(
-                                        "during requires a time range, not a specific moment: {}",
-                                        arg
-                                    )));
-                                }
-                                Err(_) => {
-                                    return Err(ParseError::InvalidTimestamp(arg));
-                                }
-                            }
-                        }
-                        _ => unreachable!(),
-                    };
+                    let expr = function_expr(&function_name, arg)?;
                     Ok((expr, pos))
                 }
                 _ => Err(ParseError::InvalidFunction(function_name)),
@@ -308,6 +344,356 @@ fn parse_expr(tokens: &[Token], start: usize) -> Result<(SearchExpr, usize), Par
     }
 }
 
+/// The functions that take a single argument, in either syntax.
+const FUNCTIONS: &[&str] = &[
+    "tag",
+    "type",
+    "site",
+    "fulltext",
+    "title",
+    "meta",
+    "desc",
+    "url",
+    "after",
+    "before",
+    "during",
+    "min-width",
+    "min-height",
+    "orientation",
+    "longer-than",
+    "shorter-than",
+    "color",
+];
+
+/// Check `arg` and build the expression for one of [`FUNCTIONS`].
+fn function_expr(function_name: &str, arg: String) -> Result<SearchExpr, ParseError> {
+    let expr = match function_name.to_lowercase().as_str() {
+        "tag" => SearchExpr::Tag(arg),
+        "type" => {
+            let type_lower = arg.to_lowercase();
+            if type_lower != "image" && type_lower != "video" && type_lower != "text" {
+                return Err(ParseError::InvalidArgument(format!(
+                    "type must be 'image', 'video', or 'text', got: {}",
+                    arg
+                )));
+            }
+            SearchExpr::Type(type_lower)
+        }
+        "site" => SearchExpr::Site(arg),
+        "fulltext" => SearchExpr::Fulltext(arg),
+        "title" => SearchExpr::Title(arg),
+        "meta" => SearchExpr::Meta(arg),
+        "desc" => SearchExpr::Desc(arg),
+        "url" => SearchExpr::Url(arg),
+        "after" => {
+            // Validate the time string can be parsed
+            let now = Utc::now().with_timezone(&SEARCH_TIMEZONE);
+            if timestring::parse(&arg, now, SEARCH_TIMEZONE).is_err() {
+                return Err(ParseError::InvalidTimestamp(arg));
+            }
+            SearchExpr::After(arg)
+        }
+        "before" => {
+            // Validate the time string can be parsed
+            let now = Utc::now().with_timezone(&SEARCH_TIMEZONE);
+            if timestring::parse(&arg, now, SEARCH_TIMEZONE).is_err() {
+                return Err(ParseError::InvalidTimestamp(arg));
+            }
+            SearchExpr::Before(arg)
+        }
+        "during" => {
+            // Validate the time string can be parsed AND is a range
+            let now = Utc::now().with_timezone(&SEARCH_TIMEZONE);
+            match timestring::parse(&arg, now, SEARCH_TIMEZONE) {
+                Ok(spec) if spec.is_range() => SearchExpr::During(arg),
+                Ok(_) => {
+                    return Err(ParseError::InvalidArgument(format!(
+                        "during requires a time range, not a specific moment: {}",
+                        arg
+                    )));
+                }
+                Err(_) => {
+                    return Err(ParseError::InvalidTimestamp(arg));
+                }
+            }
+        }
+        "min-width" => SearchExpr::MinWidth(parse_pixels(function_name, &arg)?),
+        "min-height" => SearchExpr::MinHeight(parse_pixels(function_name, &arg)?),
+        "orientation" => {
+            let orientation = match arg.to_lowercase().as_str() {
+                "portrait" => Orientation::Portrait,
+                "landscape" => Orientation::Landscape,
+                "square" => Orientation::Square,
+                _ => {
+                    return Err(ParseError::InvalidArgument(format!(
+                        "orientation must be 'portrait', 'landscape', or 'square', got: {}",
+                        arg
+                    )));
+                }
+            };
+            SearchExpr::Orientation(orientation)
+        }
+        "longer-than" => SearchExpr::LongerThan(parse_length(function_name, &arg)?),
+        "shorter-than" => SearchExpr::ShorterThan(parse_length(function_name, &arg)?),
+        "color" => SearchExpr::Color(ColorQuery::parse(&arg).ok_or_else(|| {
+            ParseError::InvalidArgument(format!(
+                "color requires a color name like \"red\" or a hex color like \"#aabbcc\", got: {}",
+                arg
+            ))
+        })?),
+        _ => return Err(ParseError::InvalidFunction(function_name.to_string())),
+    };
+    Ok(expr)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum InfixToken {
+    OpenParen,
+    CloseParen,
+    Or,
+    Not,
+    /// `value`, `"value"`, `field:value` or `field:"value"`
+    Term {
+        field: Option<String>,
+        value: String,
+        quoted: bool,
+    },
+}
+
+impl std::fmt::Display for InfixToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InfixToken::OpenParen => write!(f, "("),
+            InfixToken::CloseParen => write!(f, ")"),
+            InfixToken::Or => write!(f, "OR"),
+            InfixToken::Not => write!(f, "-"),
+            InfixToken::Term { field, value, .. } => match field {
+                Some(field) => write!(f, "{}:{}", field, value),
+                None => write!(f, "{}", value),
+            },
+        }
+    }
+}
+
+/// Parse the infix syntax:
+///
+/// - `cute` is `(tag "cute")`, and `"exact phrase"` is
+///   `(fulltext "exact phrase")`
+/// - `field:value` or `field:"some value"` is `(field "value")`, for any of
+///   the functions taking a single argument, like `type:video` or
+///   `after:"2 weeks ago"`
+/// - `-term` is `(not term)`
+/// - terms next to each other must all match; `OR` (or `|`) between them
+///   means either can, and binds looser
+/// - parentheses group terms, like `(cat OR dog) -nsfw`
+pub fn parse_infix_search(input: &str) -> Result<SearchExpr, ParseError> {
+    let tokens = tokenize_infix(input)?;
+    let mut parser = InfixParser {
+        tokens: &tokens,
+        pos: 0,
+    };
+    let expr = parser.or()?;
+    if let Some(token) = parser.peek() {
+        return Err(ParseError::UnexpectedToken(token.to_string()));
+    }
+    Ok(expr)
+}
+
+fn tokenize_infix(input: &str) -> Result<Vec<InfixToken>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&ch) = chars.peek() {
+        match ch {
+            ch if ch.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(InfixToken::OpenParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(InfixToken::CloseParen);
+            }
+            '-' => {
+                chars.next();
+                tokens.push(InfixToken::Not);
+            }
+            '"' => {
+                chars.next();
+                tokens.push(InfixToken::Term {
+                    field: None,
+                    value: read_quoted(&mut chars)?,
+                    quoted: true,
+                });
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&ch) = chars.peek() {
+                    if ch.is_whitespace() || matches!(ch, '(' | ')' | '"') {
+                        break;
+                    }
+                    word.push(ch);
+                    chars.next();
+                }
+
+                if word == "OR" || word == "|" {
+                    tokens.push(InfixToken::Or);
+                    continue;
+                }
+                if let Some(field) = word.strip_suffix(':') {
+                    if chars.peek() == Some(&'"') {
+                        chars.next();
+                        tokens.push(InfixToken::Term {
+                            field: Some(field.to_string()),
+                            value: read_quoted(&mut chars)?,
+                            quoted: true,
+                        });
+                        continue;
+                    }
+                }
+                tokens.push(match word.split_once(':') {
+                    Some((field, value)) if !field.is_empty() => InfixToken::Term {
+                        field: Some(field.to_string()),
+                        value: value.to_string(),
+                        quoted: false,
+                    },
+                    _ => InfixToken::Term {
+                        field: None,
+                        value: word,
+                        quoted: false,
+                    },
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// The rest of a quoted string, after the opening quote.
+fn read_quoted(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, ParseError> {
+    let mut value = String::new();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => value.push(chars.next().ok_or(ParseError::UnexpectedEnd)?),
+            '"' => return Ok(value),
+            ch => value.push(ch),
+        }
+    }
+    Err(ParseError::UnexpectedEnd)
+}
+
+struct InfixParser<'a> {
+    tokens: &'a [InfixToken],
+    pos: usize,
+}
+
+impl InfixParser<'_> {
+    fn peek(&self) -> Option<&InfixToken> {
+        self.tokens.get(self.pos)
+    }
+
+    fn or(&mut self) -> Result<SearchExpr, ParseError> {
+        let mut exprs = vec![self.and()?];
+        while self.peek() == Some(&InfixToken::Or) {
+            self.pos += 1;
+            exprs.push(self.and()?);
+        }
+        Ok(match exprs.len() {
+            1 => exprs.remove(0),
+            _ => SearchExpr::Or(exprs),
+        })
+    }
+
+    fn and(&mut self) -> Result<SearchExpr, ParseError> {
+        let mut exprs = Vec::new();
+        while let Some(token) = self.peek() {
+            if matches!(token, InfixToken::Or | InfixToken::CloseParen) {
+                break;
+            }
+            exprs.push(self.unary()?);
+        }
+        match exprs.len() {
+            0 => Err(match self.peek() {
+                Some(token) => ParseError::UnexpectedToken(token.to_string()),
+                None => ParseError::UnexpectedEnd,
+            }),
+            1 => Ok(exprs.remove(0)),
+            _ => Ok(SearchExpr::And(exprs)),
+        }
+    }
+
+    fn unary(&mut self) -> Result<SearchExpr, ParseError> {
+        let token = self.peek().cloned().ok_or(ParseError::UnexpectedEnd)?;
+        self.pos += 1;
+        match token {
+            InfixToken::Not => Ok(SearchExpr::Not(Box::new(self.unary()?))),
+            InfixToken::OpenParen => {
+                let expr = self.or()?;
+                if self.peek() != Some(&InfixToken::CloseParen) {
+                    return Err(ParseError::UnexpectedEnd);
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            InfixToken::Term {
+                field: None,
+                value,
+                quoted,
+            } if quoted => Ok(SearchExpr::Fulltext(value)),
+            InfixToken::Term {
+                field: None, value, ..
+            } => Ok(SearchExpr::Tag(value)),
+            InfixToken::Term {
+                field: Some(field),
+                value,
+                ..
+            } => {
+                let function_name = field.to_lowercase();
+                if !FUNCTIONS.contains(&function_name.as_str()) {
+                    return Err(ParseError::InvalidFunction(field));
+                }
+                if value.is_empty() {
+                    return Err(ParseError::InvalidArgument(format!(
+                        "{}: requires a value",
+                        field
+                    )));
+                }
+                function_expr(&function_name, value)
+            }
+            InfixToken::CloseParen | InfixToken::Or => {
+                Err(ParseError::UnexpectedToken(token.to_string()))
+            }
+        }
+    }
+}
+
+fn parse_pixels(function_name: &str, arg: &str) -> Result<u32, ParseError> {
+    arg.parse().map_err(|_| {
+        ParseError::InvalidArgument(format!(
+            "{} requires a number of pixels, got: {}",
+            function_name, arg
+        ))
+    })
+}
+
+fn parse_length(function_name: &str, arg: &str) -> Result<f64, ParseError> {
+    timestring::parse_duration(arg).ok_or_else(|| {
+        ParseError::InvalidArgument(format!(
+            "{} requires a length like \"90s\", \"5m\" or \"1:30\", got: {}",
+            function_name, arg
+        ))
+    })
+}
+
+/// Whether any of an item's files has extracted metadata satisfying `predicate`.
+/// Items that haven't been baked have no metadata and never match.
+fn any_media(item: &CrawlItem, predicate: impl Fn(&MediaMetadata) -> bool) -> bool {
+    item.media.values().any(predicate)
+}
+
 pub fn evaluate_search_expr(expr: &SearchExpr, item: &CrawlItem) -> bool {
     match expr {
         SearchExpr::And(exprs) => exprs.iter().all(|e| evaluate_search_expr(e, item)),
@@ -400,5 +786,64 @@ pub fn evaluate_search_expr(expr: &SearchExpr, item: &CrawlItem) -> bool {
                 .expect("Time string should be validated during parsing");
             spec.contains(item.source_published)
         }
+        SearchExpr::MinWidth(width) => any_media(item, |m| m.width.is_some_and(|w| w >= *width)),
+        SearchExpr::MinHeight(height) => {
+            any_media(item, |m| m.height.is_some_and(|h| h >= *height))
+        }
+        SearchExpr::Orientation(orientation) => {
+            any_media(item, |m| m.orientation() == Some(*orientation))
+        }
+        SearchExpr::LongerThan(seconds) => {
+            any_media(item, |m| m.duration.is_some_and(|d| d > *seconds))
+        }
+        SearchExpr::ShorterThan(seconds) => {
+            any_media(item, |m| m.duration.is_some_and(|d| d < *seconds))
+        }
+        SearchExpr::Color(query) => {
+            any_media(item, |m| m.palette.iter().any(|color| query.matches(*color)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compiled(query: &str) -> String {
+        parse_query(query).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_infix_compiles_to_s_expression() {
+        assert_eq!(
+            compiled(r#"cute -nsfw type:video site:r-aww after:"2 weeks ago" "exact phrase""#),
+            r#"(and (tag "cute") (not (tag "nsfw")) (type "video") (site "r-aww") (after "2 weeks ago") (fulltext "exact phrase"))"#
+        );
+        assert_eq!(
+            compiled("(cat OR dog) -nsfw sci-fi"),
+            r#"(and (or (tag "cat") (tag "dog")) (not (tag "nsfw")) (tag "sci-fi"))"#
+        );
+        assert_eq!(
+            compiled("a b | c"),
+            r#"(or (and (tag "a") (tag "b")) (tag "c"))"#
+        );
+        assert_eq!(compiled("min-width:1920"), "(min-width 1920)");
+
+        // S-expressions are still parsed as such, and print back the same
+        let s_expression = r#"(and (tag "say \"hi\"") (not (longer-than "90s")))"#;
+        assert!(is_s_expression(s_expression));
+        assert_eq!(compiled(s_expression), s_expression);
+        assert!(!is_s_expression("(title:foo OR bar)"));
+        assert!(is_s_expression(r#"(bogus "x")"#));
+    }
+
+    #[test]
+    fn test_infix_errors() {
+        assert!(parse_query("").is_err());
+        assert!(parse_query("typ:video").is_err());
+        assert!(parse_query("type:sound").is_err());
+        assert!(parse_query("(cat OR dog").is_err());
+        assert!(parse_query("cat OR").is_err());
+        assert!(parse_query("\"unterminated").is_err());
     }
 }
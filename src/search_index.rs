@@ -0,0 +1,543 @@
+//! An inverted index over a site source's items, so searches don't have to
+//! check every item against every term.
+//!
+//! A search is compiled into a [`Plan`] against the index: tags, sites and
+//! file types are looked up in postings lists, time terms become ranges of
+//! an array sorted by publish date, and text terms narrow the items down
+//! through a word index before the few left are checked the way
+//! [`evaluate_search_expr`] would. Terms the index doesn't cover, like
+//! media dimensions and colors, are checked item by item, but only for the
+//! items the rest of an `and` leaves.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde_json::Value;
+
+use crate::{
+    handlers::SiteSource,
+    reprocessors::extract_text_from_formatted_text,
+    search::{evaluate_search_expr, SearchExpr, SEARCH_TIMEZONE},
+    site::{CrawlItem, FileCrawlType},
+    timestring::{self, TimeSpec},
+};
+
+/// A set of documents, numbered by their position in the index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DocSet {
+    words: Vec<u64>,
+}
+
+impl DocSet {
+    fn empty(len: usize) -> Self {
+        DocSet {
+            words: vec![0; len.div_ceil(64)],
+        }
+    }
+
+    fn full(len: usize) -> Self {
+        let mut set = DocSet {
+            words: vec![u64::MAX; len.div_ceil(64)],
+        };
+        if !len.is_multiple_of(64) {
+            if let Some(last) = set.words.last_mut() {
+                *last = (1 << (len % 64)) - 1;
+            }
+        }
+        set
+    }
+
+    fn insert(&mut self, doc: usize) {
+        self.words[doc / 64] |= 1 << (doc % 64);
+    }
+
+    fn contains(&self, doc: usize) -> bool {
+        self.words[doc / 64] & (1 << (doc % 64)) != 0
+    }
+
+    fn is_empty(&self) -> bool {
+        self.words.iter().all(|word| *word == 0)
+    }
+
+    fn union(&mut self, other: &DocSet) {
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word |= other;
+        }
+    }
+
+    fn intersect(&mut self, other: &DocSet) {
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word &= other;
+        }
+    }
+
+    fn subtract(&mut self, other: &DocSet) {
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word &= !other;
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, word)| {
+            let mut word = *word;
+            std::iter::from_fn(move || {
+                (word != 0).then(|| {
+                    let bit = word.trailing_zeros() as usize;
+                    word &= word - 1;
+                    i * 64 + bit
+                })
+            })
+        })
+    }
+}
+
+/// A search compiled against an index, with its time strings resolved.
+enum Plan<'a> {
+    And(Vec<Plan<'a>>),
+    Or(Vec<Plan<'a>>),
+    Not(Box<Plan<'a>>),
+    /// The documents in a postings list
+    Postings(&'a [u32]),
+    /// Documents published in this range, inclusive
+    Published {
+        start: i64,
+        end: i64,
+    },
+    /// Documents among `candidates`, or among all of them if it's `None`,
+    /// that match `expr`
+    Verify {
+        candidates: Option<DocSet>,
+        expr: &'a SearchExpr,
+    },
+}
+
+impl Plan<'_> {
+    /// Roughly how much running the plan costs, so the cheap parts of an
+    /// `and` go first and leave the others fewer documents to check.
+    fn cost(&self) -> u8 {
+        match self {
+            Plan::And(plans) | Plan::Or(plans) => plans.iter().map(Plan::cost).max().unwrap_or(0),
+            Plan::Not(plan) => plan.cost(),
+            Plan::Postings(_) | Plan::Published { .. } => 0,
+            Plan::Verify {
+                candidates: Some(_),
+                ..
+            } => 1,
+            Plan::Verify {
+                candidates: None, ..
+            } => 2,
+        }
+    }
+}
+
+/// The items of a site source, newest first, indexed for searching.
+pub struct SearchIndex {
+    items: Vec<CrawlItem>,
+    /// Documents with each tag, lowercased
+    tags: HashMap<String, Vec<u32>>,
+    sites: HashMap<String, Vec<u32>>,
+    /// Documents with an image, video or text file
+    types: HashMap<&'static str, Vec<u32>>,
+    /// Documents with each lowercased word of their title, URL,
+    /// description, meta and text files
+    words: HashMap<String, Vec<u32>>,
+    /// Each document's publish date, in order
+    published: Vec<(i64, u32)>,
+}
+
+impl SearchIndex {
+    pub fn build(mut items: Vec<CrawlItem>) -> Self {
+        items.sort_by_key(|item| -item.source_published);
+
+        let mut index = SearchIndex {
+            items: vec![],
+            tags: HashMap::new(),
+            sites: HashMap::new(),
+            types: HashMap::new(),
+            words: HashMap::new(),
+            published: vec![],
+        };
+        fn post<K: std::hash::Hash + Eq>(postings: &mut HashMap<K, Vec<u32>>, key: K, doc: u32) {
+            let list = postings.entry(key).or_default();
+            if list.last() != Some(&doc) {
+                list.push(doc);
+            }
+        }
+
+        for (doc, item) in items.iter().enumerate() {
+            let doc = doc as u32;
+            for tag in &item.tags {
+                post(&mut index.tags, tag.to_string().to_lowercase(), doc);
+            }
+            post(&mut index.sites, item.site_settings.site_slug.clone(), doc);
+            for file in item.flat_files().values() {
+                let file_type = match file {
+                    FileCrawlType::Image { .. } => "image",
+                    FileCrawlType::Video { .. } => "video",
+                    FileCrawlType::Text { .. } => "text",
+                    _ => continue,
+                };
+                post(&mut index.types, file_type, doc);
+            }
+            for text in item_texts(item) {
+                for word in words(&text.to_lowercase()) {
+                    // Only allocate for words the document isn't posted under yet
+                    if index.words.get(word).and_then(|list| list.last()) != Some(&doc) {
+                        post(&mut index.words, word.to_string(), doc);
+                    }
+                }
+            }
+            index.published.push((item.source_published, doc));
+        }
+        index.published.sort_unstable();
+        index.items = items;
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// The items matching `expr`, newest first.
+    pub fn search(&self, expr: &SearchExpr) -> Vec<&CrawlItem> {
+        let now = Utc::now().with_timezone(&SEARCH_TIMEZONE);
+        let plan = self.compile(expr, now);
+        self.run(&plan, &DocSet::full(self.len()))
+            .iter()
+            .map(|doc| &self.items[doc])
+            .collect()
+    }
+
+    fn compile<'a>(&'a self, expr: &'a SearchExpr, now: DateTime<Tz>) -> Plan<'a> {
+        let postings = |list: Option<&'a Vec<u32>>| Plan::Postings(list.map_or(&[], Vec::as_slice));
+        let time = |time_str: &str| {
+            timestring::parse(time_str, now, SEARCH_TIMEZONE)
+                .expect("Time string should be validated during parsing")
+        };
+
+        match expr {
+            SearchExpr::And(exprs) => {
+                let mut plans: Vec<Plan> = exprs.iter().map(|e| self.compile(e, now)).collect();
+                plans.sort_by_key(Plan::cost);
+                Plan::And(plans)
+            }
+            SearchExpr::Or(exprs) => Plan::Or(exprs.iter().map(|e| self.compile(e, now)).collect()),
+            SearchExpr::Not(expr) => Plan::Not(Box::new(self.compile(expr, now))),
+            SearchExpr::Tag(tag) => postings(self.tags.get(&tag.to_lowercase())),
+            SearchExpr::Site(slug) => postings(self.sites.get(slug)),
+            SearchExpr::Type(file_type) => postings(self.types.get(file_type.as_str())),
+            SearchExpr::After(time_str) => Plan::Published {
+                start: time(time_str).for_after(),
+                end: i64::MAX,
+            },
+            SearchExpr::Before(time_str) => Plan::Published {
+                start: i64::MIN,
+                end: time(time_str).for_before(),
+            },
+            SearchExpr::During(time_str) => match time(time_str) {
+                TimeSpec::Moment(moment) => Plan::Published {
+                    start: moment,
+                    end: moment,
+                },
+                TimeSpec::Range { start, end } => Plan::Published { start, end },
+            },
+            SearchExpr::Fulltext(text)
+            | SearchExpr::Title(text)
+            | SearchExpr::Meta(text)
+            | SearchExpr::Desc(text)
+            | SearchExpr::Url(text) => Plan::Verify {
+                candidates: self.text_candidates(text),
+                expr,
+            },
+            SearchExpr::MinWidth(_)
+            | SearchExpr::MinHeight(_)
+            | SearchExpr::Orientation(_)
+            | SearchExpr::LongerThan(_)
+            | SearchExpr::ShorterThan(_)
+            | SearchExpr::Color(_) => Plan::Verify {
+                candidates: None,
+                expr,
+            },
+        }
+    }
+
+    /// Every document that could contain `text`, or `None` if it has no
+    /// words to look up. Text found inside a field is made up of the end
+    /// of one of its words, whole words, and the start of another, or is
+    /// inside a single word.
+    fn text_candidates(&self, text: &str) -> Option<DocSet> {
+        let text = text.to_lowercase();
+        let query: Vec<&str> = words(&text).collect();
+        let (first, last) = (*query.first()?, *query.last()?);
+
+        let matching = |matches: &dyn Fn(&str) -> bool| {
+            let mut set = DocSet::empty(self.len());
+            for (word, docs) in &self.words {
+                if matches(word) {
+                    docs.iter().for_each(|doc| set.insert(*doc as usize));
+                }
+            }
+            set
+        };
+
+        if query.len() == 1 {
+            return Some(matching(&|word| word.contains(first)));
+        }
+        let mut candidates = matching(&|word| word.ends_with(first));
+        for middle in &query[1..query.len() - 1] {
+            let mut set = DocSet::empty(self.len());
+            for doc in self.words.get(*middle).into_iter().flatten() {
+                set.insert(*doc as usize);
+            }
+            candidates.intersect(&set);
+        }
+        candidates.intersect(&matching(&|word| word.starts_with(last)));
+        Some(candidates)
+    }
+
+    /// The documents in `within` that `plan` matches.
+    fn run(&self, plan: &Plan, within: &DocSet) -> DocSet {
+        match plan {
+            Plan::And(plans) => {
+                let mut set = within.clone();
+                for plan in plans {
+                    if set.is_empty() {
+                        break;
+                    }
+                    set = self.run(plan, &set);
+                }
+                set
+            }
+            Plan::Or(plans) => {
+                let mut set = DocSet::empty(self.len());
+                let mut remaining = within.clone();
+                for plan in plans {
+                    let matched = self.run(plan, &remaining);
+                    set.union(&matched);
+                    remaining.subtract(&matched);
+                }
+                set
+            }
+            Plan::Not(plan) => {
+                let mut set = within.clone();
+                set.subtract(&self.run(plan, within));
+                set
+            }
+            Plan::Postings(docs) => {
+                let mut set = DocSet::empty(self.len());
+                for doc in docs.iter().map(|doc| *doc as usize) {
+                    if within.contains(doc) {
+                        set.insert(doc);
+                    }
+                }
+                set
+            }
+            Plan::Published { start, end } => {
+                let from = self.published.partition_point(|(at, _)| at < start);
+                let to = self.published.partition_point(|(at, _)| at <= end);
+                let mut set = DocSet::empty(self.len());
+                for (_, doc) in &self.published[from..to.max(from)] {
+                    if within.contains(*doc as usize) {
+                        set.insert(*doc as usize);
+                    }
+                }
+                set
+            }
+            Plan::Verify { candidates, expr } => {
+                let mut docs = within.clone();
+                if let Some(candidates) = candidates {
+                    docs.intersect(candidates);
+                }
+                let mut set = DocSet::empty(self.len());
+                for doc in docs.iter() {
+                    if evaluate_search_expr(expr, &self.items[doc]) {
+                        set.insert(doc);
+                    }
+                }
+                set
+            }
+        }
+    }
+}
+
+/// The runs of letters and digits in `text`.
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+}
+
+/// Each piece of an item's text that full-text searches look in. Words are
+/// split within each piece, never across two of them.
+fn item_texts(item: &CrawlItem) -> Vec<String> {
+    fn meta_texts(value: &Value, texts: &mut Vec<String>) {
+        match value {
+            Value::String(s) => texts.push(s.clone()),
+            Value::Number(n) => texts.push(n.to_string()),
+            Value::Object(map) => {
+                for (key, value) in map {
+                    texts.push(key.clone());
+                    meta_texts(value, texts);
+                }
+            }
+            Value::Array(values) => values.iter().for_each(|value| meta_texts(value, texts)),
+            Value::Bool(_) | Value::Null => {}
+        }
+    }
+
+    let mut texts = vec![
+        item.title.clone(),
+        item.url.clone(),
+        extract_text_from_formatted_text(&item.description),
+    ];
+    meta_texts(&item.meta, &mut texts);
+    for file in item.flat_files().into_values() {
+        if let FileCrawlType::Text { content, .. } = file {
+            texts.push(content);
+        }
+    }
+    texts
+}
+
+/// The index, and the `loaded_at` of each WorkDir it was built from.
+type CachedIndex = (Vec<u128>, Arc<SearchIndex>);
+
+/// The search index of a site source. Built up front and rebuilt by
+/// [`SharedSearchIndex::refresh`] after any of its WorkDirs reloads, so
+/// searches never wait for a build.
+#[derive(Clone)]
+pub struct SharedSearchIndex {
+    site_source: SiteSource,
+    cached: Arc<RwLock<CachedIndex>>,
+}
+
+impl SharedSearchIndex {
+    pub fn new(site_source: SiteSource) -> Self {
+        let cached = Self::build(&site_source);
+        SharedSearchIndex {
+            site_source,
+            cached: Arc::new(RwLock::new(cached)),
+        }
+    }
+
+    /// The index as of the last build.
+    pub fn get(&self) -> Arc<SearchIndex> {
+        self.cached.read().unwrap().1.clone()
+    }
+
+    /// Rebuild the index if any of the WorkDirs reloaded since it was built.
+    pub fn refresh(&self) {
+        if self.cached.read().unwrap().0 == self.site_source.loaded_at() {
+            return;
+        }
+        let cached = Self::build(&self.site_source);
+        *self.cached.write().unwrap() = cached;
+    }
+
+    fn build(site_source: &SiteSource) -> CachedIndex {
+        // Read before the items, so a reload in between only means the next
+        // refresh builds the index again
+        let loaded_at = site_source.loaded_at();
+        let index = SearchIndex::build(site_source.all_items());
+        (loaded_at, Arc::new(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::parse_query;
+    use serde_json::json;
+
+    fn item(key: &str, published: i64, tags: &[&str], meta: Value, text: &str) -> CrawlItem {
+        let mut item = CrawlItem::for_test(key, &format!("{} Title", key), tags);
+        item.url = format!("https://example.com/{}", key);
+        item.meta = meta;
+        item.source_published = published;
+        item.files.insert(
+            "t".to_string(),
+            FileCrawlType::Text {
+                key: "t".to_string(),
+                content: text.to_string(),
+            },
+        );
+        item
+    }
+
+    #[test]
+    fn test_index_matches_linear_scan() {
+        let items = vec![
+            item(
+                "a",
+                1_700_000_000_000,
+                &["Cat", "cute"],
+                json!({ "score": 1.5 }),
+                "hello world",
+            ),
+            item(
+                "b",
+                1_600_000_000_000,
+                &["dog"],
+                json!({ "nested": { "artist": "Bob Ross" } }),
+                "",
+            ),
+            item(
+                "c",
+                1_750_000_000_000,
+                &["cat"],
+                json!([]),
+                "say hello-world again",
+            ),
+            item("d", 1_600_000_000_000, &[], json!({}), "cathedral"),
+        ];
+        let index = SearchIndex::build(items.clone());
+        let keys = |items: Vec<&CrawlItem>| {
+            items
+                .iter()
+                .map(|item| item.key.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            keys(index.search(&parse_query(r#""ello""#).unwrap())),
+            ["c", "a"]
+        );
+
+        for query in [
+            "cat",
+            "cat -cute",
+            "cat OR dog",
+            "-cat",
+            r#""lo wor""#,
+            r#""ello""#,
+            r#""o-w""#,
+            r#""at""#,
+            r#""!!""#,
+            "meta:1.5",
+            "meta:artist",
+            r#"meta:"b ro""#,
+            "title:title url:example.com/b",
+            "type:text",
+            "type:video",
+            r#"after:"2023-11-14T22:13:20Z""#,
+            r#"before:"2020-09-13T12:26:40Z" -dog"#,
+            "during:2020",
+            r#"(cat OR "cathedral") before:2024"#,
+            "min-width:10",
+        ] {
+            let expr = parse_query(query).unwrap();
+            let mut expected: Vec<&CrawlItem> = items
+                .iter()
+                .filter(|item| evaluate_search_expr(&expr, item))
+                .collect();
+            expected.sort_by_key(|item| -item.source_published);
+            assert_eq!(keys(index.search(&expr)), keys(expected), "{}", query);
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! Settings shared by every site a server loads, read from the file given
+//! with `--server-config`.
+//!
+//! ```json
+//! {
+//!   "reprocessors": [
+//!     { "name": "normalize", "position": "before", "reprocessors": [{ "type": "normalize-tags" }] },
+//!     { "name": "blocklist", "position": "after", "reprocessors": [
+//!       { "type": "filter-out-items-with-tag", "tags": ["spam"] }
+//!     ] }
+//!   ],
+//!   "tag_rules": "tag-rules.json"
+//! }
+//! ```
+
+use std::{
+    collections::HashSet,
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{Error, Result, ResultExt},
+    reprocessors::Reprocessor,
+    tag_rules::TagRules,
+};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerConfig {
+    /// Named sets of reprocessors every site runs, in this order, around
+    /// its own. Sites can skip a set by listing its name in their
+    /// `skip_server_reprocessors`.
+    #[serde(default)]
+    pub reprocessors: Vec<SharedReprocessors>,
+    /// File with tag aliases, implications and deprecations for every site,
+    /// relative to the server config
+    #[serde(default)]
+    pub tag_rules: Option<PathBuf>,
+    /// The rules read from `tag_rules`
+    #[serde(skip)]
+    pub tags: TagRules,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Position {
+    /// Before the site's own reprocessors
+    #[default]
+    Before,
+    /// After the site's own reprocessors
+    After,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharedReprocessors {
+    pub name: String,
+    #[serde(default)]
+    pub position: Position,
+    pub reprocessors: Vec<Reprocessor>,
+}
+
+/// One reprocessor of a site's full list, and the server-wide set it came
+/// from, if it isn't one of the site's own.
+#[derive(Debug, Clone, Copy)]
+pub struct PipelineStep<'a> {
+    pub set: Option<&'a str>,
+    pub reprocessor: &'a Reprocessor,
+}
+
+impl ServerConfig {
+    /// Read and check a server config and the tag rules it names. Its
+    /// reprocessors are compiled, as the sites' are when a WorkDir is loaded.
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).context("Unable to open the server config")?;
+        let mut config: ServerConfig =
+            serde_json::from_reader(file).context("The server config was not well-formatted")?;
+
+        // Paths in the config are relative to it
+        let dir = path.parent().unwrap_or(Path::new("."));
+        // Read first, as the reprocessors' queries are expanded through them
+        if let Some(tag_rules) = &config.tag_rules {
+            config.tags = TagRules::load(&dir.join(tag_rules))?;
+        }
+
+        let mut names = HashSet::new();
+        for set in &config.reprocessors {
+            if !names.insert(set.name.as_str()) {
+                return Err(Error::Config(format!(
+                    "server reprocessor set {:?} is defined twice",
+                    set.name
+                )));
+            }
+            for reprocessor in &set.reprocessors {
+                reprocessor.compile(dir, &config.tags).map_err(|e| {
+                    Error::Config(format!("server reprocessor set {:?}: {}", set.name, e))
+                })?;
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn has_set(&self, name: &str) -> bool {
+        self.reprocessors.iter().any(|set| set.name == name)
+    }
+
+    /// Everything a site runs, in order: the shared sets placed before its
+    /// own reprocessors, then `site`, then the sets placed after, leaving
+    /// out the sets named in `skip`.
+    pub fn pipeline<'a>(
+        &'a self,
+        site: &'a [Reprocessor],
+        skip: &[String],
+    ) -> Vec<PipelineStep<'a>> {
+        let shared = |position: Position| {
+            self.reprocessors
+                .iter()
+                .filter(move |set| set.position == position && !skip.contains(&set.name))
+                .flat_map(|set| {
+                    set.reprocessors.iter().map(|reprocessor| PipelineStep {
+                        set: Some(set.name.as_str()),
+                        reprocessor,
+                    })
+                })
+        };
+
+        shared(Position::Before)
+            .chain(site.iter().map(|reprocessor| PipelineStep {
+                set: None,
+                reprocessor,
+            }))
+            .chain(shared(Position::After))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_pipeline_order_and_skips() {
+        let config: ServerConfig = serde_json::from_value(json!({
+            "reprocessors": [
+                { "name": "blocklist", "position": "after", "reprocessors": [
+                    { "type": "filter-out-items-with-tag", "tags": ["spam"] }
+                ] },
+                { "name": "normalize", "reprocessors": [{ "type": "normalize-tags" }] },
+            ],
+        }))
+        .unwrap();
+        let site: Vec<Reprocessor> =
+            serde_json::from_value(json!([{ "type": "sort-videos-first" }])).unwrap();
+
+        let steps = |skip: &[String]| {
+            config
+                .pipeline(&site, skip)
+                .into_iter()
+                .map(|step| (step.set, step.reprocessor.name()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            steps(&[]),
+            vec![
+                (Some("normalize"), "normalize-tags"),
+                (None, "sort-videos-first"),
+                (Some("blocklist"), "filter-out-items-with-tag"),
+            ]
+        );
+        assert_eq!(
+            steps(&["blocklist".to_string()]),
+            vec![
+                (Some("normalize"), "normalize-tags"),
+                (None, "sort-videos-first"),
+            ]
+        );
+    }
+}
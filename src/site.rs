@@ -1,7 +1,11 @@
+use std::collections::HashMap;
 use std::fmt::Display;
 use std::path::PathBuf;
+use std::sync::Arc;
 
+use crate::bake::{BakeConfig, MediaMetadata};
 use crate::collections::*;
+use crate::dupes::ClusterMember;
 use crate::serde::*;
 use indexmap::IndexMap;
 use maud::html;
@@ -119,6 +123,8 @@ pub struct SiteSettings {
     pub hide_titles: bool,
     /// Path to the work directory for this item's site (for thumbnail lookups)
     pub work_dir_path: Option<PathBuf>,
+    /// Widths and formats bake resizes this site's images to
+    pub bake: BakeConfig,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -189,6 +195,36 @@ pub struct CrawlItem {
     #[serde(skip)]
     #[serde(default)]
     pub site_settings: SiteSettings,
+
+    /// Metadata extracted by bake, keyed by file key. Populated at runtime,
+    /// not persisted.
+    #[serde(skip)]
+    #[serde(default)]
+    pub media: HashMap<String, MediaMetadata>,
+
+    /// Copies of this item found on other sites (or elsewhere on this one).
+    /// Populated at runtime for detail pages, not persisted.
+    #[serde(skip)]
+    #[serde(default)]
+    pub also_appears_in: Vec<ClusterMember>,
+
+    /// The item's key on its own site, when `key` has been namespaced for the
+    /// `all` view. Populated at runtime, not persisted.
+    #[serde(skip)]
+    #[serde(default)]
+    pub original_key: Option<String>,
+
+    /// Left out of listings, search and random picks, but still reachable by
+    /// its URL. Set by the `hide-matching` reprocessor, not persisted.
+    #[serde(skip)]
+    #[serde(default)]
+    pub hidden: bool,
+
+    /// The baked placeholder as a `data:` URI, read when the WorkDir is
+    /// loaded. Populated at runtime, not persisted.
+    #[serde(skip)]
+    #[serde(default)]
+    pub placeholder: Option<Arc<str>>,
 }
 
 impl crate::collections::GetKey for CrawlItem {
@@ -197,6 +233,28 @@ impl crate::collections::GetKey for CrawlItem {
     }
 }
 
+#[cfg(test)]
+impl CrawlItem {
+    /// A minimal item for tests, with no files and simple tags only. Tests set
+    /// whatever else they need on the result.
+    pub fn for_test(key: &str, title: &str, tags: &[&str]) -> CrawlItem {
+        serde_json::from_value(serde_json::json!({
+            "key": key,
+            "title": title,
+            "url": "",
+            "description": { "format": "plaintext", "value": "" },
+            "meta": {},
+            "sourcePublished": 0,
+            "firstSeen": 0,
+            "lastSeen": 0,
+            "seenInLastRefresh": true,
+            "tags": tags,
+            "files": [],
+        }))
+        .unwrap()
+    }
+}
+
 impl CrawlItem {
     /// Returns the relative path to the thumbnail for this item, if one exists.
     /// Uses `self.site_settings.work_dir_path` internally to check for auto-generated thumbnails.
@@ -217,13 +275,8 @@ impl CrawlItem {
         // Requires work_dir_path to be set in site_settings
         let work_dir_path = self.site_settings.work_dir_path.as_ref()?;
 
-        let flat_files = self.flat_files();
-        let first_usable_file = flat_files
-            .values()
-            .find(|file| file.is_downloaded() && (file.is_image() || file.is_video()));
-
-        if let Some(file) = first_usable_file {
-            let auto_path = self.calculate_auto_thumbnail_path(work_dir_path, file);
+        if let Some(file) = self.first_thumbnailable_file() {
+            let auto_path = self.calculate_auto_thumbnail_path(work_dir_path, &file);
             if auto_path.exists() {
                 Some(
                     auto_path
@@ -240,6 +293,12 @@ impl CrawlItem {
         }
     }
 
+    /// Extracted metadata for one of the item's files or previews, if bake
+    /// has probed it.
+    pub fn media_for(&self, file: &FileCrawlType) -> Option<&MediaMetadata> {
+        self.media.get(file.get_key())
+    }
+
     /// Take the files and replace any intermediate files with their nested files
     pub fn flat_files(&self) -> IndexMap<String, FileCrawlType> {
         self.files
@@ -0,0 +1,343 @@
+//! Relationships between tags, shared by every site and read from the file
+//! the server config names in `tag_rules`.
+//!
+//! ```json
+//! {
+//!   "aliases": { "kitty": "cat" },
+//!   "implications": { "kitten": ["cat"], "cat": ["animal"] },
+//!   "deprecated": ["tagme"]
+//! }
+//! ```
+//!
+//! Aliases and deprecations rewrite each site's items after its
+//! reprocessors have run. Implications are resolved when searching, on tag
+//! pages and in reprocessor queries, so `(tag "animal")` and `/tag/animal`
+//! also find items tagged `kitten`. Tags are matched without regard to case.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    fs::File,
+    path::Path,
+};
+
+use indexmap::IndexMap;
+use serde::Deserialize;
+
+use crate::{
+    errors::{Error, Result, ResultExt},
+    search::SearchExpr,
+    site::{CrawlItem, CrawlTag},
+};
+
+#[derive(Debug, Default, Deserialize)]
+struct TagRulesFile {
+    #[serde(default)]
+    aliases: BTreeMap<String, String>,
+    #[serde(default)]
+    implications: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    deprecated: Vec<String>,
+}
+
+/// Checked tag rules, with alias chains and implications already followed
+/// to the end.
+#[derive(Debug, Clone, Default)]
+pub struct TagRules {
+    /// Each alias and the tag it ends up as
+    aliases: HashMap<String, String>,
+    deprecated: HashSet<String>,
+    /// Every tag each tag implies, nearest first
+    implies: BTreeMap<String, Vec<String>>,
+    /// Every tag implying each tag
+    implied_by: HashMap<String, Vec<String>>,
+}
+
+impl TagRules {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).context("Unable to open the tag rules")?;
+        let rules: TagRulesFile =
+            serde_json::from_reader(file).context("The tag rules were not well-formatted")?;
+        Self::check(rules)
+    }
+
+    fn check(rules: TagRulesFile) -> Result<Self> {
+        let lower = |tag: &String| tag.to_lowercase();
+        let direct_aliases: HashMap<String, String> = rules
+            .aliases
+            .iter()
+            .map(|(from, to)| (lower(from), lower(to)))
+            .collect();
+        let deprecated: HashSet<String> = rules.deprecated.iter().map(lower).collect();
+
+        let mut aliases = HashMap::new();
+        for from in direct_aliases.keys() {
+            let mut chain = vec![from.clone()];
+            let mut to = from;
+            while let Some(next) = direct_aliases.get(to) {
+                chain.push(next.clone());
+                if chain[..chain.len() - 1].contains(next) {
+                    return Err(Error::Config(format!(
+                        "tag aliases form a cycle: {}",
+                        chain.join(" -> ")
+                    )));
+                }
+                to = next;
+            }
+            if deprecated.contains(from) {
+                return Err(Error::Config(format!(
+                    "tag {:?} is deprecated but is also an alias of {:?}",
+                    from, to
+                )));
+            }
+            if deprecated.contains(to) {
+                return Err(Error::Config(format!(
+                    "tag {:?} is an alias of {:?}, which is deprecated",
+                    from, to
+                )));
+            }
+            aliases.insert(from.clone(), to.clone());
+        }
+
+        // Implications are between the tags aliases end up as
+        let canonical = |tag: &String| {
+            let tag = lower(tag);
+            aliases.get(&tag).cloned().unwrap_or(tag)
+        };
+        let mut direct: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (tag, implied) in &rules.implications {
+            let tag = canonical(tag);
+            for implied in implied {
+                let implied = canonical(implied);
+                if let Some(tag) = [&tag, &implied]
+                    .into_iter()
+                    .find(|t| deprecated.contains(*t))
+                {
+                    return Err(Error::Config(format!(
+                        "tag {:?} is deprecated but is used in an implication",
+                        tag
+                    )));
+                }
+                let targets = direct.entry(tag.clone()).or_default();
+                if !targets.contains(&implied) {
+                    targets.push(implied);
+                }
+            }
+        }
+        if let Some(cycle) = find_cycle(&direct) {
+            return Err(Error::Config(format!(
+                "tag implications form a cycle: {}",
+                cycle.join(" -> ")
+            )));
+        }
+
+        let mut implies = BTreeMap::new();
+        let mut implied_by: HashMap<String, Vec<String>> = HashMap::new();
+        for tag in direct.keys() {
+            let mut all: Vec<String> = vec![];
+            let mut queue: VecDeque<&String> = direct[tag].iter().collect();
+            while let Some(next) = queue.pop_front() {
+                if all.contains(next) {
+                    continue;
+                }
+                all.push(next.clone());
+                queue.extend(direct.get(next).into_iter().flatten());
+            }
+            for implied in &all {
+                implied_by
+                    .entry(implied.clone())
+                    .or_default()
+                    .push(tag.clone());
+            }
+            implies.insert(tag.clone(), all);
+        }
+
+        Ok(TagRules {
+            aliases,
+            deprecated,
+            implies,
+            implied_by,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.aliases.is_empty() && self.deprecated.is_empty() && self.implies.is_empty()
+    }
+
+    /// Every tag `tag` implies, directly or through others.
+    pub fn implies(&self, tag: &str) -> &[String] {
+        self.implies
+            .get(&tag.to_lowercase())
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Rename aliased tags and remove deprecated ones. A tag that an alias
+    /// turns into one the item already has is dropped.
+    pub fn apply(&self, items: &mut IndexMap<String, CrawlItem>) {
+        if self.aliases.is_empty() && self.deprecated.is_empty() {
+            return;
+        }
+        for item in items.values_mut() {
+            let mut seen = HashSet::new();
+            item.tags = std::mem::take(&mut item.tags)
+                .into_iter()
+                .filter(|tag| !self.deprecated.contains(&tag.to_string().to_lowercase()))
+                .map(
+                    |tag| match self.aliases.get(&tag.to_string().to_lowercase()) {
+                        None => tag,
+                        Some(to) => match tag {
+                            CrawlTag::Simple(_) => CrawlTag::Simple(to.clone()),
+                            CrawlTag::Detailed { group, .. } => CrawlTag::Detailed {
+                                group,
+                                value: to.clone(),
+                            },
+                        },
+                    },
+                )
+                .filter(|tag| seen.insert(tag.to_string().to_lowercase()))
+                .collect();
+        }
+    }
+
+    /// Rewrite the `tag` terms of a search so that they also match the tags
+    /// implying them, and search for an alias's tag in its place.
+    pub fn expand(&self, expr: SearchExpr) -> SearchExpr {
+        match expr {
+            SearchExpr::And(exprs) => {
+                SearchExpr::And(exprs.into_iter().map(|e| self.expand(e)).collect())
+            }
+            SearchExpr::Or(exprs) => {
+                SearchExpr::Or(exprs.into_iter().map(|e| self.expand(e)).collect())
+            }
+            SearchExpr::Not(expr) => SearchExpr::Not(Box::new(self.expand(*expr))),
+            SearchExpr::Tag(tag) => {
+                let tag = match self.aliases.get(&tag.to_lowercase()) {
+                    Some(to) => to.clone(),
+                    None => tag,
+                };
+                match self.implied_by.get(&tag.to_lowercase()) {
+                    None => SearchExpr::Tag(tag),
+                    Some(implying) => SearchExpr::Or(
+                        std::iter::once(tag)
+                            .chain(implying.iter().cloned())
+                            .map(SearchExpr::Tag)
+                            .collect(),
+                    ),
+                }
+            }
+            expr => expr,
+        }
+    }
+}
+
+/// A path through `graph` that comes back to where it started, if any.
+fn find_cycle(graph: &BTreeMap<String, Vec<String>>) -> Option<Vec<String>> {
+    fn visit<'a>(
+        graph: &'a BTreeMap<String, Vec<String>>,
+        tag: &'a String,
+        path: &mut Vec<&'a String>,
+        done: &mut HashSet<&'a String>,
+    ) -> Option<Vec<String>> {
+        if let Some(start) = path.iter().position(|t| *t == tag) {
+            let mut cycle: Vec<String> = path[start..].iter().map(|t| t.to_string()).collect();
+            cycle.push(tag.clone());
+            return Some(cycle);
+        }
+        if done.contains(tag) {
+            return None;
+        }
+        path.push(tag);
+        for next in graph.get(tag).into_iter().flatten() {
+            if let Some(cycle) = visit(graph, next, path, done) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        done.insert(tag);
+        None
+    }
+
+    let mut done = HashSet::new();
+    graph
+        .keys()
+        .find_map(|tag| visit(graph, tag, &mut vec![], &mut done))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reprocessors::Reprocessor;
+    use crate::search::{evaluate_search_expr, parse_search_expr};
+    use serde_json::json;
+
+    fn rules(value: serde_json::Value) -> Result<TagRules> {
+        TagRules::check(serde_json::from_value(value).unwrap())
+    }
+
+    fn item(key: &str, tags: &[&str]) -> (String, CrawlItem) {
+        (key.to_string(), CrawlItem::for_test(key, key, tags))
+    }
+
+    #[test]
+    fn test_aliases_implications_and_deprecations() {
+        let rules = rules(json!({
+            "aliases": { "kitty": "Kitten", "kitteh": "kitty" },
+            "implications": { "kitten": ["cat"], "cat": ["animal"] },
+            "deprecated": ["tagme"],
+        }))
+        .unwrap();
+        assert_eq!(rules.implies("Kitten"), ["cat", "animal"]);
+
+        let mut items = IndexMap::from([
+            item("a", &["kitteh", "kitten", "tagme"]),
+            item("b", &["cat"]),
+            item("c", &["dog"]),
+        ]);
+        rules.apply(&mut items);
+        let tags: Vec<String> = items["a"].tags.iter().map(|t| t.to_string()).collect();
+        assert_eq!(tags, ["kitten"]);
+
+        let matching = |query: &str| {
+            let expr = rules.expand(parse_search_expr(query).unwrap());
+            items
+                .values()
+                .filter(|item| evaluate_search_expr(&expr, item))
+                .map(|item| item.key.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(matching(r#"(tag "animal")"#), ["a", "b"]);
+        assert_eq!(matching(r#"(tag "kitty")"#), ["a"]);
+        assert_eq!(matching(r#"(not (tag "cat"))"#), ["c"]);
+    }
+
+    #[test]
+    fn test_reprocessor_queries_use_implications() {
+        let rules = rules(json!({ "implications": { "kitten": ["cat"] } })).unwrap();
+        let mut items = IndexMap::from([item("a", &["kitten"]), item("b", &["dog"])]);
+        let reprocessor: Reprocessor = serde_json::from_value(json!({
+            "type": "remove-matching",
+            "query": r#"(tag "cat")"#,
+        }))
+        .unwrap();
+        reprocessor.compile(Path::new("."), &rules).unwrap();
+        reprocessor.apply(&mut items);
+        assert_eq!(items.keys().collect::<Vec<_>>(), ["b"]);
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        let error = |value| rules(value).unwrap_err().to_string();
+        assert!(error(json!({ "aliases": { "a": "b", "b": "a" } })).contains("cycle"));
+        assert!(error(json!({
+            "implications": { "kitten": ["cat"], "cat": ["animal"], "animal": ["kitten"] }
+        }))
+        .contains("animal -> kitten -> cat -> animal"));
+        assert!(error(
+            json!({ "aliases": { "kitty": "cat" }, "implications": { "cat": ["kitty"] } })
+        )
+        .contains("cycle"));
+        assert!(
+            error(json!({ "aliases": { "a": "b" }, "deprecated": ["b"] })).contains("deprecated")
+        );
+    }
+}
@@ -14,28 +14,53 @@ impl ThreadSafeWorkDir {
         }
     }
 
-    pub fn check_for_updates(&self) {
+    /// Reload the WorkDir if its sources changed since it was loaded.
+    /// Returns the keys of items the reload brought in, to the site's own
+    /// view or to the `all` view.
+    pub fn check_for_updates(&self) -> Vec<String> {
         // Read-only snapshot (drops before we take the write lock)
-        let (prev_ts, workdir_path) = {
+        let (prev_ts, workdir_path, server_config) = {
             let workdir = self.work_dir.read().expect("work_dir read poisoned");
-            (workdir.last_seen_modified, workdir.path.clone())
+            (
+                workdir.last_seen_modified,
+                workdir.path.clone(),
+                workdir.server_config.clone(),
+            )
         };
 
-        // Single stat; treat missing file as timestamp 0
-        let latest_ts = std::fs::metadata(&workdir_path.join("crawled.json"))
-            .ok()
-            .and_then(|m| m.modified().ok())
-            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-            .map(|d| d.as_secs())
-            .unwrap_or(0);
+        // Treat missing files as timestamp 0
+        let latest_ts = WorkDir::sources_modified(&workdir_path);
 
         if latest_ts > prev_ts {
             println!("Noticed update for {}", workdir_path.to_string_lossy());
 
-            let replacement = WorkDir::new(workdir_path.clone()).expect("rebuild WorkDir failed");
+            let replacement =
+                WorkDir::new(workdir_path.clone(), server_config).expect("rebuild WorkDir failed");
 
             let mut workdir = self.work_dir.write().expect("work_dir write poisoned");
+            let mut added: Vec<String> = replacement
+                .crawled
+                .items
+                .keys()
+                .filter(|key| !workdir.crawled.items.contains_key(*key))
+                .cloned()
+                .collect();
+            // Items the `all` view has of its own, as thumbnails are made per
+            // key and so shared by items in both
+            if replacement.crawled_for_all.is_some() {
+                let previous = workdir.items_for_all_view();
+                for key in replacement.items_for_all_view().items.keys() {
+                    if !previous.items.contains_key(key)
+                        && !replacement.crawled.items.contains_key(key)
+                    {
+                        added.push(key.clone());
+                    }
+                }
+            }
             *workdir = replacement;
+            return added;
         }
+
+        vec![]
     }
 }
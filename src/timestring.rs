@@ -14,6 +14,9 @@
 //! - **ISO dates**: `"2025-01-15"` (date only, treated as full day)
 //! - **ISO8601**: `"2024-01-01T00:00:00Z"`
 //! - **Unix milliseconds**: `"1704067200000"` (must be > 4 digits)
+//!
+//! [`parse_duration`] separately parses lengths of time such as `"5m"` or `"1:30"`,
+//! and [`find_date`] picks a date out of text such as a title or URL.
 
 use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone};
 use chrono_tz::Tz;
@@ -455,6 +458,68 @@ fn try_parse_iso_date(input: &str, tz: Tz) -> Option<TimeSpec> {
     })
 }
 
+/// Parse a length of time such as `"90s"`, `"5m"`, `"1h30m"`, `"1:30"` or
+/// `"1:02:03"` into seconds. A bare number is taken as seconds.
+pub fn parse_duration(input: &str) -> Option<f64> {
+    let input = input.trim().to_lowercase();
+    if input.is_empty() {
+        return None;
+    }
+
+    if let Ok(seconds) = input.parse::<f64>() {
+        return (seconds >= 0.0).then_some(seconds);
+    }
+
+    if input.contains(':') {
+        let parts = input.split(':').collect::<Vec<_>>();
+        if parts.len() > 3 {
+            return None;
+        }
+        return parts.iter().try_fold(0.0, |total, part| {
+            let value = part.parse::<f64>().ok()?;
+            (value >= 0.0).then_some(total * 60.0 + value)
+        });
+    }
+
+    let re = Regex::new(r"^(?:(\d+(?:\.\d+)?)h)?\s*(?:(\d+(?:\.\d+)?)m)?\s*(?:(\d+(?:\.\d+)?)s)?$")
+        .ok()?;
+    let caps = re.captures(&input)?;
+    let unit = |i: usize| caps.get(i).map(|m| m.as_str().parse::<f64>().ok());
+    let (hours, minutes, seconds) = (unit(1), unit(2), unit(3));
+    if hours.is_none() && minutes.is_none() && seconds.is_none() {
+        return None;
+    }
+    Some(
+        hours.unwrap_or(Some(0.0))? * 3600.0
+            + minutes.unwrap_or(Some(0.0))? * 60.0
+            + seconds.unwrap_or(Some(0.0))?,
+    )
+}
+
+/// Find a calendar date embedded in free text, such as a title or URL:
+/// `2024-01-15`, `2024/01/15`, `2024_01_15` or `20240115`. Returns the start
+/// of that day in `tz`, in milliseconds.
+pub fn find_date(text: &str, tz: Tz) -> Option<i64> {
+    let re = Regex::new(
+        r"(?:^|\D)((?:19|20)\d{2})(?:([-/_.])(\d{1,2})[-/_.](\d{1,2})|(\d{2})(\d{2}))(?:\D|$)",
+    )
+    .ok()?;
+    let found = re.captures_iter(text).find_map(|caps| {
+        let number = |i: usize| caps.get(i)?.as_str().parse::<u32>().ok();
+        let year = number(1)? as i32;
+        let (month, day) = if caps.get(2).is_some() {
+            (number(3)?, number(4)?)
+        } else {
+            (number(5)?, number(6)?)
+        };
+        let date = NaiveDate::from_ymd_opt(year, month, day)?;
+        tz.with_ymd_and_hms(date.year(), date.month(), date.day(), 0, 0, 0)
+            .single()
+            .map(|start| start.timestamp_millis())
+    });
+    found
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -810,4 +875,36 @@ mod tests {
         assert!(parse("", now, TEST_TZ).is_err());
         assert!(parse("13/45/2025", now, TEST_TZ).is_err()); // invalid month/day
     }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90"), Some(90.0));
+        assert_eq!(parse_duration("90s"), Some(90.0));
+        assert_eq!(parse_duration("5m"), Some(300.0));
+        assert_eq!(parse_duration("1h30m"), Some(5400.0));
+        assert_eq!(parse_duration("1h 30m 15s"), Some(5415.0));
+        assert_eq!(parse_duration("1.5m"), Some(90.0));
+        assert_eq!(parse_duration("1:30"), Some(90.0));
+        assert_eq!(parse_duration("1:02:03"), Some(3723.0));
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("five minutes"), None);
+        assert_eq!(parse_duration("-5"), None);
+        assert_eq!(parse_duration("1:2:3:4"), None);
+    }
+
+    #[test]
+    fn test_find_date() {
+        let utc = chrono_tz::UTC;
+        let jan_15 = Some(1705276800000);
+        assert_eq!(find_date("Photos from 2024-01-15 meetup", utc), jan_15);
+        assert_eq!(
+            find_date("https://example.com/2024/01/15/post", utc),
+            jan_15
+        );
+        assert_eq!(find_date("IMG_20240115_120000.jpg", utc), jan_15);
+        // Not a real date, so the next candidate is used
+        assert_eq!(find_date("v2024-13-40 then 2024.1.15", utc), jan_15);
+        assert_eq!(find_date("Part 12345678 of the series", utc), None);
+        assert_eq!(find_date("no date here", utc), None);
+    }
 }
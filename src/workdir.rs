@@ -2,6 +2,7 @@ use std::{
     fs::File,
     ops::{Deref, DerefMut},
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use crate::{errors::*, site::FileCrawlType};
@@ -9,8 +10,12 @@ use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    bake::{
+        manifest::FINISHED_FILENAME, metadata::METADATA_FILENAME, BakeConfig, MediaMetadataStore,
+    },
     reprocessors::Reprocessor,
     serde::{deserialize_map_values, serialize_map_values},
+    server_config::ServerConfig,
     site::{CrawlItem, SiteSettings},
 };
 
@@ -24,6 +29,12 @@ pub struct Config {
     pub hide_titles: bool,
     #[serde(default)]
     pub reprocessors: Vec<Reprocessor>,
+    /// Names of server-wide reprocessor sets this site doesn't run. The
+    /// `all` view still runs them on the site's items.
+    #[serde(default)]
+    pub skip_server_reprocessors: Vec<String>,
+    #[serde(default)]
+    pub bake: BakeConfig,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -86,19 +97,97 @@ pub struct WorkDir {
     pub path: Box<Path>,
     pub config: Config,
     pub crawled: SiteItems,
+    /// The items as the `all` view shows them, when the site skips some of
+    /// the server-wide reprocessors and so they differ from `crawled`
+    pub crawled_for_all: Option<SiteItems>,
+    pub server_config: Arc<ServerConfig>,
     pub last_seen_modified: u64,
     pub loaded_at: u128,
 }
 
 #[allow(dead_code)]
 impl WorkDir {
-    pub fn new<P: Into<PathBuf>>(p: P) -> Result<Self> {
+    pub fn new<P: Into<PathBuf>>(p: P, server_config: Arc<ServerConfig>) -> Result<Self> {
         let path = p.into();
-        let config_path = path.join("config.json");
-        let config_file = File::open(config_path).context("Unable to open config.json")?;
-        let config: Config =
-            serde_json::from_reader(config_file).context("config.json was not well-formatted")?;
+        let config = Self::load_config(&path)?;
+
+        // Check reprocessor patterns and queries before loading anything else,
+        // so a bad config is reported as such
+        for reprocessor in &config.reprocessors {
+            reprocessor.compile(&path, &server_config.tags)?;
+        }
+        for name in &config.skip_server_reprocessors {
+            if !server_config.has_set(name) {
+                log::warn!(
+                    "{} skips server reprocessors {:?}, which the server config doesn't define",
+                    config.slug,
+                    name
+                );
+            }
+        }
+
+        let last_seen_modified = Self::sources_modified(&path);
+        let unprocessed = Self::load_unprocessed(&path, &config)?;
+
+        // Apply reprocessors, then the server's tag rules. Runs last so that
+        // queries can use the site and media metadata attached by
+        // `load_unprocessed`.
+        let apply = |mut items: SiteItems, skip: &[String]| {
+            for step in server_config.pipeline(&config.reprocessors, skip) {
+                step.reprocessor.apply(&mut items.items);
+            }
+            server_config.tags.apply(&mut items.items);
+            for item in items.items.values_mut() {
+                item.load_placeholder();
+            }
+            items
+        };
+        let skips_any = config
+            .skip_server_reprocessors
+            .iter()
+            .any(|name| server_config.has_set(name));
+        // Only the first pass needs a copy of the unprocessed items
+        let (crawled, crawled_for_all) = if skips_any {
+            (
+                apply(unprocessed.clone(), &config.skip_server_reprocessors),
+                Some(apply(unprocessed, &[])),
+            )
+        } else {
+            (apply(unprocessed, &config.skip_server_reprocessors), None)
+        };
+
+        let loaded_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+
+        Ok(WorkDir {
+            path: path.into(),
+            crawled,
+            crawled_for_all,
+            server_config,
+            config,
+            last_seen_modified,
+            loaded_at,
+        })
+    }
 
+    /// The items the `all` view shows for this site.
+    pub fn items_for_all_view(&self) -> &SiteItems {
+        self.crawled_for_all.as_ref().unwrap_or(&self.crawled)
+    }
+
+    pub fn load_config(path: &Path) -> Result<Config> {
+        let config_file =
+            File::open(path.join("config.json")).context("Unable to open config.json")?;
+        serde_json::from_reader(config_file).context("config.json was not well-formatted")
+    }
+
+    /// The site's items as they are before any reprocessors run: read from
+    /// crawled.json and cleaned up, with the site settings and extracted
+    /// media metadata attached.
+    pub fn load_unprocessed(path: &Path, config: &Config) -> Result<SiteItems> {
+        let path = path.to_path_buf();
         let crawled_path = path.join("crawled.json");
         let mut crawled: SiteItems = {
             if crawled_path.exists() {
@@ -111,33 +200,12 @@ impl WorkDir {
             }
         };
 
-        let crawled_path = path.join("crawled.json");
-        let last_seen_modified = if crawled_path.exists() {
-            let metadata = std::fs::metadata(crawled_path)
-                .context("Unable to get metadata for crawled.json")
-                .unwrap();
-            metadata
-                .modified()
-                .context("Unable to get modified time for crawled.json")
-                .unwrap()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs()
-        } else {
-            0
-        };
-
         crawled.sort();
         crawled.remove_duplicate_tags();
         if std::env::var("ALLOW_NO_FILES").is_err() {
             crawled.remove_items_without_files();
         }
 
-        // Apply reprocessors
-        for reprocessor in &config.reprocessors {
-            reprocessor.apply(&mut crawled.items);
-        }
-
         // Attach site settings to each item
         for item in crawled.items.values_mut() {
             item.site_settings = SiteSettings {
@@ -145,20 +213,45 @@ impl WorkDir {
                 forced_author: config.forced_author.clone(),
                 hide_titles: config.hide_titles,
                 work_dir_path: Some(path.clone()),
+                bake: config.bake.clone(),
             };
         }
 
-        let loaded_at = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis();
+        // Attach extracted media metadata to each item
+        let media_metadata = MediaMetadataStore::load(&path).unwrap_or_else(|e| {
+            println!("Ignoring unreadable media metadata: {}", e);
+            MediaMetadataStore::default()
+        });
+        for item in crawled.items.values_mut() {
+            let files = item
+                .flat_files()
+                .into_iter()
+                .chain(item.flat_previews())
+                .collect::<Vec<_>>();
+            for (key, file) in files {
+                let metadata = file
+                    .media_filename()
+                    .and_then(|filename| media_metadata.files.get(filename));
+                if let Some(metadata) = metadata {
+                    item.media.insert(key, metadata.clone());
+                }
+            }
+        }
 
-        Ok(WorkDir {
-            path: path.into(),
-            crawled,
-            config,
-            last_seen_modified,
-            loaded_at,
-        })
+        Ok(crawled)
+    }
+
+    /// The latest modification time, in seconds, of the files a WorkDir is
+    /// loaded from, and of the stamp a finished bake leaves for its baked
+    /// placeholders. Missing files count as 0.
+    pub fn sources_modified(path: &Path) -> u64 {
+        ["crawled.json", METADATA_FILENAME, FINISHED_FILENAME]
+            .iter()
+            .filter_map(|filename| std::fs::metadata(path.join(filename)).ok())
+            .filter_map(|metadata| metadata.modified().ok())
+            .filter_map(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs())
+            .max()
+            .unwrap_or(0)
     }
 }